@@ -0,0 +1,146 @@
+//! Times each step of a GPU multiplication on its own, and how long creating each
+//! variant's pipeline takes.
+//!
+//! The whole-`multiply` benchmarks in `gpu_bench.rs` include allocating buffers and
+//! copying the matrices, which swamp the kernel for small shapes.
+
+use benches::{eligible, random_matrix, Family, Flops, Shape, SHAPES};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::executor::block_on;
+use matmul::device::{DeviceOptions, GpuContext};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A step of [`matmul::PhasedMultiply`].
+#[derive(Copy, Clone, Debug)]
+enum Phase {
+    Allocate,
+    Upload,
+    Dispatch,
+    ReadBack,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [
+        Phase::Allocate,
+        Phase::Upload,
+        Phase::Dispatch,
+        Phase::ReadBack,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Allocate => "allocate",
+            Phase::Upload => "upload",
+            Phase::Dispatch => "dispatch",
+            Phase::ReadBack => "read_back",
+        }
+    }
+
+    /// What the phase moves or computes, if anything.
+    fn throughput(&self, shape: &Shape) -> Option<Throughput> {
+        let bytes = |elements: u64| elements * std::mem::size_of::<f32>() as u64;
+        let Shape { m, k, n, .. } = *shape;
+        let (m, k, n) = (m as u64, k as u64, n as u64);
+        match self {
+            Phase::Allocate => None,
+            Phase::Upload => Some(Throughput::Bytes(bytes(m * k + k * n))),
+            Phase::Dispatch => Some(benches::throughput(shape)),
+            Phase::ReadBack => Some(Throughput::Bytes(bytes(m * n))),
+        }
+    }
+}
+
+fn bench_phases(c: &mut Criterion<Flops>) {
+    let context = Arc::new(block_on(GpuContext::new(&DeviceOptions::default())).unwrap());
+    let variants = benches::variants(Some(&context), |info| info.backend.is_gpu());
+    let shapes = SHAPES
+        .iter()
+        .filter(|shape| shape.family == Family::Square)
+        .collect::<Vec<_>>();
+
+    for phase in Phase::ALL {
+        let mut group = c.benchmark_group(format!("phases/{}", phase.name()));
+        group.sample_size(10);
+        for shape in &shapes {
+            let Shape { m, k, n, .. } = **shape;
+            let (a, b) = (random_matrix(m, k), random_matrix(k, n));
+            if let Some(throughput) = phase.throughput(shape) {
+                group.throughput(throughput);
+            }
+
+            for (info, multiplier) in variants.iter().filter(|(info, _)| eligible(info, shape)) {
+                let phased = multiplier
+                    .phased()
+                    .expect("wgpu variants can be split into phases");
+                // Everything before the timed phase runs once.
+                let buffers = phased.allocate(m, k, n);
+                phased.upload(&buffers, &a, &b).unwrap();
+                phased.dispatch(&buffers).unwrap();
+
+                let id = BenchmarkId::new(info.id(), shape);
+                group.bench_function(id, |bench| {
+                    bench.iter_custom(|iterations| {
+                        let mut total = Duration::ZERO;
+                        for _ in 0..iterations {
+                            let start = Instant::now();
+                            match phase {
+                                Phase::Allocate => drop(black_box(phased.allocate(m, k, n))),
+                                Phase::Upload => phased.upload(&buffers, &a, &b).unwrap(),
+                                Phase::Dispatch => {
+                                    // The GPU's own clock leaves out submission and
+                                    // waiting, when the adapter has one.
+                                    let gpu_time = phased.dispatch(&buffers).unwrap();
+                                    total += gpu_time.unwrap_or_else(|| start.elapsed());
+                                    continue;
+                                }
+                                Phase::ReadBack => {
+                                    black_box(phased.read_back(&buffers).unwrap());
+                                }
+                            }
+                            total += start.elapsed();
+                        }
+                        total
+                    });
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+/// How long creating each variant's multiplier takes on an existing device, including
+/// its shader module, as the first multiplier on a new context pays for it. Drivers
+/// may still cache compiled pipelines on disk.
+fn bench_pipeline_creation(c: &mut Criterion<Flops>) {
+    let context = Arc::new(block_on(GpuContext::new(&DeviceOptions::default())).unwrap());
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    for info in matmul::registry::variants()
+        .into_iter()
+        .filter(|info| info.backend.is_gpu())
+    {
+        group.bench_function(BenchmarkId::from_parameter(info.id()), |bench| {
+            bench.iter_custom(|iterations| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iterations {
+                    context.clear_shader_modules();
+                    let start = Instant::now();
+                    let multiplier = black_box(info.create(Some(&context)).unwrap());
+                    total += start.elapsed();
+                    drop(multiplier);
+                }
+                total
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = phases;
+    config = benches::criterion();
+    targets = bench_phases, bench_pipeline_creation
+}
+
+criterion_main!(phases);
@@ -0,0 +1,102 @@
+//! A criterion measurement that reports throughput in FLOP/s.
+
+use criterion::measurement::{Measurement, ValueFormatter, WallTime};
+use criterion::Throughput;
+use std::time::{Duration, Instant};
+
+/// Wall-clock time, like criterion's default [`WallTime`], but with element throughput
+/// shown as FLOP/s. Set the throughput with [`crate::throughput`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Flops;
+
+impl Measurement for Flops {
+    type Intermediate = Instant;
+    type Value = Duration;
+
+    fn start(&self) -> Instant {
+        WallTime.start()
+    }
+
+    fn end(&self, start: Instant) -> Duration {
+        WallTime.end(start)
+    }
+
+    fn add(&self, v1: &Duration, v2: &Duration) -> Duration {
+        WallTime.add(v1, v2)
+    }
+
+    fn zero(&self) -> Duration {
+        WallTime.zero()
+    }
+
+    fn to_f64(&self, value: &Duration) -> f64 {
+        WallTime.to_f64(value)
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for Flops {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        WallTime.formatter().scale_values(typical_value, values)
+    }
+
+    fn scale_throughputs(
+        &self,
+        typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let Throughput::Elements(flops) = *throughput else {
+            return WallTime
+                .formatter()
+                .scale_throughputs(typical_value, throughput, values);
+        };
+
+        // Values are nanoseconds per iteration.
+        let per_second = flops as f64 * 1e9 / typical_value;
+        let (factor, unit) = if per_second < 1e3 {
+            (1.0, "FLOP/s")
+        } else if per_second < 1e6 {
+            (1e-3, "KFLOP/s")
+        } else if per_second < 1e9 {
+            (1e-6, "MFLOP/s")
+        } else if per_second < 1e12 {
+            (1e-9, "GFLOP/s")
+        } else {
+            (1e-12, "TFLOP/s")
+        };
+        for value in values {
+            *value = flops as f64 * 1e9 / *value * factor;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, values: &mut [f64]) -> &'static str {
+        WallTime.formatter().scale_for_machines(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_throughput() {
+        // 2 GFLOPs in half a second.
+        let throughput = Throughput::Elements(2_000_000_000);
+        assert_eq!(
+            Flops.format_throughput(&throughput, 0.5e9).trim(),
+            "4.0000 GFLOP/s"
+        );
+        assert_eq!(
+            Flops.format_throughput(&throughput, 1e6).trim(),
+            "2.0000 TFLOP/s"
+        );
+        assert!(Flops
+            .format_throughput(&Throughput::Bytes(1024), 1e9)
+            .ends_with("KiB/s"));
+    }
+}
@@ -0,0 +1,133 @@
+//! What the criterion benchmarks share: the shapes they multiply, which variants run on
+//! which shapes, and a measurement that reports FLOP/s.
+//!
+//! Each benchmark picks its variants from the registry and hands them to
+//! [`bench_variants`], so a new variant is benchmarked as soon as it is registered.
+
+mod flops;
+mod shapes;
+
+pub use flops::Flops;
+pub use shapes::{Family, Shape, SHAPES};
+
+use criterion::{black_box, BenchmarkId, Criterion, SamplingMode, Throughput};
+use matmul::device::GpuContext;
+use matmul::registry::{self, VariantInfo};
+use matmul::DynMatrixMultiply;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::Duration;
+
+const WARMUP_TIME: Duration = Duration::from_secs(2);
+const SAMPLE_SIZE: usize = 10;
+
+/// Whether `info` is benchmarked on `shape`, see [`registry::RULES`].
+pub fn eligible(info: &VariantInfo, shape: &Shape) -> bool {
+    info.practical(shape.m, shape.k, shape.n)
+}
+
+/// The throughput of one multiplication of `shape`. It is counted in elements, which
+/// [`Flops`] shows as FLOP/s.
+pub fn throughput(shape: &Shape) -> Throughput {
+    Throughput::Elements(shape.flops())
+}
+
+/// The configuration every benchmark uses.
+pub fn criterion() -> Criterion<Flops> {
+    Criterion::default()
+        .with_measurement(Flops)
+        .with_plots()
+        .significance_level(0.01)
+        .noise_threshold(0.02)
+}
+
+pub type Variant = (VariantInfo, Box<dyn DynMatrixMultiply>);
+
+/// Creates every registered variant `filter` accepts, running GPU variants on `context`
+/// or on a device of their own if there is none.
+pub fn variants(
+    context: Option<&Arc<GpuContext>>,
+    filter: impl Fn(&VariantInfo) -> bool,
+) -> Vec<Variant> {
+    registry::variants()
+        .into_iter()
+        .filter(filter)
+        .map(|info| {
+            let multiplier = info.create(context).unwrap();
+            (info, multiplier)
+        })
+        .collect()
+}
+
+/// Benchmarks every variant on every shape it is [`eligible`] for, in a group named
+/// `<name>/<family>` for each family of shapes.
+pub fn bench_variants(
+    c: &mut Criterion<Flops>,
+    name: &str,
+    variants: &[Variant],
+    sampling_mode: SamplingMode,
+) {
+    for family in Family::ALL {
+        let mut group = c.benchmark_group(format!("{name}/{family}"));
+        group.sampling_mode(sampling_mode);
+        group.warm_up_time(WARMUP_TIME);
+        group.sample_size(SAMPLE_SIZE);
+
+        for shape in SHAPES.iter().filter(|shape| shape.family == family) {
+            let selected = variants
+                .iter()
+                .filter(|(info, _)| eligible(info, shape))
+                .collect::<Vec<_>>();
+            if selected.is_empty() {
+                continue;
+            }
+
+            group.throughput(throughput(shape));
+            let (a, b) = (
+                random_matrix(shape.m, shape.k),
+                random_matrix(shape.k, shape.n),
+            );
+            let Shape { m, k, n, .. } = *shape;
+            for (info, multiplier) in selected {
+                group.bench_with_input(BenchmarkId::new(info.id(), shape), shape, |bench, _| {
+                    bench.iter(|| {
+                        black_box(multiplier.multiply(black_box(&a), black_box(&b), m, k, n))
+                    });
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+/// A `rows x cols` matrix of values in `[0, 1)`.
+pub fn random_matrix(rows: u32, cols: u32) -> Vec<f32> {
+    let mut rng = rand::thread_rng();
+    (0..rows as u64 * cols as u64)
+        .map(|_| rng.gen::<f32>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eligible() {
+        let naive = registry::find("naive").unwrap();
+        let tiling_2d = registry::find("tiling_2d").unwrap();
+        let isomorphic = registry::find("isomorphic:cpu:single").unwrap();
+        let find = |m, k, n| {
+            *SHAPES
+                .iter()
+                .find(|s| (s.m, s.k, s.n) == (m, k, n))
+                .unwrap()
+        };
+
+        assert!(eligible(&naive, &find(128, 128, 128)));
+        assert!(!eligible(&naive, &find(256, 256, 256)));
+        assert!(eligible(&tiling_2d, &find(4096, 4096, 4096)));
+        assert!(eligible(&isomorphic, &find(1021, 1021, 1021)));
+        assert!(!eligible(&isomorphic, &find(2048, 2048, 2048)));
+    }
+}
@@ -0,0 +1,144 @@
+//! The shapes every benchmark multiplies.
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// What a group of shapes exercises.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Family {
+    /// Powers of two, the sizes the blog post measures.
+    Square,
+    /// Every dimension different, so a kernel that mixes them up is caught.
+    NonSquare,
+    /// Many rows and few columns, which leave most of a square tile empty.
+    TallSkinny,
+    /// Sizes that are not a multiple of any workgroup or tile.
+    Prime,
+}
+
+impl Family {
+    pub const ALL: [Family; 4] = [
+        Family::Square,
+        Family::NonSquare,
+        Family::TallSkinny,
+        Family::Prime,
+    ];
+}
+
+impl Display for Family {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Family::Square => write!(f, "square"),
+            Family::NonSquare => write!(f, "non_square"),
+            Family::TallSkinny => write!(f, "tall_skinny"),
+            Family::Prime => write!(f, "prime"),
+        }
+    }
+}
+
+/// An `m x k` matrix multiplied by a `k x n` matrix.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub family: Family,
+    pub m: u32,
+    pub k: u32,
+    pub n: u32,
+}
+
+impl Shape {
+    const fn new(family: Family, m: u32, k: u32, n: u32) -> Self {
+        Self { family, m, k, n }
+    }
+
+    /// A multiply and an add for every term of every element of the result.
+    pub fn flops(&self) -> u64 {
+        2 * self.m as u64 * self.k as u64 * self.n as u64
+    }
+
+    /// The largest of the three dimensions.
+    pub fn max_dimension(&self) -> u32 {
+        self.m.max(self.k).max(self.n)
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.m, self.k, self.n)
+    }
+}
+
+const fn square(size: u32) -> Shape {
+    Shape::new(Family::Square, size, size, size)
+}
+
+const fn non_square(m: u32, k: u32, n: u32) -> Shape {
+    Shape::new(Family::NonSquare, m, k, n)
+}
+
+const fn tall_skinny(m: u32, k: u32, n: u32) -> Shape {
+    Shape::new(Family::TallSkinny, m, k, n)
+}
+
+const fn prime(m: u32, k: u32, n: u32) -> Shape {
+    Shape::new(Family::Prime, m, k, n)
+}
+
+/// Every shape, grouped by family. Which variants run on each is decided by
+/// [`crate::eligible`].
+pub const SHAPES: &[Shape] = &[
+    square(2),
+    square(4),
+    square(8),
+    square(16),
+    square(32),
+    square(64),
+    square(128),
+    square(256),
+    square(512),
+    square(1024),
+    square(2048),
+    square(4096),
+    non_square(4, 2, 8),
+    non_square(8, 4, 2),
+    non_square(16, 8, 32),
+    non_square(32, 16, 8),
+    non_square(64, 32, 128),
+    non_square(1024, 512, 2048),
+    non_square(2048, 1024, 4096),
+    tall_skinny(1024, 16, 16),
+    tall_skinny(4096, 64, 64),
+    tall_skinny(16384, 128, 8),
+    tall_skinny(65536, 16, 1),
+    prime(3, 3, 3),
+    prime(7, 7, 7),
+    prime(31, 31, 31),
+    prime(127, 127, 127),
+    prime(257, 509, 131),
+    prime(1021, 1021, 1021),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_families() {
+        let is_prime = |n: u32| {
+            let mut divisors = (2..n).take_while(|d| d * d <= n);
+            n > 1 && divisors.all(|d| n.rem_euclid(d) != 0)
+        };
+        for shape in SHAPES {
+            let Shape { m, k, n, .. } = *shape;
+            match shape.family {
+                Family::Square => assert!(m == k && k == n && m.is_power_of_two(), "{shape}"),
+                Family::NonSquare => assert!(m != k && k != n && m != n, "{shape}"),
+                Family::TallSkinny => assert!(m >= 16 * k.max(n), "{shape}"),
+                Family::Prime => assert!([m, k, n].into_iter().all(is_prime), "{shape}"),
+            }
+        }
+        for family in Family::ALL {
+            assert!(SHAPES.iter().any(|shape| shape.family == family));
+        }
+    }
+}
@@ -1,7 +1,23 @@
-use matmul::MatrixMultiply;
-use std::fmt::Display;
-use std::sync::{Mutex, OnceLock};
-use std::time::Instant;
+mod cli;
+mod compare;
+mod report;
+mod roofline;
+
+use clap::Parser;
+use cli::{Cli, Command, CompareArgs, MultiplyArgs, OutputArgs, RooflineArgs, Selection};
+use futures::executor::block_on;
+use matmul::device::{self, DeviceOptions, GpuContext};
+use matmul::io::{self, Matrix};
+use matmul::registry::{self, Backend, VariantInfo};
+use matmul::roofline::Microbenchmark;
+use matmul::verify::{self, Verifier};
+use matmul::{DynMatrixMultiply, MatrixMultiplyError};
+use report::{Record, Report, SCHEMA_VERSION};
+use roofline::Roofline;
+use std::error::Error;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, instrument, span, trace, warn, Level};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use wgpu::Device;
@@ -52,74 +68,502 @@ fn install_error_handler(device: &Device) {
     }));
 }
 
-fn main() {
+fn main() -> ExitCode {
     // Initialize the error state.
     init_error_state();
 
+    // Logs go to stderr so records written to stdout with `--output -` stay parseable.
     tracing_subscriber::registry()
-        .with(fmt::Layer::default())
+        .with(fmt::Layer::default().with_writer(std::io::stderr))
         .with(EnvFilter::from_default_env())
         .init();
 
-    let sizes = [
-        (2, 2, 2),
-        (4, 4, 4),
-        (8, 8, 8),
-        (16, 16, 16),
-        (32, 32, 32),
-        (64, 64, 64),
-        (128, 128, 128),
-        (256, 256, 256),
-        (512, 512, 512),
-        (1024, 1024, 1024),
-        (2048, 2048, 2048),
-    ];
+    let cli = Cli::parse();
+    let options = cli.device_options();
+    let result = match &cli.command {
+        Command::Run(args) => run(
+            &args.selection,
+            &args.tolerance.verifier(),
+            &args.output,
+            &options,
+            args.warmup,
+            args.repetitions,
+        ),
+        Command::Bench(args) => bench(
+            &args.selection,
+            &args.tolerance.verifier(),
+            &args.output,
+            &options,
+            args.warmup,
+            args.repetitions,
+        ),
+        Command::Verify(args) => verify(
+            &args.selection,
+            &args.tolerance.verifier(),
+            &args.output,
+            &options,
+        ),
+        Command::Multiply(args) => multiply(args, &options),
+        Command::Roofline(args) => roofline(args, &options),
+        Command::Compare(args) => compare(args),
+        Command::ListDevices => {
+            list_devices(&options);
+            Ok(())
+        }
+    };
 
-    for size in sizes {
-        let matmul = matmul::naive::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            error!("{}", error);
+            ExitCode::FAILURE
+        }
     }
+}
+
+/// Multiplies every shape with every selected variant, reporting GFLOPS for each
+/// repetition.
+fn run(
+    selection: &Selection,
+    verifier: &Verifier,
+    output: &OutputArgs,
+    options: &DeviceOptions,
+    warmup: u32,
+    repetitions: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut records = Records::new("run", output)?;
+    for_each_test(selection, options, |target, size| {
+        warm_up(target.multiplier, size, warmup);
+        for repetition in 0..repetitions {
+            let measurement = run_test(target.multiplier, size, verifier);
+            records.write(&target, size, repetition, &measurement)?;
+        }
+        Ok(())
+    })?;
+    records.finish()
+}
+
+/// Times every shape with every selected variant and summarizes the repetitions.
+fn bench(
+    selection: &Selection,
+    verifier: &Verifier,
+    output: &OutputArgs,
+    options: &DeviceOptions,
+    warmup: u32,
+    repetitions: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut records = Records::new("bench", output)?;
+    for_each_test(selection, options, |target, size| {
+        warm_up(target.multiplier, size, warmup);
+        let mut gflops = Vec::new();
+        for repetition in 0..repetitions {
+            let measurement = run_test(target.multiplier, size, verifier);
+            records.write(&target, size, repetition, &measurement)?;
+            if let Ok(result) = measurement.result {
+                gflops.push(result.gflops);
+            }
+        }
+        if gflops.is_empty() {
+            return Ok(());
+        }
+
+        gflops.sort_by(f64::total_cmp);
+        let mean = gflops.iter().sum::<f64>() / gflops.len() as f64;
+        info!(
+            algorithm = %target.multiplier,
+            size = ?size,
+            repetitions = gflops.len(),
+            min = gflops[0],
+            median = gflops[gflops.len() / 2],
+            mean,
+            max = gflops[gflops.len() - 1],
+            "GFlops"
+        );
+        Ok(())
+    })?;
+    records.finish()
+}
 
-    for size in sizes {
-        let matmul = matmul::workgroup_256::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+/// Multiplies every shape once with every selected variant and fails if any result is
+/// wrong.
+fn verify(
+    selection: &Selection,
+    verifier: &Verifier,
+    output: &OutputArgs,
+    options: &DeviceOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut records = Records::new("verify", output)?;
+    let mut failures = 0;
+    let mut tests = 0;
+    for_each_test(selection, options, |target, size| {
+        let measurement = run_test(target.multiplier, size, verifier);
+        records.write(&target, size, 0, &measurement)?;
+        tests += 1;
+        if !measurement.passed() {
+            failures += 1;
+        }
+        Ok(())
+    })?;
+    records.finish()?;
+
+    if failures > 0 {
+        return Err(format!("{failures} of {tests} tests failed").into());
     }
+    info!("All {} tests passed", tests);
+    Ok(())
+}
 
-    for size in sizes {
-        let matmul = matmul::workgroup_2d::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+/// Multiplies the matrices in two files with one variant and writes the result.
+fn multiply(args: &MultiplyArgs, options: &DeviceOptions) -> Result<(), Box<dyn Error>> {
+    // Fail before the multiplication if the result can't be written.
+    io::Format::from_path(&args.out)?;
+    let a = io::read(&args.a)?;
+    let b = io::read(&args.b)?;
+    if a.cols != b.rows {
+        return Err(MatrixMultiplyError::MatrixSize(format!(
+            "can't multiply a {}x{} matrix by a {}x{} matrix",
+            a.rows, a.cols, b.rows, b.cols
+        ))
+        .into());
     }
+    let (m, k, n) = (a.rows, a.cols, b.cols);
 
-    for size in sizes {
-        let matmul = matmul::tiling_1d::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+    let backend = Backend::from(args.backend);
+    let info = registry::find(&format!("{}:{backend}", args.variant))?;
+    if !info.supports(m, k, n) {
+        return Err(MatrixMultiplyError::NoSupportedVariant(m, k, n).into());
     }
+    let multiplier = if backend.is_gpu() {
+        let context = Arc::new(block_on(GpuContext::new(options))?);
+        install_error_handler(context.device());
+        info!("Running on {}", context.adapter_info());
+        info.create(Some(&context))?
+    } else {
+        info.create(None)?
+    };
 
-    for size in sizes {
-        let matmul = matmul::tiling_1d_loop::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+    let start = Instant::now();
+    let result = multiplier.multiply(&a.data, &b.data, m, k, n);
+    let compute_time = start.elapsed();
+    if let Some(error) = take_error() {
+        return Err(error.into());
     }
+    let result = result?;
+    let gflops = 2.0 * (m as u64 * n as u64 * k as u64) as f64 / compute_time.as_secs_f64() / 1e9;
+    info!(algorithm = %multiplier, m, k, n, gflops, "Multiplied");
 
-    for size in sizes {
-        let matmul = matmul::tiling_2d::wgpu().unwrap();
-        install_error_handler(&matmul.device);
-        run_test(matmul, size);
-        clear_error();
+    if args.verify {
+        let verification = args
+            .tolerance
+            .verifier()
+            .verify(&a.data, &b.data, &result, m, k, n)?;
+        if !verification.passed() {
+            return Err(format!("Verification failed: {verification}").into());
+        }
+        info!("Verification passed");
     }
+
+    io::write(&args.out, &Matrix::new(m, n, result)?)?;
+    Ok(())
 }
 
-#[instrument(skip(multiplier, size), fields(algorithm = %multiplier, size=?size))]
-fn run_test<T: Display, U: MatrixMultiply<T>>(multiplier: U, size: (u32, u32, u32)) {
+/// Places every shape multiplied by every selected GPU variant under the roofline of
+/// the adapter.
+fn roofline(args: &RooflineArgs, options: &DeviceOptions) -> Result<(), Box<dyn Error>> {
+    let selection = args.selection();
+    let verifier = args.tolerance.verifier();
+    let (context, variants) = create_variants(&selection, options)?;
+    let context = context.ok_or("no GPU variant is selected")?;
+
+    info!("Measuring bandwidth and peak FLOPS");
+    let ceilings = Microbenchmark::new().measure(&context)?;
+    if let Some(error) = take_error() {
+        return Err(error.into());
+    }
+    let mut roofline = Roofline::new(context.adapter_info().name.clone(), ceilings);
+
+    for (info, multiplier) in &variants {
+        for size @ (m, k, n) in selection.shapes() {
+            if !info.supports(m, k, n) {
+                debug!(algorithm = %info, "Skipping unsupported size {}x{}x{}", m, k, n);
+                continue;
+            }
+            warm_up(multiplier.as_ref(), size, args.warmup);
+            let fastest = (0..args.repetitions)
+                .map(|_| run_test(multiplier.as_ref(), size, &verifier))
+                .filter(Measurement::passed)
+                .filter_map(|measurement| measurement.result.ok())
+                .map(|result| result.gflops)
+                .max_by(f64::total_cmp);
+            clear_error();
+            match fastest {
+                Some(gflops) => roofline.add(info, size, gflops),
+                None => warn!(algorithm = %info, "No passing result for {}x{}x{}", m, k, n),
+            }
+        }
+    }
+
+    print!("{roofline}");
+    if let Some(path) = &args.svg {
+        std::fs::write(path, roofline.to_svg())
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    if let Some(path) = &args.json {
+        std::fs::write(path, serde_json::to_string_pretty(&roofline)?)
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Prints a Markdown table comparing two runs and fails if any variant and shape got
+/// slower by more than the threshold.
+fn compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    if args.threshold.is_nan() || args.threshold < 0.0 {
+        return Err(format!("threshold {} is not a percentage", args.threshold).into());
+    }
+    let baseline = compare::read(&args.baseline, args.ignore_adapter)?;
+    let current = compare::read(&args.current, args.ignore_adapter)?;
+    for key in baseline.keys().filter(|key| !current.contains_key(key)) {
+        warn!(?key, "Only in the baseline");
+    }
+    for key in current.keys().filter(|key| !baseline.contains_key(key)) {
+        warn!(?key, "Only in the current run");
+    }
+
+    let threshold = args.threshold / 100.0;
+    let comparisons = compare::compare(&baseline, &current, threshold);
+    if comparisons.is_empty() {
+        return Err("no variant and shape has passing records in both runs".into());
+    }
+    print!("{}", compare::markdown(&comparisons, threshold));
+
+    let regressions = comparisons
+        .iter()
+        .filter(|comparison| comparison.verdict == compare::Verdict::Regression)
+        .count();
+    if regressions > 0 {
+        return Err(format!(
+            "{regressions} of {} comparisons regressed by more than {}%",
+            comparisons.len(),
+            args.threshold
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn list_devices(options: &DeviceOptions) {
+    let adapters = device::enumerate_adapters(options);
+    if adapters.is_empty() {
+        warn!("No adapters found");
+    }
+    for adapter in adapters {
+        println!("{adapter}");
+    }
+}
+
+/// A variant being tested and where it runs.
+struct Target<'a> {
+    info: &'a VariantInfo,
+    multiplier: &'a dyn DynMatrixMultiply,
+    /// The adapter's name, for GPU variants.
+    adapter: Option<&'a str>,
+}
+
+/// Runs `test` for every selected variant and every shape it supports.
+fn for_each_test(
+    selection: &Selection,
+    options: &DeviceOptions,
+    mut test: impl FnMut(Target, (u32, u32, u32)) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let shapes = selection.shapes();
+    let (context, variants) = create_variants(selection, options)?;
+    let adapter = context.map(|context| context.adapter_info().name.clone());
+    for (info, multiplier) in &variants {
+        for (m, k, n) in shapes.iter().copied() {
+            if !info.supports(m, k, n) {
+                debug!(algorithm = %info, "Skipping unsupported size {}x{}x{}", m, k, n);
+                continue;
+            }
+            let target = Target {
+                info,
+                multiplier: multiplier.as_ref(),
+                adapter: adapter.as_deref().filter(|_| info.backend.is_gpu()),
+            };
+            test(target, (m, k, n))?;
+            clear_error();
+        }
+    }
+    Ok(())
+}
+
+type Variant = (VariantInfo, Box<dyn DynMatrixMultiply>);
+
+/// The device GPU variants run on, if any were selected, and the variants.
+type Variants = (Option<Arc<GpuContext>>, Vec<Variant>);
+
+/// Creates every registered variant matching `selection`.
+fn create_variants(
+    selection: &Selection,
+    options: &DeviceOptions,
+) -> Result<Variants, Box<dyn Error>> {
+    let backends = selection
+        .backends
+        .iter()
+        .map(|&backend| Backend::from(backend))
+        .collect::<Vec<_>>();
+    let registered = registry::variants();
+    if let Some(unknown) = selection
+        .variants
+        .iter()
+        .find(|name| !registered.iter().any(|info| info.name == name.as_str()))
+    {
+        return Err(MatrixMultiplyError::UnknownVariant(unknown.clone()).into());
+    }
+
+    let selected = registered
+        .into_iter()
+        .filter(|info| backends.contains(&info.backend))
+        .filter(|info| {
+            selection.variants.is_empty() || selection.variants.iter().any(|name| name == info.name)
+        })
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        return Err("no variant matches the selected variants and backends".into());
+    }
+
+    // Create the device once and share it between all variants, unless none of them
+    // run on the GPU.
+    let context = if selected.iter().any(|info| info.backend.is_gpu()) {
+        let context = Arc::new(block_on(GpuContext::new(options))?);
+        install_error_handler(context.device());
+        info!("Running on {}", context.adapter_info());
+        Some(context)
+    } else {
+        None
+    };
+
+    let variants = selected
+        .into_iter()
+        .map(|info| {
+            let multiplier = info.create(context.as_ref())?;
+            Ok((info, multiplier))
+        })
+        .collect::<Result<_, MatrixMultiplyError>>()?;
+    Ok((context, variants))
+}
+
+/// Writes a record of each multiplication, if `--output` was given.
+struct Records {
+    command: &'static str,
+    report: Option<Report>,
+}
+
+impl Records {
+    fn new(command: &'static str, output: &OutputArgs) -> Result<Self, Box<dyn Error>> {
+        let report = match &output.output {
+            Some(path) => Some(
+                Report::create(path, output.format)
+                    .map_err(|error| format!("{}: {error}", path.display()))?,
+            ),
+            None => None,
+        };
+        Ok(Self { command, report })
+    }
+
+    fn write(
+        &mut self,
+        target: &Target,
+        size: (u32, u32, u32),
+        repetition: u32,
+        measurement: &Measurement,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(report) = &mut self.report else {
+            return Ok(());
+        };
+
+        let (m, k, n) = size;
+        let result = measurement.result.as_ref().ok();
+        let verification = result.map(|result| &result.verification);
+        report.write(&Record {
+            schema_version: SCHEMA_VERSION,
+            command: self.command,
+            variant: target.info.name.to_string(),
+            backend: target.info.backend.to_string(),
+            adapter: target.adapter.map(str::to_string),
+            m,
+            k,
+            n,
+            repetition,
+            setup_ms: milliseconds(measurement.setup_time),
+            compute_ms: result.map(|result| milliseconds(result.compute_time)),
+            verify_ms: result.map(|result| milliseconds(result.verify_time)),
+            gflops: result.map(|result| result.gflops),
+            bandwidth_gbps: result.map(|result| result.bandwidth_gbps),
+            max_abs_error: verification.map(|verification| verification.max_abs_error),
+            max_rel_error: verification.map(|verification| verification.max_rel_error),
+            max_ulps: verification.map(|verification| verification.max_ulps),
+            mismatches: verification.map(|verification| verification.mismatches),
+            passed: measurement.passed(),
+            error: measurement.result.as_ref().err().cloned(),
+        })
+    }
+
+    fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(report) = &mut self.report {
+            report.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+/// Untimed multiplications, so the timed ones don't pay for first-use costs.
+fn warm_up(multiplier: &dyn DynMatrixMultiply, size: (u32, u32, u32), count: u32) {
+    let (m, k, n) = size;
+    let a = vec![1.0; (m * k) as usize];
+    let b = vec![1.0; (k * n) as usize];
+    for _ in 0..count {
+        if let Err(error) = multiplier.multiply(&a, &b, m, k, n) {
+            warn!("Error during warmup: {:?}", error);
+            return;
+        }
+    }
+    clear_error();
+}
+
+/// The outcome of one multiplication.
+struct Measurement {
+    setup_time: Duration,
+    /// The multiplication's result, or why it failed.
+    result: Result<Computed, String>,
+}
+
+impl Measurement {
+    fn passed(&self) -> bool {
+        self.result
+            .as_ref()
+            .is_ok_and(|result| result.verification.passed())
+    }
+}
+
+struct Computed {
+    compute_time: Duration,
+    verify_time: Duration,
+    gflops: f64,
+    bandwidth_gbps: f64,
+    verification: verify::Report,
+}
+
+#[instrument(skip(multiplier, size, verifier), fields(algorithm = %multiplier, size=?size))]
+fn run_test(
+    multiplier: &dyn DynMatrixMultiply,
+    size: (u32, u32, u32),
+    verifier: &Verifier,
+) -> Measurement {
     debug!(algorithm = %multiplier, "Starting tests");
     let (m, k, n) = size;
 
@@ -130,9 +574,11 @@ fn run_test<T: Display, U: MatrixMultiply<T>>(multiplier: U, size: (u32, u32, u3
 
     // Setup phase
     let setup_span = span!(Level::DEBUG, "setup_phase");
+    let setup_start = Instant::now();
     let _setup_enter = setup_span.enter();
     let a: Vec<f32> = (0..m * k).map(|i| i as f32).collect();
     let b: Vec<f32> = (0..k * n).map(|i| i as f32).collect();
+    let setup_time = setup_start.elapsed();
     drop(_setup_enter);
 
     // Compute phase
@@ -143,17 +589,23 @@ fn run_test<T: Display, U: MatrixMultiply<T>>(multiplier: U, size: (u32, u32, u3
     let compute_time = compute_start.elapsed();
     drop(_compute_enter);
 
+    let failed = |error: String| Measurement {
+        setup_time,
+        result: Err(error),
+    };
+
     if let Some(error) = take_error() {
         warn!("wgpu error occurred: {:?}", error);
-        return;
-    }
-
-    if result.is_err() {
-        error!("Error during computation: {:?}", result);
-        return;
+        return failed(error.to_string());
     }
 
-    let result = result.unwrap();
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => {
+            error!("Error during computation: {:?}", error);
+            return failed(error.to_string());
+        }
+    };
 
     // Calculate FLOPS
     let flop_span = span!(Level::DEBUG, "calculate_flops");
@@ -162,39 +614,40 @@ fn run_test<T: Display, U: MatrixMultiply<T>>(multiplier: U, size: (u32, u32, u3
     let flops = ops / compute_time.as_secs_f64();
     let gflops = flops / 1e9;
     info!("GFlops: {}", gflops);
+    // Every element of both inputs is read and every element of the result written.
+    let bytes = (m as u64 * k as u64 + k as u64 * n as u64 + m as u64 * n as u64) * 4;
+    let bandwidth_gbps = bytes as f64 / compute_time.as_secs_f64() / 1e9;
     drop(_flop_enter);
 
     // Verification phase
     let verify_span = span!(Level::DEBUG, "verification_phase");
+    let verify_start = Instant::now();
     let _verify_enter = verify_span.enter();
-    verify_results(&a, &b, &result, m, k, n);
+    let verification = verifier.verify(&a, &b, &result, m, k, n);
+    let verify_time = verify_start.elapsed();
     drop(_verify_enter);
-}
-
-#[instrument(skip(a, b, result), fields(rows = m, cols = n))]
-fn verify_results(a: &[f32], b: &[f32], result: &[f32], m: u32, k: u32, n: u32) {
-    let verify_rows = std::cmp::min(m, 2);
-    let verify_cols = std::cmp::min(n, 2);
 
-    for i in 0..verify_rows {
-        for j in 0..verify_cols {
-            let mut expected = 0.0;
-            for x in 0..k {
-                expected += a[(i * k + x) as usize] * b[(x * n + j) as usize];
-            }
-            let actual = result[(i * n + j) as usize];
-            let diff = (actual - expected).abs();
-            let rel_error = diff / expected.abs();
-            assert!(
-                rel_error < 1e-3,
-                "Mismatch at [{}, {}]: expected {}, got {}",
-                i,
-                j,
-                expected,
-                actual
-            );
+    let verification = match verification {
+        Ok(verification) => verification,
+        Err(error) => {
+            error!("Error during verification: {:?}", error);
+            return failed(error.to_string());
         }
+    };
+    if verification.passed() {
+        trace!("Verification passed");
+    } else {
+        error!("Verification failed: {}", verification);
     }
 
-    trace!("Verification passed");
+    Measurement {
+        setup_time,
+        result: Ok(Computed {
+            compute_time,
+            verify_time,
+            gflops,
+            bandwidth_gbps,
+            verification,
+        }),
+    }
 }
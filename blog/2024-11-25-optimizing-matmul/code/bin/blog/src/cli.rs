@@ -0,0 +1,446 @@
+//! Command-line arguments.
+
+use crate::report::Format;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use matmul::device::{AdapterSelection, DeviceOptions};
+use matmul::registry::Backend;
+use matmul::verify::{Tolerance, Verifier};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Runs, benchmarks and verifies the matrix multiplication variants from the blog post.
+#[derive(Parser, Debug)]
+#[command(name = "blog", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// The adapter to run GPU variants on, by index in `list-devices` or by a
+    /// case-insensitive part of its name.
+    #[arg(long, global = true)]
+    pub adapter: Option<AdapterArg>,
+
+    /// Only use the fallback (software) adapter, such as lavapipe or WARP.
+    #[arg(long, global = true)]
+    pub fallback_adapter: bool,
+}
+
+impl Cli {
+    pub fn device_options(&self) -> DeviceOptions {
+        let options = DeviceOptions::new().force_fallback_adapter(self.fallback_adapter);
+        match &self.adapter {
+            Some(adapter) => options.adapter(adapter.0.clone()),
+            None => options,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Multiply each shape once with every selected variant and report GFLOPS.
+    Run(RunArgs),
+    /// Time each selected variant over many repetitions.
+    Bench(BenchArgs),
+    /// Check that every selected variant computes the right result.
+    Verify(VerifyArgs),
+    /// Multiply two matrices read from files and write the result to a file.
+    Multiply(MultiplyArgs),
+    /// Measure the adapter's bandwidth and peak FLOPS and place each GPU variant and
+    /// shape on a roofline chart.
+    Roofline(RooflineArgs),
+    /// Compare the records of two runs and fail if any variant got slower.
+    Compare(CompareArgs),
+    /// List the adapters `--adapter` can select.
+    ListDevices,
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub selection: Selection,
+
+    #[command(flatten)]
+    pub tolerance: ToleranceArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// How many times to multiply each shape. GFLOPS is reported for each.
+    #[arg(long, default_value_t = 1)]
+    pub repetitions: u32,
+
+    /// Untimed multiplications before the timed ones.
+    #[arg(long, default_value_t = 0)]
+    pub warmup: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub selection: Selection,
+
+    #[command(flatten)]
+    pub tolerance: ToleranceArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// How many timed multiplications to summarize for each shape.
+    #[arg(long, default_value_t = 10)]
+    pub repetitions: u32,
+
+    /// Untimed multiplications before the timed ones.
+    #[arg(long, default_value_t = 3)]
+    pub warmup: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub selection: Selection,
+
+    #[command(flatten)]
+    pub tolerance: ToleranceArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct MultiplyArgs {
+    /// The `M`x`K` left-hand matrix, as `.npy`, MatrixMarket `.mtx` or `.csv`.
+    #[arg(long, value_name = "PATH")]
+    pub a: PathBuf,
+
+    /// The `K`x`N` right-hand matrix, in any of the same formats.
+    #[arg(long, value_name = "PATH")]
+    pub b: PathBuf,
+
+    /// Where to write the `M`x`N` result. The format is chosen by the extension.
+    #[arg(long, value_name = "PATH")]
+    pub out: PathBuf,
+
+    /// The variant to multiply with.
+    #[arg(long, short, value_name = "NAME", default_value = "tiling_2d")]
+    pub variant: String,
+
+    /// The backend to run the variant on.
+    #[arg(long, short, value_name = "BACKEND", default_value_t = BackendArg::Wgpu)]
+    pub backend: BackendArg,
+
+    /// Check the result against a double-precision reference and fail if it is wrong.
+    #[arg(long)]
+    pub verify: bool,
+
+    #[command(flatten)]
+    pub tolerance: ToleranceArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RooflineArgs {
+    /// Variants to place on the chart, by name. Defaults to every GPU variant.
+    #[arg(long = "variant", short, value_name = "NAME")]
+    pub variants: Vec<String>,
+
+    /// Shapes to multiply, as for `run`.
+    #[arg(long = "size", short, value_name = "SHAPES", default_values_t = [Shapes::default()])]
+    pub sizes: Vec<Shapes>,
+
+    #[command(flatten)]
+    pub tolerance: ToleranceArgs,
+
+    /// How many times to multiply each shape. The fastest is placed on the chart.
+    #[arg(long, default_value_t = 3)]
+    pub repetitions: u32,
+
+    /// Untimed multiplications before the timed ones.
+    #[arg(long, default_value_t = 1)]
+    pub warmup: u32,
+
+    /// Write the chart as an SVG image.
+    #[arg(long, value_name = "PATH")]
+    pub svg: Option<PathBuf>,
+
+    /// Write the measured ceilings and every point on the chart as JSON.
+    #[arg(long, value_name = "PATH")]
+    pub json: Option<PathBuf>,
+}
+
+impl RooflineArgs {
+    /// The roofline is measured on the adapter, so only GPU variants are placed on it.
+    pub fn selection(&self) -> Selection {
+        Selection {
+            variants: self.variants.clone(),
+            backends: vec![BackendArg::Wgpu],
+            sizes: self.sizes.clone(),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Records of the run to compare against, as written by `--output`.
+    #[arg(value_name = "BASELINE")]
+    pub baseline: PathBuf,
+
+    /// Records of the run to compare.
+    #[arg(value_name = "CURRENT")]
+    pub current: PathBuf,
+
+    /// How much slower, in percent, a variant must be to count as a regression.
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.0)]
+    pub threshold: f64,
+
+    /// Match records from different adapters, to compare machines.
+    #[arg(long)]
+    pub ignore_adapter: bool,
+}
+
+/// Which variants to run, where and on what shapes.
+#[derive(Args, Debug)]
+pub struct Selection {
+    /// Variants to run, by name. Defaults to every variant on the selected backends.
+    #[arg(long = "variant", short, value_name = "NAME")]
+    pub variants: Vec<String>,
+
+    /// Backends to run on.
+    #[arg(long = "backend", short, value_name = "BACKEND", default_values_t = [BackendArg::Wgpu])]
+    pub backends: Vec<BackendArg>,
+
+    /// Shapes to multiply: `MxKxN`, a square size `S`, or a range of square sizes
+    /// `FROM..TO`, doubling by default or stepped with `FROM..TO:+STEP` or
+    /// `FROM..TO:*FACTOR`.
+    #[arg(long = "size", short, value_name = "SHAPES", default_values_t = [Shapes::default()])]
+    pub sizes: Vec<Shapes>,
+}
+
+impl Selection {
+    /// Every shape, in the order given.
+    pub fn shapes(&self) -> Vec<(u32, u32, u32)> {
+        self.sizes.iter().flat_map(Shapes::shapes).collect()
+    }
+}
+
+/// How close results must be to the reference to pass.
+#[derive(Args, Debug)]
+pub struct ToleranceArgs {
+    /// Absolute error allowed in every element.
+    #[arg(long, value_name = "ERROR", default_value_t = Tolerance::default().absolute)]
+    pub abs_tolerance: f64,
+
+    /// Error allowed per summed term, relative to the size of the terms.
+    #[arg(long, value_name = "ERROR", default_value_t = Tolerance::default().relative)]
+    pub rel_tolerance: f64,
+
+    /// How many mismatching elements to log for each failed multiplication.
+    #[arg(long, value_name = "COUNT", default_value_t = 10)]
+    pub max_mismatches: usize,
+}
+
+impl ToleranceArgs {
+    pub fn verifier(&self) -> Verifier {
+        Verifier::new()
+            .tolerance(
+                Tolerance::default()
+                    .absolute(self.abs_tolerance)
+                    .relative(self.rel_tolerance),
+            )
+            .max_reported(self.max_mismatches)
+    }
+}
+
+/// Where to write a record of each multiplication.
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// Write a record of each multiplication to this file, or `-` for stdout.
+    #[arg(long, short, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// The format of `--output`. Defaults to CSV for `.csv` files and JSON Lines
+    /// otherwise.
+    #[arg(long, requires = "output")]
+    pub format: Option<Format>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BackendArg {
+    Wgpu,
+    CpuSingle,
+    CpuMulti,
+}
+
+impl From<BackendArg> for Backend {
+    fn from(backend: BackendArg) -> Self {
+        match backend {
+            BackendArg::Wgpu => Backend::Wgpu,
+            BackendArg::CpuSingle => Backend::CpuSingle,
+            BackendArg::CpuMulti => Backend::CpuMulti,
+        }
+    }
+}
+
+impl Display for BackendArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped values");
+        write!(f, "{}", value.get_name())
+    }
+}
+
+/// An adapter index or part of an adapter's name.
+#[derive(Clone, Debug)]
+pub struct AdapterArg(AdapterSelection);
+
+impl FromStr for AdapterArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("empty adapter name".to_string());
+        }
+        Ok(AdapterArg(match s.parse() {
+            Ok(index) => AdapterSelection::Index(index),
+            Err(_) => AdapterSelection::Name(s.to_string()),
+        }))
+    }
+}
+
+/// One shape or a range of square shapes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Shapes {
+    Shape(u32, u32, u32),
+    Range { from: u32, to: u32, step: Step },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Add(u32),
+    Multiply(u32),
+}
+
+impl Shapes {
+    pub fn shapes(&self) -> Vec<(u32, u32, u32)> {
+        match *self {
+            Shapes::Shape(m, k, n) => vec![(m, k, n)],
+            Shapes::Range { from, to, step } => {
+                std::iter::successors(Some(from), |&size| match step {
+                    Step::Add(step) => size.checked_add(step),
+                    Step::Multiply(factor) => size.checked_mul(factor),
+                })
+                .take_while(|&size| size <= to)
+                .map(|size| (size, size, size))
+                .collect()
+            }
+        }
+    }
+}
+
+impl Default for Shapes {
+    /// The sizes the blog post measures.
+    fn default() -> Self {
+        Shapes::Range {
+            from: 2,
+            to: 2048,
+            step: Step::Multiply(2),
+        }
+    }
+}
+
+impl Display for Shapes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Shapes::Shape(m, k, n) => write!(f, "{m}x{k}x{n}"),
+            Shapes::Range { from, to, step } => {
+                write!(f, "{from}..{to}")?;
+                match step {
+                    Step::Multiply(2) => Ok(()),
+                    Step::Multiply(factor) => write!(f, ":*{factor}"),
+                    Step::Add(step) => write!(f, ":+{step}"),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Shapes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = |s: &str| match s.parse::<u32>() {
+            Ok(0) | Err(_) => Err(format!("`{s}` is not a positive size")),
+            Ok(size) => Ok(size),
+        };
+
+        if let Some((from, rest)) = s.split_once("..") {
+            let (to, step) = match rest.split_once(':') {
+                None => (rest, Step::Multiply(2)),
+                Some((to, step)) => {
+                    let step = if let Some(step) = step.strip_prefix('+') {
+                        Step::Add(size(step)?)
+                    } else if let Some(factor) = step.strip_prefix('*') {
+                        match size(factor)? {
+                            1 => return Err("a range can't multiply by 1".to_string()),
+                            factor => Step::Multiply(factor),
+                        }
+                    } else {
+                        return Err(format!("step `{step}` must start with `+` or `*`"));
+                    };
+                    (to, step)
+                }
+            };
+            let (from, to) = (size(from)?, size(to)?);
+            if from > to {
+                return Err(format!("range `{s}` is empty"));
+            }
+            return Ok(Shapes::Range { from, to, step });
+        }
+
+        let dimensions = s.split('x').map(size).collect::<Result<Vec<_>, _>>()?;
+        match dimensions[..] {
+            [size] => Ok(Shapes::Shape(size, size, size)),
+            [m, k, n] => Ok(Shapes::Shape(m, k, n)),
+            _ => Err(format!("`{s}` is not `MxKxN`, `S` or `FROM..TO`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shapes() {
+        assert_eq!("64".parse(), Ok(Shapes::Shape(64, 64, 64)));
+        assert_eq!("512x256x1024".parse(), Ok(Shapes::Shape(512, 256, 1024)));
+        assert_eq!(
+            "2..16".parse::<Shapes>().unwrap().shapes(),
+            [(2, 2, 2), (4, 4, 4), (8, 8, 8), (16, 16, 16)]
+        );
+        assert_eq!(
+            "64..200:+64".parse::<Shapes>().unwrap().shapes(),
+            [(64, 64, 64), (128, 128, 128), (192, 192, 192)]
+        );
+        assert_eq!(
+            "1..100:*10".parse::<Shapes>().unwrap().shapes(),
+            [(1, 1, 1), (10, 10, 10), (100, 100, 100)]
+        );
+
+        for invalid in ["", "0", "2x3", "axbxc", "16..2", "2..16:2", "2..16:*1"] {
+            assert!(invalid.parse::<Shapes>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_default_shapes_round_trip() {
+        let shapes = Shapes::default();
+        assert_eq!(shapes.to_string().parse(), Ok(shapes));
+    }
+
+    #[test]
+    fn test_cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
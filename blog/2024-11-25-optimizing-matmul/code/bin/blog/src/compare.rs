@@ -0,0 +1,415 @@
+//! Comparing the records of two runs, such as `bench --output` before and after a
+//! change or on two machines.
+//!
+//! The compute times of the passing repetitions of each variant and shape are compared
+//! on a log scale: the speedup is the ratio of their geometric means, and its confidence
+//! interval comes from Welch's t-test on the logarithms of the times.
+
+use crate::report::{Format, SCHEMA_VERSION};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The fields of a [`crate::report::Record`] a comparison needs.
+#[derive(Clone, Debug, Deserialize)]
+struct Sample {
+    schema_version: u32,
+    variant: String,
+    backend: String,
+    adapter: Option<String>,
+    m: u32,
+    k: u32,
+    n: u32,
+    compute_ms: Option<f64>,
+    passed: bool,
+}
+
+/// What records are matched by.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub variant: String,
+    pub backend: String,
+    /// `None` for CPU variants, and for every variant when adapters are ignored.
+    pub adapter: Option<String>,
+    pub m: u32,
+    pub k: u32,
+    pub n: u32,
+}
+
+/// The compute times of every passing repetition, in milliseconds, by variant and shape.
+pub type Samples = BTreeMap<Key, Vec<f64>>;
+
+/// Reads the records in `path`, as CSV if it ends in `.csv` and JSON Lines otherwise.
+///
+/// With `ignore_adapter`, records from different adapters are matched, to compare
+/// machines.
+pub fn read(path: &Path, ignore_adapter: bool) -> Result<Samples, Box<dyn Error>> {
+    let file = File::open(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let reader = BufReader::new(file);
+    let samples = match Format::from_path(path).unwrap_or(Format::Jsonl) {
+        Format::Jsonl => read_jsonl(reader),
+        Format::Csv => read_csv(reader),
+    }
+    .map_err(|error| format!("{}: {error}", path.display()))?;
+
+    let mut grouped = Samples::new();
+    for sample in samples {
+        if sample.schema_version != SCHEMA_VERSION {
+            return Err(format!(
+                "{}: schema version {} is not {SCHEMA_VERSION}",
+                path.display(),
+                sample.schema_version
+            )
+            .into());
+        }
+        let Some(compute_ms) = sample.compute_ms.filter(|_| sample.passed) else {
+            continue;
+        };
+        let key = Key {
+            variant: sample.variant,
+            backend: sample.backend,
+            adapter: sample.adapter.filter(|_| !ignore_adapter),
+            m: sample.m,
+            k: sample.k,
+            n: sample.n,
+        };
+        grouped.entry(key).or_default().push(compute_ms);
+    }
+    Ok(grouped)
+}
+
+fn read_jsonl(reader: impl BufRead) -> Result<Vec<Sample>, Box<dyn Error>> {
+    let mut samples = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sample =
+            serde_json::from_str(&line).map_err(|error| format!("line {}: {error}", index + 1))?;
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+fn read_csv(reader: impl BufRead) -> Result<Vec<Sample>, Box<dyn Error>> {
+    Ok(csv::Reader::from_reader(reader)
+        .deserialize()
+        .collect::<Result<_, _>>()?)
+}
+
+/// A summary of the times of one variant and shape in one run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub median_ms: f64,
+    /// The mean and sample variance of the logarithms of the times.
+    log_mean: f64,
+    log_variance: f64,
+}
+
+impl Summary {
+    fn new(times: &[f64]) -> Self {
+        let mut sorted = times.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len();
+        let median_ms = (sorted[(count - 1) / 2] + sorted[count / 2]) / 2.0;
+
+        let logs = sorted.iter().map(|time| time.ln()).collect::<Vec<_>>();
+        let log_mean = logs.iter().sum::<f64>() / count as f64;
+        let log_variance = if count > 1 {
+            logs.iter().map(|log| (log - log_mean).powi(2)).sum::<f64>() / (count - 1) as f64
+        } else {
+            0.0
+        };
+        Self {
+            count,
+            median_ms,
+            log_mean,
+            log_variance,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The whole confidence interval is faster than the threshold.
+    Faster,
+    /// The whole confidence interval is slower than the threshold.
+    Regression,
+    /// The change is within the threshold, too noisy to tell, or measured fewer than
+    /// twice in one of the runs so there is no interval to judge it by.
+    Unchanged,
+}
+
+/// How one variant and shape changed between the runs.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub key: Key,
+    pub baseline: Summary,
+    pub current: Summary,
+    /// The baseline time divided by the current time, so above 1 is faster.
+    pub speedup: f64,
+    /// The 95% confidence interval of `speedup`, if both runs have two or more times.
+    pub interval: Option<(f64, f64)>,
+    pub verdict: Verdict,
+}
+
+/// Compares every variant and shape in both runs.
+///
+/// A change is only a regression or an improvement when its whole confidence interval
+/// is more than `threshold`, as a fraction, away from no change. Without an interval,
+/// because a run has a single time, a change is always [`Verdict::Unchanged`]: one
+/// sample says nothing about how much of the difference is noise.
+pub fn compare(baseline: &Samples, current: &Samples, threshold: f64) -> Vec<Comparison> {
+    baseline
+        .iter()
+        .filter_map(|(key, baseline)| Some((key, baseline, current.get(key)?)))
+        .map(|(key, baseline, current)| {
+            let (baseline, current) = (Summary::new(baseline), Summary::new(current));
+            let difference = baseline.log_mean - current.log_mean;
+            let speedup = difference.exp();
+            let interval = (baseline.count > 1 && current.count > 1).then(|| {
+                let margin = welch_margin(&baseline, &current);
+                ((difference - margin).exp(), (difference + margin).exp())
+            });
+
+            let verdict = match interval {
+                Some((low, _)) if low > 1.0 + threshold => Verdict::Faster,
+                Some((_, high)) if high < 1.0 / (1.0 + threshold) => Verdict::Regression,
+                _ => Verdict::Unchanged,
+            };
+            Comparison {
+                key: key.clone(),
+                baseline,
+                current,
+                speedup,
+                interval,
+                verdict,
+            }
+        })
+        .collect()
+}
+
+/// Half the width of the 95% confidence interval of the difference of the log means.
+fn welch_margin(baseline: &Summary, current: &Summary) -> f64 {
+    let baseline_term = baseline.log_variance / baseline.count as f64;
+    let current_term = current.log_variance / current.count as f64;
+    let standard_error = (baseline_term + current_term).sqrt();
+    if standard_error == 0.0 {
+        return 0.0;
+    }
+    // The Welch–Satterthwaite approximation of the degrees of freedom.
+    let degrees_of_freedom = (baseline_term + current_term).powi(2)
+        / (baseline_term.powi(2) / (baseline.count - 1) as f64
+            + current_term.powi(2) / (current.count - 1) as f64);
+    t_critical(degrees_of_freedom) * standard_error
+}
+
+/// The two-sided 95% critical value of Student's t-distribution, rounding the degrees
+/// of freedom down so the interval errs on the wide side.
+fn t_critical(degrees_of_freedom: f64) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom.floor() as usize {
+        0 => TABLE[0],
+        df @ 1..=30 => TABLE[df - 1],
+        31..=40 => 2.042,
+        41..=60 => 2.021,
+        61..=120 => 2.000,
+        _ => 1.980,
+    }
+}
+
+/// Renders `comparisons` as a Markdown table, slowest first.
+pub fn markdown(comparisons: &[Comparison], threshold: f64) -> String {
+    let mut comparisons = comparisons.iter().collect::<Vec<_>>();
+    comparisons.sort_by(|a, b| a.speedup.total_cmp(&b.speedup));
+    let adapters = comparisons
+        .iter()
+        .any(|comparison| comparison.key.adapter.is_some());
+
+    let mut table = String::new();
+    let header = if adapters {
+        "| Variant | Backend | Adapter | Shape | Baseline (ms) | Current (ms) | Speedup | 95% CI | |"
+    } else {
+        "| Variant | Backend | Shape | Baseline (ms) | Current (ms) | Speedup | 95% CI | |"
+    };
+    let columns = header.matches('|').count() - 1;
+    writeln!(table, "{header}").unwrap();
+    writeln!(table, "|{}", "---|".repeat(columns)).unwrap();
+
+    for comparison in comparisons {
+        let key = &comparison.key;
+        write!(table, "| {} | {} |", key.variant, key.backend).unwrap();
+        if adapters {
+            write!(table, " {} |", key.adapter.as_deref().unwrap_or("")).unwrap();
+        }
+        let interval = match comparison.interval {
+            Some((low, high)) => format!("{low:.2}x – {high:.2}x"),
+            None => "–".to_string(),
+        };
+        let verdict = match comparison.verdict {
+            Verdict::Faster => "faster",
+            Verdict::Regression => "**regression**",
+            Verdict::Unchanged => "",
+        };
+        writeln!(
+            table,
+            " {}x{}x{} | {:.3} (n={}) | {:.3} (n={}) | {:.2}x | {interval} | {verdict} |",
+            key.m,
+            key.k,
+            key.n,
+            comparison.baseline.median_ms,
+            comparison.baseline.count,
+            comparison.current.median_ms,
+            comparison.current.count,
+            comparison.speedup,
+        )
+        .unwrap();
+    }
+    writeln!(
+        table,
+        "\nTimes are medians. Changes within {:.0}% or whose interval crosses that are not flagged.",
+        threshold * 100.0
+    )
+    .unwrap();
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(variant: &str) -> Key {
+        Key {
+            variant: variant.to_string(),
+            backend: "wgpu".to_string(),
+            adapter: None,
+            m: 64,
+            k: 64,
+            n: 64,
+        }
+    }
+
+    fn samples(times: &[(&str, &[f64])]) -> Samples {
+        times
+            .iter()
+            .map(|(variant, times)| (key(variant), times.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = samples(&[
+            ("naive", &[10.0, 10.2, 9.8, 10.1]),
+            ("tiling_2d", &[1.0, 1.1, 0.9, 1.0]),
+            ("workgroup_256", &[2.0, 2.1, 1.9]),
+            ("noisy", &[1.0, 3.0, 1.0]),
+            ("single", &[4.0]),
+        ]);
+        let current = samples(&[
+            ("naive", &[5.0, 5.1, 4.9, 5.0]),
+            ("tiling_2d", &[1.5, 1.6, 1.4, 1.5]),
+            ("workgroup_256", &[2.0, 2.05, 1.95]),
+            ("noisy", &[3.0, 1.0, 3.0]),
+            ("single", &[5.0]),
+        ]);
+        let comparisons = compare(&baseline, &current, 0.05);
+        let find = |variant: &str| {
+            comparisons
+                .iter()
+                .find(|comparison| comparison.key.variant == variant)
+                .unwrap()
+        };
+        let verdict = |variant: &str| find(variant).verdict;
+        assert_eq!(verdict("naive"), Verdict::Faster);
+        assert_eq!(verdict("tiling_2d"), Verdict::Regression);
+        assert_eq!(verdict("workgroup_256"), Verdict::Unchanged);
+        assert_eq!(verdict("noisy"), Verdict::Unchanged);
+        assert_eq!(verdict("single"), Verdict::Unchanged);
+
+        let naive = find("naive");
+        assert!((naive.speedup - 2.0).abs() < 0.02);
+        let (low, high) = naive.interval.unwrap();
+        assert!(low < naive.speedup && naive.speedup < high);
+        assert!(find("single").interval.is_none());
+    }
+
+    #[test]
+    fn test_unmatched() {
+        let baseline = samples(&[("naive", &[1.0]), ("tiling_2d", &[1.0])]);
+        let current = samples(&[("tiling_2d", &[1.0]), ("isomorphic", &[1.0])]);
+        let comparisons = compare(&baseline, &current, 0.05);
+        assert_eq!(comparisons.len(), 1);
+        assert_eq!(comparisons[0].speedup, 1.0);
+    }
+
+    #[test]
+    fn test_read() {
+        let directory = std::env::temp_dir().join(format!("compare-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let jsonl = directory.join("records.jsonl");
+        std::fs::write(
+            &jsonl,
+            r#"{"schema_version":1,"command":"bench","variant":"naive","backend":"wgpu","adapter":"llvmpipe","m":2,"k":3,"n":4,"repetition":0,"compute_ms":1.5,"passed":true}
+{"schema_version":1,"command":"bench","variant":"naive","backend":"wgpu","adapter":"llvmpipe","m":2,"k":3,"n":4,"repetition":1,"compute_ms":null,"passed":false}
+"#,
+        )
+        .unwrap();
+        let csv = directory.join("records.csv");
+        std::fs::write(
+            &csv,
+            "schema_version,variant,backend,adapter,m,k,n,compute_ms,passed\n\
+             1,isomorphic,cpu:single,,8,8,8,0.25,true\n",
+        )
+        .unwrap();
+
+        let samples = read(&jsonl, false).unwrap();
+        let (key, times) = samples.iter().next().unwrap();
+        assert_eq!(key.adapter.as_deref(), Some("llvmpipe"));
+        assert_eq!(times, &[1.5]);
+        assert_eq!(
+            read(&jsonl, true).unwrap().keys().next().unwrap().adapter,
+            None
+        );
+
+        let samples = read(&csv, false).unwrap();
+        let (key, times) = samples.iter().next().unwrap();
+        assert_eq!(
+            (key.variant.as_str(), key.adapter.as_ref()),
+            ("isomorphic", None)
+        );
+        assert_eq!(times, &[0.25]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_markdown() {
+        let baseline = samples(&[("naive", &[2.0, 2.0]), ("tiling_2d", &[1.0])]);
+        let current = samples(&[("naive", &[1.0, 1.0]), ("tiling_2d", &[2.0])]);
+        let table = markdown(&compare(&baseline, &current, 0.05), 0.05);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "| Variant | Backend | Shape | Baseline (ms) | Current (ms) | Speedup | 95% CI | |"
+        );
+        assert_eq!(lines[1], "|---|---|---|---|---|---|---|---|");
+        assert_eq!(
+            lines[2],
+            "| tiling_2d | wgpu | 64x64x64 | 1.000 (n=1) | 2.000 (n=1) | 0.50x | – |  |"
+        );
+        assert_eq!(
+            lines[3],
+            "| naive | wgpu | 64x64x64 | 2.000 (n=2) | 1.000 (n=2) | 2.00x | 2.00x – 2.00x | faster |"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Machine-readable results, one record per multiplication, as JSON Lines or CSV.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The version of the record format. Bump it when a field is removed, renamed or
+/// changes meaning; adding a field does not need a new version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The result of one multiplication.
+///
+/// Fields measured after the multiplication are `None` when it failed, with the reason
+/// in `error`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Record {
+    pub schema_version: u32,
+    /// The command that produced the record: `run`, `bench` or `verify`.
+    pub command: &'static str,
+    pub variant: String,
+    pub backend: String,
+    /// The adapter GPU variants ran on. Empty for the CPU backends.
+    pub adapter: Option<String>,
+    pub m: u32,
+    pub k: u32,
+    pub n: u32,
+    /// Which repetition of this variant and shape, counting from 0 after warmup.
+    pub repetition: u32,
+    pub setup_ms: f64,
+    pub compute_ms: Option<f64>,
+    pub verify_ms: Option<f64>,
+    pub gflops: Option<f64>,
+    /// Bytes of both inputs and the result divided by the compute time, in GB/s.
+    pub bandwidth_gbps: Option<f64>,
+    pub max_abs_error: Option<f64>,
+    pub max_rel_error: Option<f64>,
+    /// The largest distance from the reference in units in the last place.
+    pub max_ulps: Option<u64>,
+    /// The number of elements outside the tolerance.
+    pub mismatches: Option<usize>,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma-separated values with a header row.
+    Csv,
+}
+
+impl Format {
+    /// The format for `path`'s extension, if it has a known one.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Format::Csv),
+            "jsonl" | "ndjson" | "json" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+/// Writes records to a file or stdout.
+pub struct Report {
+    writer: Writer,
+}
+
+enum Writer {
+    Jsonl(Box<dyn Write>),
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+}
+
+impl Report {
+    /// Writes to `path`, or stdout if it is `-`. Without a `format`, it is chosen by the
+    /// file extension and defaults to JSON Lines.
+    pub fn create(path: &Path, format: Option<Format>) -> io::Result<Self> {
+        let output: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Self::new(
+            output,
+            format
+                .or_else(|| Format::from_path(path))
+                .unwrap_or(Format::Jsonl),
+        ))
+    }
+
+    pub fn new(output: Box<dyn Write>, format: Format) -> Self {
+        let writer = match format {
+            Format::Jsonl => Writer::Jsonl(output),
+            Format::Csv => Writer::Csv(Box::new(csv::Writer::from_writer(output))),
+        };
+        Self { writer }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        match &mut self.writer {
+            Writer::Jsonl(output) => {
+                serde_json::to_writer(&mut *output, record)?;
+                writeln!(output)?;
+            }
+            Writer::Csv(output) => output.serialize(record)?,
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Writer::Jsonl(output) => output.flush(),
+            Writer::Csv(output) => output.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Output that can be read back after the report is done with it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(error: Option<&str>) -> Record {
+        Record {
+            schema_version: SCHEMA_VERSION,
+            command: "run",
+            variant: "tiling_2d".to_string(),
+            backend: "wgpu".to_string(),
+            adapter: Some("llvmpipe, \"software\"".to_string()),
+            m: 2,
+            k: 3,
+            n: 4,
+            repetition: 0,
+            setup_ms: 0.5,
+            compute_ms: error.is_none().then_some(1.5),
+            verify_ms: None,
+            gflops: None,
+            bandwidth_gbps: None,
+            max_abs_error: None,
+            max_rel_error: None,
+            max_ulps: None,
+            mismatches: None,
+            passed: error.is_none(),
+            error: error.map(str::to_string),
+        }
+    }
+
+    fn write(format: Format, records: &[Record]) -> String {
+        let buffer = Buffer::default();
+        let mut report = Report::new(Box::new(buffer.clone()), format);
+        for record in records {
+            report.write(record).unwrap();
+        }
+        report.flush().unwrap();
+        drop(report);
+        let bytes = buffer.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let output = write(Format::Jsonl, &[record(None), record(Some("lost"))]);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+
+        let value: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["variant"], "tiling_2d");
+        assert_eq!(value["compute_ms"], serde_json::Value::Null);
+        assert_eq!(value["passed"], false);
+        assert_eq!(value["error"], "lost");
+    }
+
+    #[test]
+    fn test_csv() {
+        let output = write(Format::Csv, &[record(None), record(Some("lost"))]);
+        let mut reader = csv::Reader::from_reader(output.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(&headers[0], "schema_version");
+        assert_eq!(headers.len(), 20);
+
+        let rows = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][4], "llvmpipe, \"software\"");
+        assert_eq!(&rows[0][10], "1.5");
+        assert_eq!(&rows[1][10], "");
+        assert_eq!(&rows[1][19], "lost");
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("out.csv")), Some(Format::Csv));
+        assert_eq!(
+            Format::from_path(Path::new("out.jsonl")),
+            Some(Format::Jsonl)
+        );
+        assert_eq!(Format::from_path(Path::new("-")), None);
+    }
+}
@@ -0,0 +1,326 @@
+//! The roofline chart drawn by `blog roofline`, as a table, SVG or JSON.
+
+use matmul::registry::VariantInfo;
+use matmul::roofline::{arithmetic_intensity, Ceilings};
+use serde::Serialize;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Write;
+
+/// The version of the JSON document. Bump it when a field is removed, renamed or
+/// changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The ceilings of an adapter and where each variant and shape sits under them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Roofline {
+    pub schema_version: u32,
+    pub adapter: String,
+    pub ceilings: Ceilings,
+    /// The intensity where the bandwidth and compute ceilings meet, in FLOPs per byte.
+    pub ridge_point: f64,
+    pub points: Vec<Point>,
+}
+
+/// One variant multiplying one shape.
+#[derive(Clone, Debug, Serialize)]
+pub struct Point {
+    pub variant: String,
+    pub m: u32,
+    pub k: u32,
+    pub n: u32,
+    /// FLOPs per byte loaded, from the variant's tile size.
+    pub intensity: f64,
+    /// The fastest of the repetitions.
+    pub gflops: f64,
+    /// The most the ceilings allow at this intensity.
+    pub attainable_gflops: f64,
+    /// `gflops` as a fraction of `attainable_gflops`.
+    pub efficiency: f64,
+}
+
+impl Roofline {
+    pub fn new(adapter: String, ceilings: Ceilings) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            adapter,
+            ceilings,
+            ridge_point: ceilings.ridge_point(),
+            points: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, info: &VariantInfo, size: (u32, u32, u32), gflops: f64) {
+        let (m, k, n) = size;
+        let intensity = arithmetic_intensity(info.tile);
+        let attainable_gflops = self.ceilings.attainable_gflops(intensity);
+        self.points.push(Point {
+            variant: info.name.to_string(),
+            m,
+            k,
+            n,
+            intensity,
+            gflops,
+            attainable_gflops,
+            efficiency: gflops / attainable_gflops,
+        });
+    }
+
+    /// Draws the chart with logarithmic axes, one color per variant.
+    pub fn to_svg(&self) -> String {
+        const WIDTH: f64 = 800.0;
+        const HEIGHT: f64 = 500.0;
+        const LEFT: f64 = 70.0;
+        const RIGHT: f64 = 180.0;
+        const TOP: f64 = 40.0;
+        const BOTTOM: f64 = 50.0;
+        const COLORS: [&str; 8] = [
+            "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+        ];
+
+        let Ceilings {
+            bandwidth_gbps,
+            peak_gflops,
+        } = self.ceilings;
+
+        // Whole decades that fit every point and both ceilings.
+        let intensities = self.points.iter().map(|point| point.intensity);
+        let (x_min, x_max) = decades(intensities.chain([self.ridge_point]));
+        let gflops = self.points.iter().map(|point| point.gflops);
+        let (y_min, y_max) = decades(gflops.chain([peak_gflops]));
+
+        let plot_width = WIDTH - LEFT - RIGHT;
+        let plot_height = HEIGHT - TOP - BOTTOM;
+        let x = |value: f64| LEFT + (value.log10() - x_min) / (x_max - x_min) * plot_width;
+        let y = |value: f64| TOP + (y_max - value.log10()) / (y_max - y_min) * plot_height;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="12">"#
+        )
+        .unwrap();
+        writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{}" y="20" text-anchor="middle" font-size="14">Roofline on {}</text>"#,
+            LEFT + plot_width / 2.0,
+            escape(&self.adapter)
+        )
+        .unwrap();
+
+        // Grid lines and labels at every decade.
+        for decade in x_min as i32..=x_max as i32 {
+            let position = x(10f64.powi(decade));
+            writeln!(
+                svg,
+                r##"<line x1="{position:.1}" y1="{TOP}" x2="{position:.1}" y2="{}" stroke="#ddd"/>"##,
+                TOP + plot_height
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<text x="{position:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                TOP + plot_height + 16.0,
+                10f64.powi(decade)
+            )
+            .unwrap();
+        }
+        for decade in y_min as i32..=y_max as i32 {
+            let position = y(10f64.powi(decade));
+            writeln!(
+                svg,
+                r##"<line x1="{LEFT}" y1="{position:.1}" x2="{}" y2="{position:.1}" stroke="#ddd"/>"##,
+                LEFT + plot_width
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{position:.1}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+                LEFT - 6.0,
+                10f64.powi(decade)
+            )
+            .unwrap();
+        }
+        writeln!(
+            svg,
+            r##"<rect x="{LEFT}" y="{TOP}" width="{plot_width}" height="{plot_height}" fill="none" stroke="#000"/>"##
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">Arithmetic intensity (FLOPs/byte)</text>"#,
+            LEFT + plot_width / 2.0,
+            HEIGHT - 10.0
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text transform="translate(18 {}) rotate(-90)" text-anchor="middle">GFLOPS</text>"#,
+            TOP + plot_height / 2.0
+        )
+        .unwrap();
+
+        // The roof: bandwidth bound up to the ridge point, compute bound after it. It
+        // starts where the bandwidth slope enters the chart.
+        let start = 10f64.powf(x_min).max(10f64.powf(y_min) / bandwidth_gbps);
+        writeln!(
+            svg,
+            r##"<polyline points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="none" stroke="#000" stroke-width="2"/>"##,
+            x(start),
+            y(bandwidth_gbps * start),
+            x(self.ridge_point),
+            y(peak_gflops),
+            x(10f64.powf(x_max)),
+            y(peak_gflops)
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{peak_gflops:.1} GFLOPS, {bandwidth_gbps:.1} GB/s</text>"#,
+            LEFT + plot_width - 4.0,
+            y(peak_gflops) - 6.0
+        )
+        .unwrap();
+
+        // A dot for each shape, colored by variant, with the details in its tooltip.
+        let mut variants = Vec::<&str>::new();
+        for point in &self.points {
+            if !variants.contains(&point.variant.as_str()) {
+                variants.push(&point.variant);
+            }
+        }
+        let color = |variant: &str| {
+            let index = variants.iter().position(|name| *name == variant);
+            COLORS[index.unwrap_or_default() % COLORS.len()]
+        };
+        for point in &self.points {
+            writeln!(
+                svg,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="{}" fill-opacity="0.7"><title>{} {}x{}x{}: {:.2} GFLOPS, {:.0}% of attainable</title></circle>"#,
+                x(point.intensity),
+                y(point.gflops),
+                color(&point.variant),
+                escape(&point.variant),
+                point.m,
+                point.k,
+                point.n,
+                point.gflops,
+                point.efficiency * 100.0
+            )
+            .unwrap();
+        }
+
+        for (index, variant) in variants.iter().enumerate() {
+            let top = TOP + 10.0 + index as f64 * 18.0;
+            writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{top}" r="5" fill="{}"/><text x="{}" y="{top}" dominant-baseline="middle">{}</text>"#,
+                WIDTH - RIGHT + 20.0,
+                color(variant),
+                WIDTH - RIGHT + 30.0,
+                escape(variant)
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+impl Display for Roofline {
+    /// The ceilings, then a table of every point.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Adapter:     {}", self.adapter)?;
+        writeln!(f, "Bandwidth:   {:.1} GB/s", self.ceilings.bandwidth_gbps)?;
+        writeln!(f, "Peak:        {:.1} GFLOPS", self.ceilings.peak_gflops)?;
+        writeln!(f, "Ridge point: {:.2} FLOPs/byte", self.ridge_point)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<16} {:>16} {:>10} {:>12} {:>12} {:>11}",
+            "variant", "shape", "intensity", "GFLOPS", "attainable", "efficiency"
+        )?;
+        for point in &self.points {
+            writeln!(
+                f,
+                "{:<16} {:>16} {:>10.2} {:>12.2} {:>12.2} {:>10.1}%",
+                point.variant,
+                format!("{}x{}x{}", point.m, point.k, point.n),
+                point.intensity,
+                point.gflops,
+                point.attainable_gflops,
+                point.efficiency * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The powers of ten just below the smallest and just above the largest of `values`,
+/// as exponents.
+fn decades(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values
+        .filter(|value| value.is_finite() && *value > 0.0)
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    if min > max {
+        return (0.0, 1.0);
+    }
+    let (low, high) = (min.log10().floor(), max.log10().ceil());
+    (low, high.max(low + 1.0))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roofline() -> Roofline {
+        let mut roofline = Roofline::new(
+            "llvmpipe <software> & co".to_string(),
+            Ceilings {
+                bandwidth_gbps: 50.0,
+                peak_gflops: 400.0,
+            },
+        );
+        let tiling_2d = matmul::registry::find("tiling_2d").unwrap();
+        roofline.add(&tiling_2d, (64, 64, 64), 10.0);
+        roofline.add(&tiling_2d, (512, 512, 512), 40.0);
+        roofline
+    }
+
+    #[test]
+    fn test_points() {
+        let roofline = roofline();
+        assert_eq!(roofline.ridge_point, 8.0);
+        let point = &roofline.points[1];
+        assert_eq!(point.intensity, 1.0);
+        assert_eq!(point.attainable_gflops, 50.0);
+        assert_eq!(point.efficiency, 0.8);
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = roofline().to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert!(svg.contains("llvmpipe &lt;software&gt; &amp; co"));
+    }
+
+    #[test]
+    fn test_decades() {
+        assert_eq!(decades([0.25, 8.0].into_iter()), (-1.0, 1.0));
+        assert_eq!(decades([100.0].into_iter()), (2.0, 3.0));
+        assert_eq!(decades([f64::NAN].into_iter()), (0.0, 1.0));
+    }
+}
@@ -0,0 +1,50 @@
+use gpu_build::{KernelBuilder, Requirements};
+
+/// The kernels that can be left out of the build, named after the features of both this
+/// crate and the GPU crate, and what each needs from the SPIR-V target. Kernels that
+/// need more than the defaults are compiled into a shader of their own.
+fn kernels() -> Vec<(&'static str, Requirements)> {
+    vec![
+        ("naive", Requirements::new()),
+        ("workgroup_256", Requirements::new()),
+        ("workgroup_2d", Requirements::new()),
+        ("tiling_1d", Requirements::new()),
+        ("tiling_1d_loop", Requirements::new()),
+        ("tiling_2d", Requirements::new()),
+        ("isomorphic", Requirements::new()),
+        ("microbench", Requirements::new()),
+    ]
+}
+
+/// The entry points a kernel's crate provides. The isomorphic kernel has one per tile
+/// size and the microbenchmarks one per measurement, the others one named after the
+/// kernel.
+fn entry_points(kernel: &str) -> Vec<String> {
+    match kernel {
+        "isomorphic" => ["1x1", "2x2", "4x4", "8x8"]
+            .iter()
+            .map(|tile| format!("matmul_isomorphic_{}", tile))
+            .collect(),
+        "microbench" => vec!["microbench_copy".to_string(), "microbench_fma".to_string()],
+        _ => vec![format!("matmul_{}", kernel)],
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let kernels = kernels();
+    let names = kernels.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let enabled = gpu_build::enabled_features(&names);
+
+    let mut builder = KernelBuilder::new("../../../gpu/kernels")
+        .required_entry_points(enabled.iter().flat_map(|kernel| entry_points(kernel)))
+        .features(enabled)
+        // The kernels and everything they depend on, so stale prebuilt SPIR-V is caught.
+        .sources(["../../../gpu", "../../../shared"])
+        .prebuilt_env("MATMUL_PREBUILT_SPIRV")
+        .prebuilt("prebuilt/kernels.spv")
+        .update_prebuilt_env("MATMUL_UPDATE_PREBUILT");
+    for (name, requirements) in kernels {
+        builder = builder.requirements(name, requirements);
+    }
+    builder.build()
+}
@@ -0,0 +1,7 @@
+// Including the compiled shader in our rust code. This "bloats" the binary, but it also
+// means you don't have to worry about the shader file being misplaced or deleted.
+//
+// The generated module provides the shader containing each entry point in `shaders`,
+// the names of the entry points in `entry_points` and what each was compiled with in
+// `capabilities` and `extensions`.
+include!(concat!(env!("OUT_DIR"), "/kernel.rs"));
@@ -0,0 +1,942 @@
+//! Shared build script logic for the `compiled_for_gpu` crates.
+//!
+//! A `compiled_for_gpu` crate's `build.rs` configures a [`KernelBuilder`] with the path
+//! to a GPU crate and calls [`KernelBuilder::build`]. The GPU crate is compiled to
+//! SPIR-V with `spirv-builder`, or loaded from a prebuilt file (see below), and a Rust
+//! module is generated that the `compiled_for_gpu` crate includes with:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/kernel.rs"));
+//! ```
+//!
+//! [`build_kernel`] is a shorthand for a builder with no other configuration.
+//!
+//! The generated module contains:
+//!
+//! - `MODULES`, every compiled shader as 32-bit words, embedded with `include_bytes!`
+//!   and guaranteed to be 4-byte aligned so they can be handed to `wgpu` without
+//!   copying.
+//! - `ENTRY_POINTS`, the names of every entry point in the shaders.
+//! - `entry_points::*`, one constant per entry point (`matmul` becomes `MATMUL`).
+//! - `shaders::*` and `spirv()`, the shader containing each entry point.
+//! - `workgroup_sizes::*` and `workgroup_size()`, the `#[spirv(compute(threads(..)))]`
+//!   of each entry point.
+//! - `capabilities::*` and `extensions::*`, the SPIR-V capabilities and extensions each
+//!   entry point was compiled with, see [`Requirements`].
+//!
+//! Before generating anything, the bindings of every entry point are checked against
+//! `settings::BufferLayout` and the build fails if a kernel disagrees with the CPU side.
+//!
+//! # Features
+//!
+//! Each kernel sits behind a feature of the GPU crate. A `compiled_for_gpu` crate
+//! declares features of the same names and forwards the enabled ones with
+//! [`enabled_features`] and [`KernelBuilder::features`], so a kernel that isn't needed
+//! is never compiled. [`KernelBuilder::required_entry_points`] then makes sure the
+//! shader has every entry point those features promise.
+//!
+//! This crate's own `compile` feature pulls in `spirv-builder` and is what lets the GPU
+//! crate be compiled at all. Without it, every build uses prebuilt SPIR-V.
+//!
+//! # Prebuilt SPIR-V
+//!
+//! Compiling the GPU crate needs the nightly toolchain and the rust-gpu codegen backend.
+//! To avoid that, a build can use a prebuilt `.spv` file instead, see
+//! [`KernelBuilder::prebuilt`]. Two files must sit next to it:
+//!
+//! - `.sha256`, the SHA-256 hash of the SPIR-V (as written by `sha256sum`), which catches
+//!   a truncated or corrupted artifact.
+//! - `.sources`, the hash of the kernel sources it was compiled from (see
+//!   [`KernelBuilder::sources`]), which catches an artifact that is older than the
+//!   kernels.
+//!
+//! The build fails if either doesn't match. Without the `compile` feature,
+//! `spirv-builder` is not a dependency at all and a prebuilt file is required.
+//!
+//! Every build that compiles from source writes all three files to `OUT_DIR`, as
+//! `kernel.spv`, `kernel.spv.sha256` and `kernel.spv.sources`. To update the checked-in
+//! artifact after changing a kernel, build once with the `compile` feature and the
+//! variable given to [`KernelBuilder::update_prebuilt_env`] set, which also writes them
+//! to the [`KernelBuilder::prebuilt`] path:
+//!
+//! ```text
+//! MATMUL_UPDATE_PREBUILT=1 cargo build -p compiled_kernels
+//! ```
+//!
+//! and commit the files. CI runs the same build and fails if it changes them.
+//!
+//! # Requirements
+//!
+//! By default kernels are compiled for [`TARGET`] with no extra capabilities. A kernel
+//! that needs more, such as subgroup operations or `Float16`, declares it with
+//! [`KernelBuilder::requirements`]. Such a kernel is compiled on its own into a separate
+//! shader, so the capabilities it needs don't stop the other kernels from running on
+//! devices without them. Its files get the feature name as a suffix, e.g.
+//! `kernel-tiling_2d.spv`, and so do the prebuilt files it is loaded from.
+
+pub mod reflect;
+
+use reflect::EntryPoint;
+use rspirv::spirv::Capability;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The SPIR-V target kernels are compiled for unless their [`Requirements`] say
+/// otherwise.
+pub const TARGET: &str = "spirv-unknown-vulkan1.2";
+
+/// Compiles the GPU crate at `gpu_crate_path` (relative to the calling crate) with its
+/// default features and writes `kernel.rs` to `OUT_DIR`.
+pub fn build_kernel(gpu_crate_path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    KernelBuilder::new(gpu_crate_path).build()
+}
+
+/// Returns the names in `features` that are enabled on the crate running the build
+/// script, so they can be forwarded to the GPU crate with [`KernelBuilder::features`].
+pub fn enabled_features<'a>(features: &[&'a str]) -> Vec<&'a str> {
+    features
+        .iter()
+        .copied()
+        .filter(|feature| {
+            let name = feature.to_uppercase().replace('-', "_");
+            env::var_os(format!("CARGO_FEATURE_{}", name)).is_some()
+        })
+        .collect()
+}
+
+/// What a kernel needs from the SPIR-V target it is compiled for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Requirements {
+    target: String,
+    capabilities: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl Default for Requirements {
+    fn default() -> Self {
+        Self {
+            target: TARGET.to_string(),
+            capabilities: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+impl Requirements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The target to compile for, e.g. `spirv-unknown-vulkan1.3`.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// A SPIR-V capability by its name in the specification, e.g. `Int8` or
+    /// `GroupNonUniformArithmetic`.
+    pub fn capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    /// A SPIR-V extension, e.g. `SPV_KHR_8bit_storage`.
+    pub fn extension(mut self, extension: impl Into<String>) -> Self {
+        self.extensions.push(extension.into());
+        self
+    }
+
+    /// Fails if a capability is not one SPIR-V knows about.
+    fn check(&self) -> Result<(), Box<dyn Error>> {
+        for capability in &self.capabilities {
+            if Capability::from_str(capability).is_err() {
+                return Err(format!("Unknown SPIR-V capability {}", capability).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One shader to compile, holding either every kernel with the default requirements or
+/// a single kernel with requirements of its own.
+struct Shader {
+    /// Appended to file names, `None` for the shader with the default requirements.
+    suffix: Option<String>,
+    #[cfg_attr(not(feature = "compile"), allow(dead_code))]
+    features: Option<Vec<String>>,
+    requirements: Requirements,
+}
+
+/// Compiles a GPU crate and generates the module embedding it.
+pub struct KernelBuilder {
+    gpu_crate_path: PathBuf,
+    features: Option<Vec<String>>,
+    requirements: Vec<(String, Requirements)>,
+    required_entry_points: Vec<String>,
+    sources: Vec<PathBuf>,
+    prebuilt_env: Option<String>,
+    prebuilt_path: Option<PathBuf>,
+    update_prebuilt_env: Option<String>,
+}
+
+impl KernelBuilder {
+    /// `gpu_crate_path` is relative to the crate running the build script.
+    pub fn new(gpu_crate_path: impl AsRef<Path>) -> Self {
+        Self {
+            gpu_crate_path: gpu_crate_path.as_ref().to_path_buf(),
+            features: None,
+            requirements: Vec::new(),
+            required_entry_points: Vec::new(),
+            sources: Vec::new(),
+            prebuilt_env: None,
+            prebuilt_path: None,
+            update_prebuilt_env: None,
+        }
+    }
+
+    /// Compiles the GPU crate with only these features instead of its defaults.
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features = Some(features.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Compiles the kernels behind `feature` with `requirements` instead of the defaults,
+    /// in a shader of their own. Only applies when [`KernelBuilder::features`] is used.
+    pub fn requirements(mut self, feature: impl Into<String>, requirements: Requirements) -> Self {
+        self.requirements.push((feature.into(), requirements));
+        self
+    }
+
+    /// Fails the build if any of these entry points are missing from the shader. This
+    /// matters most for prebuilt SPIR-V, which may have been built with other features.
+    pub fn required_entry_points<I, S>(mut self, entry_points: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.required_entry_points = entry_points.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The directories (relative to the crate running the build script) holding every
+    /// source the kernels are compiled from, including the crates the GPU crate depends
+    /// on. Prebuilt SPIR-V is only used if it was compiled from the same files. Defaults
+    /// to the GPU crate alone.
+    pub fn sources<I, P>(mut self, directories: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.sources = directories
+            .into_iter()
+            .map(|directory| directory.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+
+    /// Uses the prebuilt SPIR-V file named by the environment variable `name`, if it is
+    /// set, instead of compiling the GPU crate.
+    ///
+    /// If the file does not exist the GPU crate is compiled as usual, so the variable can
+    /// be set unconditionally in CI.
+    pub fn prebuilt_env(mut self, name: impl Into<String>) -> Self {
+        self.prebuilt_env = Some(name.into());
+        self
+    }
+
+    /// Uses the prebuilt SPIR-V file at `path` (relative to the crate running the build
+    /// script) when the GPU crate cannot be compiled because the `compile` feature is
+    /// disabled. This is where checked-in artifacts go.
+    pub fn prebuilt(mut self, path: impl AsRef<Path>) -> Self {
+        self.prebuilt_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// When the environment variable `name` is set, SPIR-V compiled from source is also
+    /// written to the [`KernelBuilder::prebuilt`] path along with its hashes, ready to
+    /// be checked in.
+    pub fn update_prebuilt_env(mut self, name: impl Into<String>) -> Self {
+        self.update_prebuilt_env = Some(name.into());
+        self
+    }
+
+    /// Compiles the GPU crate, or loads the prebuilt SPIR-V, and writes `kernel.rs` to
+    /// `OUT_DIR`.
+    pub fn build(self) -> Result<(), Box<dyn Error>> {
+        let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+        for (_, requirements) in &self.requirements {
+            requirements.check()?;
+        }
+        let sources = sources_hash(&self.source_directories())?;
+
+        let mut modules = Vec::new();
+        for shader in self.shaders() {
+            let spirv_path = with_suffix(&out_dir.join("kernel.spv"), shader.suffix.as_deref());
+            self.load_or_compile(&shader, &spirv_path, &sources)?;
+
+            // Make sure every kernel binds its buffers the way the CPU side expects.
+            let entry_points = reflect::reflect(&read_words(&spirv_path)?)?;
+            for entry_point in &entry_points {
+                reflect::check_buffer_layout(entry_point)?;
+            }
+            modules.push((spirv_path, entry_points, shader.requirements));
+        }
+
+        for name in &self.required_entry_points {
+            if !modules
+                .iter()
+                .flat_map(|(_, entry_points, _)| entry_points)
+                .any(|entry_point| entry_point.name == *name)
+            {
+                return Err(format!("Entry point {} is missing from the SPIR-V", name).into());
+            }
+        }
+
+        let generated_code = generate_module(&modules);
+        let kernel_rs = out_dir.join("kernel.rs");
+        fs::write(&kernel_rs, generated_code)?;
+
+        println!("Generated kernel module at {:?}", kernel_rs);
+        Ok(())
+    }
+
+    /// Splits the kernels into the shaders they are compiled into.
+    fn shaders(&self) -> Vec<Shader> {
+        let default = Requirements::default();
+        let Some(features) = &self.features else {
+            return vec![Shader {
+                suffix: None,
+                features: None,
+                requirements: default,
+            }];
+        };
+
+        let mut shaders = Vec::new();
+        let mut shared = Vec::new();
+        for feature in features {
+            match self.requirements.iter().find(|(name, _)| name == feature) {
+                Some((name, requirements)) if *requirements != default => shaders.push(Shader {
+                    suffix: Some(name.clone()),
+                    features: Some(vec![feature.clone()]),
+                    requirements: requirements.clone(),
+                }),
+                _ => shared.push(feature.clone()),
+            }
+        }
+        if !shared.is_empty() || shaders.is_empty() {
+            shaders.insert(
+                0,
+                Shader {
+                    suffix: None,
+                    features: Some(shared),
+                    requirements: default,
+                },
+            );
+        }
+        shaders
+    }
+
+    /// The directories the sources hash covers.
+    fn source_directories(&self) -> Vec<PathBuf> {
+        if self.sources.is_empty() {
+            vec![self.gpu_crate_path.clone()]
+        } else {
+            self.sources.clone()
+        }
+    }
+
+    /// Copies the prebuilt SPIR-V for `shader` to `spirv_path`, or compiles it there if
+    /// there is none. `sources` is the hash of the current kernel sources.
+    fn load_or_compile(
+        &self,
+        shader: &Shader,
+        spirv_path: &Path,
+        sources: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        // Copy the shader next to the generated code so `include_bytes!` can find it
+        // with a stable path.
+        let suffix = shader.suffix.as_deref();
+        match self.prebuilt_artifact(suffix) {
+            Some(artifact) if artifact.exists() => {
+                println!("cargo::rerun-if-changed={}", artifact.display());
+                println!("cargo::rerun-if-changed={}", hash_path(&artifact).display());
+                println!(
+                    "cargo::rerun-if-changed={}",
+                    sources_path(&artifact).display()
+                );
+                for directory in self.source_directories() {
+                    println!("cargo::rerun-if-changed={}", directory.display());
+                }
+                verify_prebuilt(&artifact, sources)?;
+                fs::copy(&artifact, spirv_path)?;
+            }
+            artifact => {
+                if let Some(artifact) = artifact {
+                    println!(
+                        "cargo::warning=Prebuilt SPIR-V {} not found, compiling from source",
+                        artifact.display()
+                    );
+                }
+                self.compile(shader, spirv_path)?;
+                record(spirv_path, sources)?;
+
+                if let Some(prebuilt) = self.prebuilt_to_update(suffix) {
+                    if let Some(directory) = prebuilt.parent() {
+                        fs::create_dir_all(directory)?;
+                    }
+                    fs::copy(spirv_path, &prebuilt)?;
+                    record(&prebuilt, sources)?;
+                    println!(
+                        "cargo::warning=Updated prebuilt SPIR-V {}",
+                        prebuilt.display()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where to write SPIR-V compiled from source, if the build asked for it.
+    fn prebuilt_to_update(&self, suffix: Option<&str>) -> Option<PathBuf> {
+        let name = self.update_prebuilt_env.as_ref()?;
+        println!("cargo::rerun-if-env-changed={}", name);
+        env::var_os(name)?;
+        self.prebuilt_path
+            .as_ref()
+            .map(|path| with_suffix(path, suffix))
+    }
+
+    /// The prebuilt SPIR-V to use, if the build asked for one.
+    fn prebuilt_artifact(&self, suffix: Option<&str>) -> Option<PathBuf> {
+        if let Some(name) = &self.prebuilt_env {
+            println!("cargo::rerun-if-env-changed={}", name);
+            if let Some(path) = env::var_os(name) {
+                return Some(with_suffix(Path::new(&path), suffix));
+            }
+        }
+        if cfg!(feature = "compile") {
+            None
+        } else {
+            self.prebuilt_path
+                .as_ref()
+                .map(|path| with_suffix(path, suffix))
+        }
+    }
+
+    /// Compiles the GPU crate with `spirv-builder` and copies the result to `spirv_path`.
+    #[cfg(feature = "compile")]
+    fn compile(&self, shader: &Shader, spirv_path: &Path) -> Result<(), Box<dyn Error>> {
+        use spirv_builder::{MetadataPrintout, SpirvBuilder};
+
+        let gpu_crate_path = self.gpu_crate_path.as_path();
+        println!("cargo::rerun-if-changed={}", gpu_crate_path.display());
+
+        // Compile the shader crate with SpirvBuilder.
+        let requirements = &shader.requirements;
+        let mut builder = SpirvBuilder::new(gpu_crate_path, &requirements.target)
+            .print_metadata(MetadataPrintout::Full);
+        for capability in &requirements.capabilities {
+            let capability = spirv_builder::Capability::from_str(capability)
+                .map_err(|_| format!("Unknown SPIR-V capability {}", capability))?;
+            builder = builder.capability(capability);
+        }
+        for extension in &requirements.extensions {
+            builder = builder.extension(extension.as_str());
+        }
+        if let Some(features) = &shader.features {
+            builder = builder
+                .shader_crate_default_features(false)
+                .shader_crate_features(features.iter().cloned());
+        }
+        let result = builder.build()?;
+
+        fs::copy(result.module.unwrap_single(), spirv_path)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "compile"))]
+    fn compile(&self, shader: &Shader, _spirv_path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut message = format!(
+            "Cannot compile {} without the `compile` feature; provide prebuilt SPIR-V",
+            self.gpu_crate_path.display()
+        );
+        if let Some(name) = &self.prebuilt_env {
+            message += &format!(" with {}", name);
+        }
+        if let Some(path) = &self.prebuilt_path {
+            message += &format!(
+                " at {}",
+                with_suffix(path, shader.suffix.as_deref()).display()
+            );
+        }
+        Err(message.into())
+    }
+}
+
+/// Inserts `-suffix` before the extension of `path`, e.g. `kernel.spv` becomes
+/// `kernel-tiling_2d.spv`.
+fn with_suffix(path: &Path, suffix: Option<&str>) -> PathBuf {
+    let Some(suffix) = suffix else {
+        return path.to_path_buf();
+    };
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(format!("-{}", suffix));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Appends `extension` to the file name of `path`, e.g. `kernel.spv` becomes
+/// `kernel.spv.sha256`.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Where the hash of the SPIR-V at `path` is recorded.
+fn hash_path(path: &Path) -> PathBuf {
+    with_extension(path, ".sha256")
+}
+
+/// Where the hash of the sources the SPIR-V at `path` was compiled from is recorded.
+fn sources_path(path: &Path) -> PathBuf {
+    with_extension(path, ".sources")
+}
+
+/// Hashes the name and contents of every file under `directories`, skipping `target`
+/// directories and hidden files, so SPIR-V can be matched to the sources it was
+/// compiled from.
+fn sources_hash(directories: &[PathBuf]) -> Result<String, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for directory in directories {
+        let name = directory
+            .canonicalize()?
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        collect_files(directory, &name, &mut files)?;
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for (name, path) in files {
+        let contents = fs::read(&path)?;
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Adds every file under `directory` to `files`, named by their path below it with `/`
+/// separators and `prefix` in front.
+fn collect_files(
+    directory: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with('.') || file_name == "target" {
+            continue;
+        }
+        let name = format!("{}/{}", prefix, file_name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &name, files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+/// Writes the hashes of the SPIR-V at `path` and of the `sources` it was compiled from
+/// next to it.
+fn record(path: &Path, sources: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(hash_path(path), hash_line(path)?)?;
+    fs::write(sources_path(path), format!("{}\n", sources))?;
+    Ok(())
+}
+
+/// Checks prebuilt SPIR-V against its recorded hash and the hash of the current
+/// `sources`.
+fn verify_prebuilt(path: &Path, sources: &str) -> Result<(), Box<dyn Error>> {
+    verify_hash(path)?;
+
+    let sources_path = sources_path(path);
+    let recorded = fs::read_to_string(&sources_path).map_err(|error| {
+        format!(
+            "Prebuilt SPIR-V {} has no recorded sources hash at {}: {}",
+            path.display(),
+            sources_path.display(),
+            error
+        )
+    })?;
+    if recorded.trim() != sources {
+        return Err(format!(
+            "Prebuilt SPIR-V {} was compiled from other kernel sources than these, \
+             compile it again with the `compile` feature",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn sha256(path: &Path) -> Result<String, Box<dyn Error>> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// The hash of `path` in the format written by `sha256sum`.
+fn hash_line(path: &Path) -> Result<String, Box<dyn Error>> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    Ok(format!("{}  {}\n", sha256(path)?, file_name))
+}
+
+/// Checks the SPIR-V at `path` against the hash recorded next to it.
+fn verify_hash(path: &Path) -> Result<(), Box<dyn Error>> {
+    let hash_path = hash_path(path);
+    let recorded = fs::read_to_string(&hash_path).map_err(|error| {
+        format!(
+            "Prebuilt SPIR-V {} has no recorded hash at {}: {}",
+            path.display(),
+            hash_path.display(),
+            error
+        )
+    })?;
+    let recorded = recorded.split_whitespace().next().unwrap_or_default();
+
+    let actual = sha256(path)?;
+    if !recorded.eq_ignore_ascii_case(&actual) {
+        return Err(format!(
+            "Prebuilt SPIR-V {} has hash {} but {} records {}",
+            path.display(),
+            actual,
+            hash_path.display(),
+            recorded
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Reads a SPIR-V file as 32-bit words.
+fn read_words(path: &Path) -> Result<Vec<u32>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    if bytes.len() % 4 != 0 {
+        return Err(format!("{} is not a whole number of words", path.display()).into());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect())
+}
+
+/// Generates the Rust code embedding the compiled shaders.
+fn generate_module(modules: &[(PathBuf, Vec<EntryPoint>, Requirements)]) -> String {
+    let entry_points = modules
+        .iter()
+        .enumerate()
+        .flat_map(|(index, (_, entry_points, requirements))| {
+            entry_points
+                .iter()
+                .map(move |entry_point| (index, entry_point, requirements))
+        })
+        .collect::<Vec<_>>();
+    let names = entry_points
+        .iter()
+        .map(|(_, entry_point, _)| entry_point.name.as_str())
+        .collect::<Vec<_>>();
+    let mut code = String::new();
+
+    // `include_bytes!` only guarantees byte alignment, so wrap it in a type that is
+    // aligned to 4 bytes before reinterpreting the bytes as words.
+    writeln!(
+        code,
+        "/// Every compiled SPIR-V shader, as 32-bit words.\n\
+         pub const MODULES: &[&[u32]] = &["
+    )
+    .unwrap();
+    for (spirv_path, _, _) in modules {
+        writeln!(
+            code,
+            "    {{\n        \
+                 #[repr(C, align(4))]\n        \
+                 struct Aligned<Bytes: ?Sized>(Bytes);\n        \
+                 const BYTES: &Aligned<[u8]> = &Aligned(*include_bytes!({:?}));\n        \
+                 // SAFETY: `BYTES` is 4-byte aligned and SPIR-V is always a whole number\n        \
+                 // of words.\n        \
+                 unsafe {{ ::core::slice::from_raw_parts(BYTES.0.as_ptr().cast::<u32>(), BYTES.0.len() / 4) }}\n    \
+             }},",
+            spirv_path.display().to_string()
+        )
+        .unwrap();
+    }
+    writeln!(code, "];\n").unwrap();
+
+    writeln!(
+        code,
+        "/// Names of all entry points in the shaders.\n\
+         pub const ENTRY_POINTS: &[&str] = &{:?};\n",
+        names
+    )
+    .unwrap();
+
+    writeln!(
+        code,
+        "/// Entry point names as constants.\npub mod entry_points {{"
+    )
+    .unwrap();
+    for name in &names {
+        writeln!(
+            code,
+            "    pub const {}: &str = {:?};",
+            constant_name(name),
+            name
+        )
+        .unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// The shader containing each entry point, as constants.\n\
+         pub mod shaders {{"
+    )
+    .unwrap();
+    for (index, entry_point, _) in &entry_points {
+        writeln!(
+            code,
+            "    pub const {}: &[u32] = super::MODULES[{}];",
+            constant_name(&entry_point.name),
+            index
+        )
+        .unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// The shader containing `entry_point`.\n\
+         pub fn spirv(entry_point: &str) -> Option<&'static [u32]> {{\n    \
+             match entry_point {{"
+    )
+    .unwrap();
+    for (_, entry_point, _) in &entry_points {
+        writeln!(
+            code,
+            "        {:?} => Some(shaders::{}),",
+            entry_point.name,
+            constant_name(&entry_point.name)
+        )
+        .unwrap();
+    }
+    writeln!(code, "        _ => None,\n    }}\n}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// Workgroup sizes from `#[spirv(compute(threads(..)))]`, as constants.\n\
+         pub mod workgroup_sizes {{"
+    )
+    .unwrap();
+    for (_, entry_point, _) in &entry_points {
+        writeln!(
+            code,
+            "    pub const {}: [u32; 3] = {:?};",
+            constant_name(&entry_point.name),
+            entry_point.workgroup_size
+        )
+        .unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// The workgroup size of `entry_point`, from `#[spirv(compute(threads(..)))]`.\n\
+         pub fn workgroup_size(entry_point: &str) -> Option<[u32; 3]> {{\n    \
+             match entry_point {{"
+    )
+    .unwrap();
+    for (_, entry_point, _) in &entry_points {
+        writeln!(
+            code,
+            "        {:?} => Some(workgroup_sizes::{}),",
+            entry_point.name,
+            constant_name(&entry_point.name)
+        )
+        .unwrap();
+    }
+    writeln!(code, "        _ => None,\n    }}\n}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// SPIR-V capabilities each entry point was compiled with, beyond the defaults.\n\
+         pub mod capabilities {{"
+    )
+    .unwrap();
+    for (_, entry_point, requirements) in &entry_points {
+        writeln!(
+            code,
+            "    pub const {}: &[&str] = &{:?};",
+            constant_name(&entry_point.name),
+            requirements.capabilities
+        )
+        .unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(
+        code,
+        "/// SPIR-V extensions each entry point was compiled with.\n\
+         pub mod extensions {{"
+    )
+    .unwrap();
+    for (_, entry_point, requirements) in &entry_points {
+        writeln!(
+            code,
+            "    pub const {}: &[&str] = &{:?};",
+            constant_name(&entry_point.name),
+            requirements.extensions
+        )
+        .unwrap();
+    }
+    writeln!(code, "}}").unwrap();
+
+    code
+}
+
+/// Turns an entry point name into a constant name, e.g. `matmul` into `MATMUL`.
+fn constant_name(entry_point: &str) -> String {
+    entry_point
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("gpu_build_{}", name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_requirements_get_a_shader_of_their_own() {
+        let half = Requirements::new()
+            .capability("Float16")
+            .extension("SPV_KHR_16bit_storage");
+        let builder = KernelBuilder::new("gpu")
+            .features(["naive", "half", "tiling_2d"])
+            .requirements("half", half.clone())
+            .requirements("tiling_2d", Requirements::new());
+        let shaders = builder.shaders();
+        assert_eq!(shaders.len(), 2);
+        assert_eq!(shaders[0].suffix, None);
+        assert_eq!(
+            shaders[0].features.as_deref(),
+            Some(&["naive".to_string(), "tiling_2d".to_string()][..])
+        );
+        assert_eq!(shaders[1].suffix.as_deref(), Some("half"));
+        assert_eq!(shaders[1].requirements, half);
+
+        let entry_point = |name: &str| EntryPoint {
+            name: name.to_string(),
+            workgroup_size: [16, 16, 1],
+            bindings: Vec::new(),
+        };
+        let code = generate_module(&[
+            (
+                PathBuf::from("kernel.spv"),
+                vec![entry_point("matmul_naive")],
+                Requirements::new(),
+            ),
+            (
+                PathBuf::from("kernel-half.spv"),
+                vec![entry_point("matmul_half")],
+                half,
+            ),
+        ]);
+        assert!(code.contains("pub const MATMUL_NAIVE: &[&str] = &[];"));
+        assert!(code.contains("pub const MATMUL_HALF: &[&str] = &[\"Float16\"];"));
+        assert!(code.contains("pub const MATMUL_HALF: &[&str] = &[\"SPV_KHR_16bit_storage\"];"));
+        assert!(code.contains("pub const MATMUL_HALF: &[u32] = super::MODULES[1];"));
+
+        let unknown = Requirements::new().capability("Float17");
+        assert!(unknown.check().is_err());
+    }
+
+    #[test]
+    fn test_sources_hash() {
+        let directory = temp_dir("sources_hash");
+        let gpu = directory.join("gpu");
+        let sources = [gpu.clone()];
+        fs::create_dir_all(gpu.join("naive/src")).unwrap();
+        fs::create_dir_all(gpu.join("target")).unwrap();
+        fs::write(gpu.join("naive/src/lib.rs"), "fn kernel() {}").unwrap();
+        let hash = sources_hash(&sources).unwrap();
+
+        // Build output and hidden files don't count.
+        fs::write(gpu.join("target/output"), "").unwrap();
+        fs::write(gpu.join(".swp"), "").unwrap();
+        assert_eq!(sources_hash(&sources).unwrap(), hash);
+
+        fs::write(gpu.join("naive/src/lib.rs"), "fn kernel() { }").unwrap();
+        assert_ne!(sources_hash(&sources).unwrap(), hash);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_verify_prebuilt() {
+        let directory = temp_dir("verify_prebuilt");
+        let spirv = directory.join("kernels.spv");
+        fs::write(&spirv, [0x03, 0x02, 0x23, 0x07]).unwrap();
+
+        // What a build that compiles from source records.
+        record(&spirv, "sources").unwrap();
+        verify_prebuilt(&spirv, "sources").unwrap();
+
+        let error = verify_prebuilt(&spirv, "changed sources").unwrap_err();
+        assert!(
+            error.to_string().contains("other kernel sources"),
+            "{error}"
+        );
+
+        fs::write(&spirv, [0x03, 0x02, 0x23]).unwrap();
+        let error = verify_prebuilt(&spirv, "sources").unwrap_err();
+        assert!(error.to_string().contains("has hash"), "{error}");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
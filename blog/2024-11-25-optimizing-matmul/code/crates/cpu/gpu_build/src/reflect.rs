@@ -0,0 +1,201 @@
+//! Reads the entry points and their resource bindings back out of compiled SPIR-V, and
+//! checks them against what the CPU side expects in [`settings::BufferLayout`].
+
+use rspirv::dr::{Module, Operand};
+use rspirv::spirv::{Decoration, ExecutionMode, Op, StorageClass, Word};
+use settings::BufferLayout;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// A resource bound to an entry point with `#[spirv(descriptor_set = .., binding = ..)]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub descriptor_set: u32,
+    pub binding: u32,
+    pub storage_class: StorageClass,
+    /// Whether the kernel only reads the resource (`&T` rather than `&mut T`).
+    pub readonly: bool,
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "set {} binding {} ({:?}, {})",
+            self.descriptor_set,
+            self.binding,
+            self.storage_class,
+            if self.readonly { "read" } else { "read/write" }
+        )
+    }
+}
+
+/// Everything the CPU needs to know about an entry point to run it.
+#[derive(Clone, Debug)]
+pub struct EntryPoint {
+    pub name: String,
+    /// From `#[spirv(compute(threads(x, y, z)))]`.
+    pub workgroup_size: [u32; 3],
+    /// Sorted by descriptor set and binding.
+    pub bindings: Vec<Binding>,
+}
+
+/// Extracts every entry point in `spirv`.
+pub fn reflect(spirv: &[u32]) -> Result<Vec<EntryPoint>, String> {
+    let module = rspirv::dr::load_words(spirv).map_err(|error| error.to_string())?;
+
+    module
+        .entry_points
+        .iter()
+        .map(|entry_point| {
+            let (function, name, interface) = match entry_point.operands.as_slice() {
+                [_, Operand::IdRef(function), Operand::LiteralString(name), interface @ ..] => {
+                    (*function, name.clone(), interface)
+                }
+                _ => return Err("Malformed OpEntryPoint".to_string()),
+            };
+
+            let workgroup_size = workgroup_size(&module, function)
+                .ok_or_else(|| format!("Entry point {} has no workgroup size", name))?;
+
+            // Since SPIR-V 1.4 the interface lists every global variable the entry point
+            // uses, including its buffers.
+            let mut bindings = interface
+                .iter()
+                .filter_map(|operand| match operand {
+                    Operand::IdRef(id) => binding(&module, *id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            bindings.sort_by_key(|binding| (binding.descriptor_set, binding.binding));
+
+            Ok(EntryPoint {
+                name,
+                workgroup_size,
+                bindings,
+            })
+        })
+        .collect()
+}
+
+/// Checks that `entry_point` binds exactly the buffers described by
+/// [`BufferLayout`], in the way the bind group layout on the CPU side expects.
+pub fn check_buffer_layout(entry_point: &EntryPoint) -> Result<(), String> {
+    let expected = [
+        (
+            "dimensions",
+            BufferLayout::DIMENSIONS,
+            StorageClass::Uniform,
+        ),
+        ("a", BufferLayout::A_MATRIX, StorageClass::StorageBuffer),
+        ("b", BufferLayout::B_MATRIX, StorageClass::StorageBuffer),
+        ("result", BufferLayout::RESULT, StorageClass::StorageBuffer),
+    ];
+
+    let mut errors = Vec::new();
+    for (name, layout, storage_class) in expected {
+        let expected = Binding {
+            descriptor_set: 0,
+            binding: layout.binding,
+            storage_class,
+            readonly: layout.readonly,
+        };
+        match entry_point
+            .bindings
+            .iter()
+            .find(|binding| binding.descriptor_set == 0 && binding.binding == layout.binding)
+        {
+            Some(binding) if *binding == expected => {}
+            Some(binding) => errors.push(format!("`{name}` expected {expected}, found {binding}")),
+            None => errors.push(format!("`{name}` expected {expected}, found nothing")),
+        }
+    }
+    for binding in &entry_point.bindings {
+        let known = expected
+            .iter()
+            .any(|(_, layout, _)| binding.descriptor_set == 0 && binding.binding == layout.binding);
+        if !known {
+            errors.push(format!("unexpected {binding}"));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Entry point {} does not match `settings::BufferLayout`:\n  {}",
+            entry_point.name,
+            errors.join("\n  ")
+        ))
+    }
+}
+
+fn workgroup_size(module: &Module, function: Word) -> Option<[u32; 3]> {
+    module.execution_modes.iter().find_map(|instruction| {
+        let [Operand::IdRef(target), Operand::ExecutionMode(mode), size @ ..] =
+            instruction.operands.as_slice()
+        else {
+            return None;
+        };
+        match size {
+            [Operand::LiteralInt32(x), Operand::LiteralInt32(y), Operand::LiteralInt32(z)]
+                if *target == function && *mode == ExecutionMode::LocalSize =>
+            {
+                Some([*x, *y, *z])
+            }
+            _ => None,
+        }
+    })
+}
+
+fn binding(module: &Module, variable: Word) -> Option<Binding> {
+    let storage_class = module
+        .types_global_values
+        .iter()
+        .find(|instruction| {
+            instruction.class.opcode == Op::Variable && instruction.result_id == Some(variable)
+        })
+        .and_then(|instruction| match instruction.operands.first() {
+            Some(Operand::StorageClass(storage_class)) => Some(*storage_class),
+            _ => None,
+        })?;
+
+    let decorations = decorations(module, variable).collect::<Vec<_>>();
+    let literal = |decoration: Decoration| {
+        decorations.iter().find_map(|operands| match operands {
+            [Operand::Decoration(found), Operand::LiteralInt32(value)] if *found == decoration => {
+                Some(*value)
+            }
+            _ => None,
+        })
+    };
+    let descriptor_set = literal(Decoration::DescriptorSet)?;
+    let binding = literal(Decoration::Binding)?;
+
+    // Uniform buffers can never be written to. Storage buffers are marked `NonWritable`
+    // when the kernel takes them by shared reference.
+    let readonly = storage_class == StorageClass::Uniform
+        || decorations
+            .iter()
+            .any(|operands| matches!(operands, [Operand::Decoration(Decoration::NonWritable)]));
+
+    Some(Binding {
+        descriptor_set,
+        binding,
+        storage_class,
+        readonly,
+    })
+}
+
+/// The operands of every `OpDecorate` on `target`, without the target itself.
+fn decorations(module: &Module, target: Word) -> impl Iterator<Item = &[Operand]> {
+    module
+        .annotations
+        .iter()
+        .filter(move |instruction| {
+            instruction.class.opcode == Op::Decorate
+                && instruction.operands.first() == Some(&Operand::IdRef(target))
+        })
+        .map(|instruction| &instruction.operands[1..])
+}
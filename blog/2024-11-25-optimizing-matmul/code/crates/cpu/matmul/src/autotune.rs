@@ -0,0 +1,591 @@
+//! Picks the fastest variant for a shape by trying them.
+//!
+//! Which variant wins depends on both the shape of the matrices and the adapter, so
+//! [`AutoMatrixMultiplier`] benchmarks the candidates the first time it sees a shape
+//! bucket and remembers the winner in a JSON cache file keyed by adapter and bucket.
+//!
+//! Tuning runs on the caller's matrices inside the first multiplication of each bucket,
+//! so by default it only tries the variants that are worth it, the GPU ones with every
+//! tile configuration and the multi-threaded CPU ones, skips shapes the
+//! [`registry::RULES`] rule out, and stops trying more candidates once its time budget is
+//! spent.
+
+use crate::device::{DeviceOptions, GpuContext};
+use crate::registry::{self, Backend, VariantInfo};
+use crate::{DynMatrixMultiply, MatrixMultiplyError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// The version of the cache file format. Caches with a different version are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// The adapter key used when none of the candidates run on the GPU.
+const CPU_ADAPTER: &str = "cpu";
+
+/// How long tuning a shape bucket may take before the remaining candidates are skipped.
+const DEFAULT_TIME_BUDGET: Duration = Duration::from_secs(2);
+
+/// Shapes are grouped by rounding each dimension up to the next power of two, so a
+/// 1000x1000x1000 multiplication reuses the result tuned for 1024x1024x1024.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShapeBucket {
+    pub m: u32,
+    pub k: u32,
+    pub n: u32,
+}
+
+impl ShapeBucket {
+    pub fn new(m: u32, k: u32, n: u32) -> Self {
+        Self {
+            m: m.next_power_of_two(),
+            k: k.next_power_of_two(),
+            n: n.next_power_of_two(),
+        }
+    }
+}
+
+impl Display for ShapeBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.m, self.k, self.n)
+    }
+}
+
+/// The winners found so far, per adapter and shape bucket.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TuningCache {
+    version: u32,
+    /// Adapter key -> shape bucket -> variant id.
+    adapters: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Default for TuningCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TuningCache {
+    pub fn new() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            adapters: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the cache from `path`, starting empty if the file is missing, unreadable
+    /// or from a different version.
+    pub fn load(path: &Path) -> Self {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Self::new();
+        };
+        match serde_json::from_str::<Self>(&contents) {
+            Ok(cache) if cache.version == CACHE_VERSION => cache,
+            Ok(cache) => {
+                warn!(
+                    version = cache.version,
+                    "Ignoring tuning cache from another version"
+                );
+                Self::new()
+            }
+            Err(error) => {
+                warn!(%error, "Ignoring unreadable tuning cache");
+                Self::new()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents)
+    }
+
+    pub fn get(&self, adapter: &str, bucket: ShapeBucket) -> Option<&str> {
+        self.adapters
+            .get(adapter)
+            .and_then(|buckets| buckets.get(&bucket.to_string()))
+            .map(String::as_str)
+    }
+
+    pub fn insert(&mut self, adapter: &str, bucket: ShapeBucket, variant: &str) {
+        self.adapters
+            .entry(adapter.to_string())
+            .or_default()
+            .insert(bucket.to_string(), variant.to_string());
+    }
+}
+
+/// Builds an [`AutoMatrixMultiplier`].
+pub struct AutoMatrixMultiplierBuilder {
+    context: Option<Arc<GpuContext>>,
+    candidates: Option<Vec<String>>,
+    cache_path: Option<PathBuf>,
+    forced_variant: Option<String>,
+    samples: usize,
+    time_budget: Duration,
+}
+
+impl AutoMatrixMultiplierBuilder {
+    /// Runs GPU candidates on `context` instead of creating a device on the default
+    /// adapter.
+    pub fn context(mut self, context: Arc<GpuContext>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Only consider these variant ids, such as `tiling_2d@2x8:wgpu`. Defaults to the GPU
+    /// variants, each of their [`registry::tile_configurations`] and the multi-threaded
+    /// CPU variants, on the shapes [`VariantInfo::practical`] allows.
+    pub fn candidates<I, S>(mut self, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.candidates = Some(candidates.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Where to load and save tuning results. Without a path results are only kept in
+    /// memory.
+    pub fn cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
+
+    /// Always use this variant and never benchmark, which makes results deterministic
+    /// in tests.
+    pub fn force_variant(mut self, id: impl Into<String>) -> Self {
+        self.forced_variant = Some(id.into());
+        self
+    }
+
+    /// How many timed runs each candidate gets. The fastest run is used.
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// How long tuning a shape bucket may take, 2 seconds by default. Once it is spent
+    /// candidates get a single timed run and the ones not tried yet are skipped, so it
+    /// can be overrun by the slowest run of a candidate.
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = budget;
+        self
+    }
+
+    pub fn build(self) -> Result<AutoMatrixMultiplier, MatrixMultiplyError> {
+        let chosen = self.forced_variant.is_some() || self.candidates.is_some();
+        let mut candidates = match (&self.forced_variant, self.candidates) {
+            (Some(id), _) => vec![registry::find(id)?],
+            (None, Some(ids)) => ids
+                .iter()
+                .map(|id| registry::find(id))
+                .collect::<Result<Vec<_>, _>>()?,
+            (None, None) => default_candidates(),
+        };
+
+        // Create the device up front so every GPU candidate shares it.
+        let needs_gpu = candidates.iter().any(|info| info.backend.is_gpu());
+        let context = match self.context {
+            Some(context) => Some(context),
+            None if needs_gpu => {
+                match futures::executor::block_on(GpuContext::new(&DeviceOptions::default())) {
+                    Ok(context) => Some(Arc::new(context)),
+                    // Without an adapter, the default candidates fall back to the variants
+                    // that run on the CPU. Candidates that were asked for still fail.
+                    Err(error) if !chosen => {
+                        candidates.retain(|info| !info.backend.is_gpu());
+                        if candidates.is_empty() {
+                            return Err(error);
+                        }
+                        warn!(%error, "No GPU adapter, only tuning the CPU variants");
+                        None
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            None => None,
+        };
+
+        let adapter = match &context {
+            Some(context) if needs_gpu => {
+                let info = context.adapter_info();
+                format!("{} ({:?}, {})", info.name, info.backend, info.driver)
+            }
+            _ => CPU_ADAPTER.to_string(),
+        };
+
+        let cache = match &self.cache_path {
+            Some(path) => TuningCache::load(path),
+            None => TuningCache::new(),
+        };
+
+        Ok(AutoMatrixMultiplier {
+            context,
+            adapter,
+            candidates,
+            forced: self.forced_variant.is_some(),
+            practical_only: !chosen,
+            cache_path: self.cache_path,
+            samples: self.samples,
+            time_budget: self.time_budget,
+            cache: Mutex::new(cache),
+            multipliers: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+/// The candidates when none are given, see [`AutoMatrixMultiplierBuilder::candidates`].
+fn default_candidates() -> Vec<VariantInfo> {
+    let mut candidates = registry::variants();
+    candidates.retain(|info| info.backend != Backend::CpuSingle);
+    candidates.extend(registry::tile_configurations());
+    // The GPU variants are usually fastest, trying them first leaves the budget to the
+    // candidates most likely to win.
+    candidates.sort_by_key(|info| !info.backend.is_gpu());
+    candidates
+}
+
+/// Multiplies matrices with whichever candidate variant is fastest for the shape.
+pub struct AutoMatrixMultiplier {
+    context: Option<Arc<GpuContext>>,
+    adapter: String,
+    candidates: Vec<VariantInfo>,
+    forced: bool,
+    /// Whether candidates are only tuned on the shapes [`VariantInfo::practical`] allows,
+    /// rather than every shape they support.
+    practical_only: bool,
+    cache_path: Option<PathBuf>,
+    samples: usize,
+    time_budget: Duration,
+    cache: Mutex<TuningCache>,
+    multipliers: Mutex<HashMap<String, Arc<dyn DynMatrixMultiply>>>,
+}
+
+impl AutoMatrixMultiplier {
+    pub fn builder() -> AutoMatrixMultiplierBuilder {
+        AutoMatrixMultiplierBuilder {
+            context: None,
+            candidates: None,
+            cache_path: None,
+            forced_variant: None,
+            samples: 3,
+            time_budget: DEFAULT_TIME_BUDGET,
+        }
+    }
+
+    /// The key results are stored under in the cache, built from the adapter name,
+    /// backend and driver.
+    pub fn adapter_key(&self) -> &str {
+        &self.adapter
+    }
+
+    /// The variant id that will be used for this shape, if it has already been tuned.
+    pub fn selected(&self, m: u32, k: u32, n: u32) -> Option<String> {
+        if self.forced {
+            return Some(self.candidates[0].id());
+        }
+        let cache = self.cache.lock().ok()?;
+        cache
+            .get(&self.adapter, ShapeBucket::new(m, k, n))
+            .map(str::to_string)
+    }
+
+    /// A snapshot of the tuning results.
+    pub fn cache(&self) -> Result<TuningCache, MatrixMultiplyError> {
+        self.cache
+            .lock()
+            .map(|cache| cache.clone())
+            .map_err(|_| MatrixMultiplyError::CpuLockError)
+    }
+
+    fn multiplier(
+        &self,
+        info: &VariantInfo,
+    ) -> Result<Arc<dyn DynMatrixMultiply>, MatrixMultiplyError> {
+        let mut multipliers = self
+            .multipliers
+            .lock()
+            .map_err(|_| MatrixMultiplyError::CpuLockError)?;
+        if let Some(multiplier) = multipliers.get(&info.id()) {
+            return Ok(multiplier.clone());
+        }
+        let multiplier: Arc<dyn DynMatrixMultiply> = info.create(self.context.as_ref())?.into();
+        multipliers.insert(info.id(), multiplier.clone());
+        Ok(multiplier)
+    }
+
+    /// Whether `info` is tuned on this shape.
+    fn eligible(&self, info: &VariantInfo, m: u32, k: u32, n: u32) -> bool {
+        match self.practical_only {
+            true => info.practical(m, k, n),
+            false => info.supports(m, k, n),
+        }
+    }
+
+    /// Times the candidates that are eligible for the shape, until the time budget runs
+    /// out, and returns the fastest along with the result it computed.
+    fn tune(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<(String, Vec<f32>), MatrixMultiplyError> {
+        let deadline = Instant::now() + self.time_budget;
+        let mut best: Option<(Duration, String, Vec<f32>)> = None;
+
+        for info in self
+            .candidates
+            .iter()
+            .filter(|info| self.eligible(info, m, k, n))
+        {
+            if best.is_some() && Instant::now() >= deadline {
+                debug!(variant = %info, "Tuning budget spent, skipping candidate");
+                continue;
+            }
+
+            let multiplier = match self.multiplier(info) {
+                Ok(multiplier) => multiplier,
+                Err(error) => {
+                    warn!(variant = %info, %error, "Skipping candidate that failed to initialize");
+                    continue;
+                }
+            };
+
+            let timed = self.time(multiplier.as_ref(), deadline, a, b, m, k, n);
+            let (fastest, result) = match timed {
+                Ok(timed) => timed,
+                Err(error) => {
+                    warn!(variant = %info, %error, "Skipping candidate that failed to multiply");
+                    continue;
+                }
+            };
+            debug!(variant = %info, ?fastest, "Timed candidate");
+
+            if best.as_ref().is_none_or(|(time, _, _)| fastest < *time) {
+                best = Some((fastest, info.id(), result));
+            }
+        }
+
+        best.map(|(_, id, result)| (id, result))
+            .ok_or(MatrixMultiplyError::NoSupportedVariant(m, k, n))
+    }
+
+    /// Returns the fastest of [`AutoMatrixMultiplierBuilder::samples`] runs, or of one run
+    /// once `deadline` has passed, and the result of the last one.
+    #[allow(clippy::too_many_arguments)]
+    fn time(
+        &self,
+        multiplier: &dyn DynMatrixMultiply,
+        deadline: Instant,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<(Duration, Vec<f32>), MatrixMultiplyError> {
+        // The first run includes one-time costs like pipeline warmup.
+        let mut result = multiplier.multiply(a, b, m, k, n)?;
+
+        let mut fastest = Duration::MAX;
+        for sample in 0..self.samples {
+            let start = Instant::now();
+            if sample > 0 && start >= deadline {
+                break;
+            }
+            result = multiplier.multiply(a, b, m, k, n)?;
+            fastest = fastest.min(start.elapsed());
+        }
+        Ok((fastest, result))
+    }
+}
+
+impl Display for AutoMatrixMultiplier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "auto")
+    }
+}
+
+impl DynMatrixMultiply for AutoMatrixMultiplier {
+    fn multiply(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<Vec<f32>, MatrixMultiplyError> {
+        if let Some(id) = self.selected(m, k, n) {
+            if let Some(info) = self.candidates.iter().find(|info| info.id() == id) {
+                // Buckets round up, so the winner may have been tuned on a smaller shape
+                // than this one and be unable to multiply it.
+                if info.supports(m, k, n) {
+                    return self.multiplier(info)?.multiply(a, b, m, k, n);
+                }
+                debug!(variant = %info, m, k, n, "Winner does not support the shape, tuning again");
+            }
+        }
+
+        let bucket = ShapeBucket::new(m, k, n);
+        let (id, result) = self.tune(a, b, m, k, n)?;
+        info!(adapter = %self.adapter, %bucket, variant = %id, "Tuned shape bucket");
+
+        let mut cache = self
+            .cache
+            .lock()
+            .map_err(|_| MatrixMultiplyError::CpuLockError)?;
+        cache.insert(&self.adapter, bucket, &id);
+        if let Some(path) = &self.cache_path {
+            if let Err(error) = cache.save(path) {
+                warn!(path = %path.display(), %error, "Failed to save tuning cache");
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_CANDIDATES: [&str; 2] = ["isomorphic:cpu:single", "isomorphic:cpu:multi"];
+
+    /// A cache file of its own for each test, and each process running the tests.
+    fn cache_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "matmul-autotune-{}-{test}.json",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_shape_bucket_rounds_up() {
+        assert_eq!(ShapeBucket::new(1000, 1, 17), ShapeBucket::new(1024, 1, 32));
+        assert_eq!(ShapeBucket::new(3, 4, 5).to_string(), "4x4x8");
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let path = cache_path("cache_round_trip");
+        let mut cache = TuningCache::new();
+        cache.insert("cpu", ShapeBucket::new(4, 4, 4), "isomorphic:cpu:single");
+        cache.save(&path).expect("Failed to save cache");
+
+        let loaded = TuningCache::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded, cache);
+        assert_eq!(
+            loaded.get("cpu", ShapeBucket::new(3, 3, 3)),
+            Some("isomorphic:cpu:single")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_tunes_and_remembers_winner() {
+        let path = cache_path("tunes_and_remembers_winner");
+        std::fs::remove_file(&path).ok();
+
+        let multiplier = AutoMatrixMultiplier::builder()
+            .candidates(CPU_CANDIDATES)
+            .cache_path(&path)
+            .samples(1)
+            .build()
+            .expect("Failed to create");
+        assert_eq!(multiplier.selected(2, 1, 1), None);
+
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+
+        let winner = multiplier.selected(2, 1, 1).expect("Shape was not tuned");
+        assert!(CPU_CANDIDATES.contains(&winner.as_str()));
+
+        // A new multiplier picks the winner up from the cache file.
+        let reloaded = AutoMatrixMultiplier::builder()
+            .candidates(CPU_CANDIDATES)
+            .cache_path(&path)
+            .build()
+            .expect("Failed to create");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(reloaded.selected(2, 1, 1), Some(winner));
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_default_candidates() {
+        // Without an adapter this only tunes the CPU variants instead of failing.
+        let multiplier = AutoMatrixMultiplier::builder()
+            .samples(1)
+            .build()
+            .expect("Failed to create");
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_time_budget() {
+        // With the budget spent by the first candidate, the others are never tried.
+        let multiplier = AutoMatrixMultiplier::builder()
+            .candidates(CPU_CANDIDATES)
+            .samples(1000)
+            .time_budget(Duration::ZERO)
+            .build()
+            .expect("Failed to create");
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+        assert_eq!(
+            multiplier.selected(2, 1, 1).as_deref(),
+            Some(CPU_CANDIDATES[0])
+        );
+    }
+
+    #[test]
+    fn test_default_candidate_order() {
+        let candidates = default_candidates();
+        assert!(candidates
+            .iter()
+            .all(|info| info.backend != Backend::CpuSingle));
+        assert!(candidates.is_sorted_by_key(|info| !info.backend.is_gpu()));
+        #[cfg(feature = "tiling_2d")]
+        for id in ["tiling_2d:wgpu", "tiling_2d@1x1:wgpu", "tiling_2d@2x4:wgpu"] {
+            assert!(
+                candidates.iter().any(|info| info.id() == id),
+                "{id} is not a candidate"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_forced_variant() {
+        let multiplier = AutoMatrixMultiplier::builder()
+            .force_variant("isomorphic:cpu:single")
+            .build()
+            .expect("Failed to create");
+        assert_eq!(
+            multiplier.selected(64, 64, 64).as_deref(),
+            Some("isomorphic:cpu:single")
+        );
+
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+    }
+}
@@ -50,26 +50,25 @@ where
             for gwy in 0..dispatch.y {
                 for wx in 0..workgroup.x {
                     for wy in 0..workgroup.y {
-                        // Calculate global indices
+                        // Calculate global indices. Like on the GPU, every invocation
+                        // in the grid runs and the kernel checks its own bounds.
                         let x = gwx * workgroup.x + wx;
                         let y = gwy * workgroup.y + wy;
 
-                        if x < m && y < n {
-                            // Define global id
-                            let global_id = UVec3::new(x, y, 1);
-
-                            // Perform the matmul operation for element (x, y). NOTE:
-                            // This is the EXACT SAME CODE THAT RUNS ON THE GPU, RUNNING
-                            // ON THE CPU. This is the power of rust-gpu.
-                            <T as Cpu>::call(
-                                &self.variant,
-                                global_id,
-                                &dimensions,
-                                &a,
-                                &b,
-                                &mut result,
-                            );
-                        }
+                        // Define global id
+                        let global_id = UVec3::new(x, y, 1);
+
+                        // Perform the matmul operation for element (x, y). NOTE:
+                        // This is the EXACT SAME CODE THAT RUNS ON THE GPU, RUNNING
+                        // ON THE CPU. This is the power of rust-gpu.
+                        <T as Cpu>::call(
+                            &self.variant,
+                            global_id,
+                            &dimensions,
+                            &a,
+                            &b,
+                            &mut result,
+                        );
                     }
                 }
             }
@@ -122,14 +121,10 @@ where
             .flat_map(|gwx| {
                 (0..dispatch.y).flat_map(move |gwy| {
                     (0..workgroup.x).flat_map(move |wx| {
-                        (0..workgroup.y).filter_map(move |wy| {
+                        (0..workgroup.y).map(move |wy| {
                             let x = gwx * workgroup.x + wx;
                             let y = gwy * workgroup.y + wy;
-                            if x < m && y < n {
-                                Some((x as usize, y as usize))
-                            } else {
-                                None
-                            }
+                            (x as usize, y as usize)
                         })
                     })
                 })
@@ -166,7 +161,7 @@ where
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "isomorphic"))]
 mod tests {
     use super::*;
     use futures::executor::block_on;
@@ -282,4 +277,31 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_tile_sizes_agree() {
+        // Not a multiple of any tile size, and wider than it is tall.
+        let (m, k, n) = (5, 3, 37);
+        let a = (0..m * k).map(|i| i as f32).collect::<Vec<_>>();
+        let b = (0..k * n).map(|i| (i % 7) as f32).collect::<Vec<_>>();
+
+        use crate::variants::IsomorphicTiled;
+        let results = [
+            block_on(MultiThreadedMatMul::new(IsomorphicTiled::<1, 1>))
+                .and_then(|multiplier| multiplier.multiply(&a, &b, m, k, n)),
+            block_on(MultiThreadedMatMul::new(IsomorphicTiled::<2, 2>))
+                .and_then(|multiplier| multiplier.multiply(&a, &b, m, k, n)),
+            block_on(MultiThreadedMatMul::new(IsomorphicTiled::<4, 4>))
+                .and_then(|multiplier| multiplier.multiply(&a, &b, m, k, n)),
+            block_on(MultiThreadedMatMul::new(IsomorphicTiled::<8, 8>))
+                .and_then(|multiplier| multiplier.multiply(&a, &b, m, k, n)),
+        ];
+        for result in results {
+            let result = result.expect("Matrix multiplication failed");
+            let report = crate::verify::Verifier::new()
+                .verify(&a, &b, &result, m, k, n)
+                .unwrap();
+            assert!(report.passed(), "{report}");
+        }
+    }
 }
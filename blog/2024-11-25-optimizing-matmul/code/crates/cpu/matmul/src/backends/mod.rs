@@ -1,2 +1,4 @@
+// Only the `isomorphic` variant runs on the CPU.
+#[cfg_attr(not(feature = "isomorphic"), allow(dead_code))]
 pub mod cpu;
 pub mod wgpu;
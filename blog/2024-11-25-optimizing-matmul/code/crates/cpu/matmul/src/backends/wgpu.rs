@@ -1,20 +1,22 @@
-use crate::{Gpu, GridComputation, MatrixMultiply, MatrixMultiplyError};
+use crate::device::{AdapterSelection, DeviceOptions, GpuContext};
+use crate::{Gpu, GridComputation, MatrixMultiply, MatrixMultiplyError, PhasedMultiply};
 use bytemuck;
 use futures::channel::oneshot;
 use futures::executor::block_on;
-use settings::{BufferLayout, Dimensions, SHADER_ENTRY_POINT};
+use settings::{spec_constants, BufferLayout, Dimensions};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::sync::Arc;
 use tracing::trace;
 use wgpu::{self, util::DeviceExt};
 
 /// Matrix multiplication on the GPU using `wgpu`.
 pub struct MatrixMultiplier<T> {
-    pub device: wgpu::Device,
-    queue: wgpu::Queue,
+    context: Arc<GpuContext>,
     pipeline: wgpu::ComputePipeline,
-    bind_group_layout: wgpu::BindGroupLayout,
     variant: T,
 }
 
@@ -24,42 +26,124 @@ impl<T: Display> Display for MatrixMultiplier<T> {
     }
 }
 
-impl<T> MatrixMultiply<T> for MatrixMultiplier<T>
+impl<T> MatrixMultiplier<T>
 where
     T: Gpu + GridComputation + Display + Send,
 {
-    /// Initializes a new `MatrixMultiplier` with necessary GPU resources.
-    async fn new(variant: T) -> Result<Self, MatrixMultiplyError> {
-        // Set up WGPU to talk to the system's GPUs and manage rendering or compute tasks.
-        let instance = create_instance().await;
-
-        // Find a GPU.
-        let adapter = request_adapter(&instance).await;
-
-        // Get access to the GPU and its command system for sending tasks.
-        let (device, queue) = request_device_and_queue(&adapter).await;
-
-        // Load the compiled code that we will run on the GPU.
-        let shader = create_shader_module(&device, <T as Gpu>::compiled_shader(&variant));
+    /// Initializes a new `MatrixMultiplier` on the adapter chosen by `options`.
+    pub async fn with_options(
+        variant: T,
+        options: &DeviceOptions,
+    ) -> Result<Self, MatrixMultiplyError> {
+        let context = GpuContext::new(options).await?;
+        Self::from_context(Arc::new(context), variant)
+    }
 
-        // Define how the GPU will connect data and resources to the GPU program.
-        let bind_group_layout = create_bind_group_layout(&device);
+    /// Initializes a new `MatrixMultiplier` on an existing device.
+    ///
+    /// Only the pipeline for this variant is compiled, everything else is shared with
+    /// other multipliers created from the same `context`.
+    pub fn from_context(context: Arc<GpuContext>, variant: T) -> Result<Self, MatrixMultiplyError> {
+        let passthrough = check_features(&context, &variant)?;
 
-        // Specify how the GPU pipeline organizes its resources and GPU programs.
-        let pipeline_layout = create_pipeline_layout(&device, &bind_group_layout);
+        // Invalid SPIR-V is reported through an error scope rather than a panic, which
+        // matters for shaders loaded at runtime.
+        let (spirv, constants) = specialized_shader(&variant)?;
+        let pipeline = validated(&context.device, || {
+            // Load the compiled code that we will run on the GPU. Variants that are entry
+            // points in the same module only create it once per context.
+            let shader = context.shader_module(&spirv, passthrough);
 
-        // Build the actual GPU pipeline to run the GPU program and manage execution.
-        let pipeline = create_compute_pipeline(&device, &pipeline_layout, &shader);
+            // Build the actual GPU pipeline to run the GPU program and manage execution.
+            create_compute_pipeline(
+                &context.device,
+                &context.pipeline_layout,
+                &shader,
+                variant.entry_point(),
+                &constants,
+            )
+        })?;
 
         Ok(Self {
-            device,
-            queue,
+            context,
             pipeline,
-            bind_group_layout,
             variant,
         })
     }
 
+    /// Loads the variant's shader again and recreates the pipeline from it.
+    ///
+    /// If the new shader fails to load or compile the error is returned, and both the
+    /// previous shader and pipeline stay in use.
+    pub fn reload(&mut self) -> Result<(), MatrixMultiplyError> {
+        self.variant.reload_shader()?;
+        match self.reloaded_pipeline() {
+            Ok(pipeline) => {
+                self.pipeline = pipeline;
+                trace!("Reloaded shader for {}", self.variant);
+                Ok(())
+            }
+            Err(error) => {
+                self.variant.revert_shader();
+                Err(error)
+            }
+        }
+    }
+
+    /// Creates a pipeline from the variant's current shader, without touching the one
+    /// in use.
+    fn reloaded_pipeline(&self) -> Result<wgpu::ComputePipeline, MatrixMultiplyError> {
+        let passthrough = check_features(&self.context, &self.variant)?;
+
+        // Not cached in the context, every reload would leave another module behind.
+        let device = &self.context.device;
+        let (spirv, constants) = specialized_shader(&self.variant)?;
+        validated(device, || {
+            let shader = create_shader_module(device, &spirv, passthrough);
+            create_compute_pipeline(
+                device,
+                &self.context.pipeline_layout,
+                &shader,
+                self.variant.entry_point(),
+                &constants,
+            )
+        })
+    }
+
+    /// Calls [`MatrixMultiplier::reload`] if the variant's shader changed, returning
+    /// whether it did.
+    pub fn reload_if_changed(&mut self) -> Result<bool, MatrixMultiplyError> {
+        if !self.variant.shader_changed() {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// The variant this multiplier runs.
+    pub fn variant(&self) -> &T {
+        &self.variant
+    }
+
+    /// The device and queue this multiplier runs on.
+    pub fn context(&self) -> &Arc<GpuContext> {
+        &self.context
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.context.device
+    }
+}
+
+impl<T> MatrixMultiply<T> for MatrixMultiplier<T>
+where
+    T: Gpu + GridComputation + Display + Send,
+{
+    /// Initializes a new `MatrixMultiplier` with necessary GPU resources.
+    async fn new(variant: T) -> Result<Self, MatrixMultiplyError> {
+        Self::with_options(variant, &DeviceOptions::default()).await
+    }
+
     /// Executes matrix multiplication for given input matrices.
     ///
     /// Uploads the input matrices to the GPU, dispatches the compute shader,
@@ -79,7 +163,7 @@ where
         // Create a memory buffer on the GPU to store matrix `a`, initialized with data
         // copied from the CPU.
         let a_buffer = create_buffer_init(
-            &self.device,
+            &self.context.device,
             "Matrix A Buffer",
             a,
             wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
@@ -88,7 +172,7 @@ where
         // Create a memory buffer on the GPU to store matrix `b`, initialized with data
         // copied from the CPU.
         let b_buffer = create_buffer_init(
-            &self.device,
+            &self.context.device,
             "Matrix B Buffer",
             b,
             wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
@@ -96,7 +180,7 @@ where
 
         // Allocate GPU memory for storing the result.
         let result_buffer = create_buffer(
-            &self.device,
+            &self.context.device,
             "Result Buffer",
             result_size,
             wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
@@ -111,7 +195,7 @@ where
         // and often less on older GPUs).
         let dimensions = Dimensions::new(m, k, n);
         let dimensions_buffer = create_buffer_init(
-            &self.device,
+            &self.context.device,
             "Dimensions Buffer",
             &[dimensions],
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
@@ -119,8 +203,8 @@ where
 
         // Group all related buffers for use in the compute pipeline.
         let bind_group = create_bind_group(
-            &self.device,
-            &self.bind_group_layout,
+            &self.context.device,
+            &self.context.bind_group_layout,
             &a_buffer,
             &b_buffer,
             &result_buffer,
@@ -128,14 +212,15 @@ where
         );
 
         // Create a buffer to retrieve computation results back from the GPU.
-        let staging_buffer = create_staging_buffer(&self.device, result_size);
+        let staging_buffer = create_staging_buffer(&self.context.device, result_size);
 
         // Set up commands to perform the computation on the GPU.
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Matrix Multiply Encoder"),
-            });
+        let mut encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Matrix Multiply Encoder"),
+                });
 
         {
             // Define the compute pass, specifying which GPU program to run and what
@@ -156,7 +241,7 @@ where
 
         // Copy the GPU's result into a buffer for CPU access.
         encoder.copy_buffer_to_buffer(&result_buffer, 0, &staging_buffer, 0, result_size);
-        self.queue.submit(Some(encoder.finish()));
+        self.context.queue.submit(Some(encoder.finish()));
 
         // Make the staging buffer's data available to the CPU.
         let slice = staging_buffer.slice(..);
@@ -166,7 +251,7 @@ where
             let _ = sender.send(result);
         });
 
-        self.device.poll(wgpu::Maintain::Wait);
+        self.context.device.poll(wgpu::Maintain::Wait);
 
         // Wait for the mapping to complete and verify success.
         block_on(receiver)
@@ -182,59 +267,638 @@ where
         trace!(?result, "Matrix multiplication result");
         Ok(result)
     }
+
+    fn phased(&self) -> Option<&dyn PhasedMultiply> {
+        Some(self)
+    }
+}
+
+/// Times a kernel that isn't a matrix multiplication, such as the microbenchmarks in
+/// [`crate::roofline`], returning the fastest of `repetitions` dispatches.
+///
+/// `input` is bound as both `a` and `b`, and `result_len` elements are allocated for
+/// `result`. Only `workgroups` along x are dispatched.
+#[cfg(feature = "roofline")]
+pub(crate) fn time_kernel<T: Gpu + Display>(
+    context: &GpuContext,
+    kernel: &T,
+    dimensions: Dimensions,
+    input: &[f32],
+    result_len: u64,
+    workgroups: u32,
+    repetitions: u32,
+) -> Result<std::time::Duration, MatrixMultiplyError> {
+    let passthrough = check_features(context, kernel)?;
+    let (spirv, constants) = specialized_shader(kernel)?;
+    let pipeline = validated(&context.device, || {
+        let shader = context.shader_module(&spirv, passthrough);
+        create_compute_pipeline(
+            &context.device,
+            &context.pipeline_layout,
+            &shader,
+            kernel.entry_point(),
+            &constants,
+        )
+    })?;
+
+    let input_buffer = create_buffer_init(
+        &context.device,
+        "Microbenchmark Input Buffer",
+        input,
+        wgpu::BufferUsages::STORAGE,
+    );
+    let result_buffer = create_buffer(
+        &context.device,
+        "Microbenchmark Result Buffer",
+        result_len * std::mem::size_of::<f32>() as u64,
+        wgpu::BufferUsages::STORAGE,
+    );
+    let dimensions_buffer = create_buffer_init(
+        &context.device,
+        "Dimensions Buffer",
+        &[dimensions],
+        wgpu::BufferUsages::UNIFORM,
+    );
+    let bind_group = create_bind_group(
+        &context.device,
+        &context.bind_group_layout,
+        &input_buffer,
+        &input_buffer,
+        &result_buffer,
+        &dimensions_buffer,
+    );
+
+    let mut fastest = std::time::Duration::MAX;
+    for _ in 0..repetitions.max(1) {
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Microbenchmark Encoder"),
+            });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Microbenchmark Compute Pass"),
+                timestamp_writes: Default::default(),
+            });
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+
+        // Nothing is read back, so waiting for the queue to drain times the dispatch.
+        let start = std::time::Instant::now();
+        context.queue.submit(Some(encoder.finish()));
+        context.device.poll(wgpu::Maintain::Wait);
+        fastest = fastest.min(start.elapsed());
+    }
+    Ok(fastest)
+}
+
+/// The buffers of one multiplication, kept on the GPU between the steps of
+/// [`PhasedMultiply`].
+pub struct GpuBuffers {
+    m: u32,
+    k: u32,
+    n: u32,
+    a: wgpu::Buffer,
+    b: wgpu::Buffer,
+    result: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    timestamps: Option<Timestamps>,
+}
+
+/// Where the GPU writes the time at the start and end of the compute pass.
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    read: wgpu::Buffer,
+}
+
+impl<T> PhasedMultiply for MatrixMultiplier<T>
+where
+    T: Gpu + GridComputation + Display + Send,
+{
+    fn allocate(&self, m: u32, k: u32, n: u32) -> GpuBuffers {
+        let device = &self.context.device;
+        let size = |elements: u32| elements as u64 * std::mem::size_of::<f32>() as u64;
+        let a = create_buffer(
+            device,
+            "Matrix A Buffer",
+            size(m * k),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let b = create_buffer(
+            device,
+            "Matrix B Buffer",
+            size(k * n),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let result = create_buffer(
+            device,
+            "Result Buffer",
+            size(m * n),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let dimensions = create_buffer_init(
+            device,
+            "Dimensions Buffer",
+            &[Dimensions::new(m, k, n)],
+            wgpu::BufferUsages::UNIFORM,
+        );
+        let bind_group = create_bind_group(
+            device,
+            &self.context.bind_group_layout,
+            &a,
+            &b,
+            &result,
+            &dimensions,
+        );
+        let staging = create_staging_buffer(device, size(m * n));
+
+        let timestamp_size = 2 * std::mem::size_of::<u64>() as u64;
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| Timestamps {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Dispatch Timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: 2,
+                }),
+                resolve: create_buffer(
+                    device,
+                    "Timestamp Resolve Buffer",
+                    timestamp_size,
+                    wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                ),
+                read: create_staging_buffer(device, timestamp_size),
+            });
+
+        GpuBuffers {
+            m,
+            k,
+            n,
+            a,
+            b,
+            result,
+            staging,
+            bind_group,
+            timestamps,
+        }
+    }
+
+    fn upload(
+        &self,
+        buffers: &GpuBuffers,
+        a: &[f32],
+        b: &[f32],
+    ) -> Result<(), MatrixMultiplyError> {
+        let (m, k, n) = (buffers.m, buffers.k, buffers.n);
+        if a.len() != (m * k) as usize || b.len() != (k * n) as usize {
+            return Err(MatrixMultiplyError::MatrixSize(format!(
+                "buffers for {m}x{k}x{n} can't hold matrices of {} and {} elements",
+                a.len(),
+                b.len()
+            )));
+        }
+        self.context
+            .queue
+            .write_buffer(&buffers.a, 0, bytemuck::cast_slice(a));
+        self.context
+            .queue
+            .write_buffer(&buffers.b, 0, bytemuck::cast_slice(b));
+        // Writes are only flushed by a submission.
+        self.context.queue.submit(None);
+        self.context.device.poll(wgpu::Maintain::Wait);
+        Ok(())
+    }
+
+    fn dispatch(
+        &self,
+        buffers: &GpuBuffers,
+    ) -> Result<Option<std::time::Duration>, MatrixMultiplyError> {
+        let mut encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Matrix Multiply Encoder"),
+                });
+        {
+            let timestamp_writes =
+                buffers
+                    .timestamps
+                    .as_ref()
+                    .map(|timestamps| wgpu::ComputePassTimestampWrites {
+                        query_set: &timestamps.query_set,
+                        beginning_of_pass_write_index: Some(0),
+                        end_of_pass_write_index: Some(1),
+                    });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Matrix Multiply Compute Pass"),
+                timestamp_writes,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &buffers.bind_group, &[]);
+            let dispatch_count = self.variant.dispatch_count(buffers.m, buffers.n);
+            compute_pass.dispatch_workgroups(dispatch_count.x, dispatch_count.y, dispatch_count.z);
+        }
+        if let Some(timestamps) = &buffers.timestamps {
+            encoder.resolve_query_set(&timestamps.query_set, 0..2, &timestamps.resolve, 0);
+            encoder.copy_buffer_to_buffer(
+                &timestamps.resolve,
+                0,
+                &timestamps.read,
+                0,
+                timestamps.read.size(),
+            );
+        }
+        self.context.queue.submit(Some(encoder.finish()));
+        self.context.device.poll(wgpu::Maintain::Wait);
+
+        let Some(timestamps) = &buffers.timestamps else {
+            return Ok(None);
+        };
+        let ticks = read_mapped::<u64>(&self.context.device, &timestamps.read)?;
+        let nanoseconds = ticks[1].saturating_sub(ticks[0]) as f64
+            * self.context.queue.get_timestamp_period() as f64;
+        Ok(Some(std::time::Duration::from_nanos(nanoseconds as u64)))
+    }
+
+    fn read_back(&self, buffers: &GpuBuffers) -> Result<Vec<f32>, MatrixMultiplyError> {
+        let mut encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Read Back Encoder"),
+                });
+        encoder.copy_buffer_to_buffer(
+            &buffers.result,
+            0,
+            &buffers.staging,
+            0,
+            buffers.staging.size(),
+        );
+        self.context.queue.submit(Some(encoder.finish()));
+        read_mapped(&self.context.device, &buffers.staging)
+    }
+}
+
+/// Waits for the GPU to finish with `buffer`, then copies it to the CPU.
+fn read_mapped<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<T>, MatrixMultiplyError> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = oneshot::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    block_on(receiver)
+        .map_err(|_| MatrixMultiplyError::GpuDataReceive)?
+        .map_err(|_| MatrixMultiplyError::GpuBufferMapping)?;
+
+    let data = slice.get_mapped_range();
+    let result = bytemuck::cast_slice(&data).to_vec();
+    drop(data);
+    buffer.unmap();
+    Ok(result)
 }
 
 /// Creates a new WGPU instance with specified backends.
-async fn create_instance() -> wgpu::Instance {
-    let backends = wgpu::util::backend_bits_from_env()
-        .unwrap_or(wgpu::Backends::VULKAN | wgpu::Backends::METAL);
+pub(crate) async fn create_instance(options: &DeviceOptions) -> wgpu::Instance {
     wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends,
+        backends: options.backends_or_default(),
         dx12_shader_compiler: wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default(),
         ..Default::default()
     })
 }
 
-/// Requests a suitable GPU adapter based on the instance.
-async fn request_adapter(instance: &wgpu::Instance) -> wgpu::Adapter {
-    instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            force_fallback_adapter: false,
-            compatible_surface: None,
-        })
-        .await
-        .expect("Failed to find appropriate adapter")
+/// Requests the GPU adapter selected by `options`.
+pub(crate) async fn request_adapter(
+    instance: &wgpu::Instance,
+    options: &DeviceOptions,
+) -> Result<wgpu::Adapter, MatrixMultiplyError> {
+    let is_allowed = |adapter: &wgpu::Adapter| {
+        !options.force_fallback_adapter || adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    };
+
+    match &options.adapter {
+        AdapterSelection::Default => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference,
+                force_fallback_adapter: options.force_fallback_adapter,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or(MatrixMultiplyError::GpuAdapterRequest),
+        AdapterSelection::Index(index) => instance
+            .enumerate_adapters(options.backends_or_default())
+            .into_iter()
+            .nth(*index)
+            .filter(is_allowed)
+            .ok_or_else(|| MatrixMultiplyError::GpuAdapterNotFound(format!("index {index}"))),
+        AdapterSelection::Name(name) => {
+            let needle = name.to_lowercase();
+            instance
+                .enumerate_adapters(options.backends_or_default())
+                .into_iter()
+                .filter(is_allowed)
+                .find(|adapter| adapter.get_info().name.to_lowercase().contains(&needle))
+                .ok_or_else(|| MatrixMultiplyError::GpuAdapterNotFound(format!("name {name:?}")))
+        }
+    }
 }
 
 /// Requests the GPU device and queue from the adapter.
-async fn request_device_and_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+pub(crate) async fn request_device_and_queue(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), MatrixMultiplyError> {
     adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("Matrix Multiply Device"),
-                required_features: wgpu::Features::empty(),
+                // Enable whatever the adapter offers that a kernel might need, the
+                // variants that need it are checked in `check_features`.
+                required_features: adapter.features() & OPTIONAL_FEATURES,
                 required_limits: wgpu::Limits::default(),
                 memory_hints: wgpu::MemoryHints::default(),
             },
             None,
         )
         .await
-        .expect("Failed to create device")
+        .map_err(|_| MatrixMultiplyError::GpuDeviceCreation)
 }
 
-/// Compiles and creates the shader module from SPIR-V bytes.
-fn create_shader_module(device: &wgpu::Device, spirv_bytes: &[u8]) -> wgpu::ShaderModule {
+/// Compiles and creates the shader module from SPIR-V words.
+///
+/// With `passthrough` the SPIR-V is handed to the driver as is instead of being
+/// translated by naga, for shaders using features naga can't read.
+pub(crate) fn create_shader_module(
+    device: &wgpu::Device,
+    spirv: &[u32],
+    passthrough: bool,
+) -> wgpu::ShaderModule {
+    if passthrough {
+        // SAFETY: wgpu doesn't validate SPIR-V it passes through. `check_features` only
+        // allows it for the kernels compiled into the binary, which are checked when they
+        // are built (see `gpu_build`), and never for shaders loaded at runtime.
+        return unsafe {
+            device.create_shader_module_spirv(&wgpu::ShaderModuleDescriptorSpirV {
+                label: Some("SPIR-V Shader Module"),
+                source: std::borrow::Cow::Borrowed(spirv),
+            })
+        };
+    }
     device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("SPIR-V Shader Module"),
-        source: wgpu::ShaderSource::SpirV(std::borrow::Cow::Borrowed(&pad_and_cast_spirv(
-            spirv_bytes,
-        ))),
+        source: wgpu::ShaderSource::SpirV(std::borrow::Cow::Borrowed(spirv)),
     })
 }
 
+/// Features that are enabled when the adapter supports them, because some SPIR-V
+/// capabilities need them (see [`required_features`]), or to time dispatches on the GPU.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::SHADER_F16
+    .union(wgpu::Features::SHADER_F64)
+    .union(wgpu::Features::SHADER_I16)
+    .union(wgpu::Features::SHADER_INT64)
+    .union(wgpu::Features::SUBGROUP)
+    .union(wgpu::Features::SPIRV_SHADER_PASSTHROUGH)
+    .union(wgpu::Features::TIMESTAMP_QUERY);
+
+/// The `wgpu` features needed to run a shader compiled with these SPIR-V capabilities
+/// and extensions.
+pub(crate) fn required_features(capabilities: &[&str], extensions: &[&str]) -> wgpu::Features {
+    // naga, which translates SPIR-V for every backend, has no 8 or 16-bit types and can't
+    // read subgroup operations, so shaders using them have to skip it.
+    let passthrough = wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
+    let mut features = wgpu::Features::empty();
+    for capability in capabilities {
+        features |= match *capability {
+            "VulkanMemoryModel" => wgpu::Features::empty(),
+            "Float64" => wgpu::Features::SHADER_F64,
+            "Int64" => wgpu::Features::SHADER_INT64,
+            "Float16" => wgpu::Features::SHADER_F16 | passthrough,
+            "Int16" => wgpu::Features::SHADER_I16 | passthrough,
+            name if name.starts_with("GroupNonUniform") => wgpu::Features::SUBGROUP | passthrough,
+            _ => passthrough,
+        };
+    }
+    for extension in extensions {
+        if !matches!(
+            *extension,
+            "SPV_KHR_storage_buffer_storage_class" | "SPV_KHR_vulkan_memory_model"
+        ) {
+            features |= passthrough;
+        }
+    }
+    features
+}
+
+/// Checks that the device supports everything `variant` needs, returning whether its
+/// shader has to be passed through to the driver.
+fn check_features<T: Gpu + Display>(
+    context: &GpuContext,
+    variant: &T,
+) -> Result<bool, MatrixMultiplyError> {
+    check_available_features(variant, context.device.features(), context.adapter_info())
+}
+
+/// [`check_features`] against the `available` features of `adapter`.
+fn check_available_features<T: Gpu + Display>(
+    variant: &T,
+    available: wgpu::Features,
+    adapter: &impl Display,
+) -> Result<bool, MatrixMultiplyError> {
+    let required = required_features(variant.capabilities(), variant.extensions());
+    let missing = required - available;
+    if !missing.is_empty() {
+        return Err(MatrixMultiplyError::UnsupportedVariant {
+            variant: variant.to_string(),
+            adapter: adapter.to_string(),
+            missing: missing
+                .iter_names()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+                .join(", "),
+        });
+    }
+
+    // A passed through shader reaches the driver unvalidated, so it has to be one that
+    // was checked when it was built, and unspecialized, so only the values compiled into
+    // it can be used.
+    let passthrough = required.contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH);
+    if passthrough && !variant.shader_checked() {
+        return Err(MatrixMultiplyError::UncheckedPassthrough {
+            variant: variant.to_string(),
+        });
+    }
+    if passthrough {
+        let defaults = spec_constant_defaults(variant.compiled_shader());
+        // The workgroup size is written into the shader itself, see `specialized_shader`.
+        let mut constants = variant.pipeline_constants();
+        take_workgroup_size(&mut constants);
+        let mut overridden = constants
+            .into_iter()
+            .filter(|(id, value)| defaults.get(id) != Some(value))
+            .map(|(id, value)| format!("{id} = {value}"))
+            .collect::<Vec<_>>();
+        if !overridden.is_empty() {
+            overridden.sort();
+            return Err(MatrixMultiplyError::UnsupportedPipelineConstants {
+                variant: variant.to_string(),
+                constants: overridden.join(", "),
+            });
+        }
+    }
+    Ok(passthrough)
+}
+
+/// The SPIR-V of a shader and the pipeline constants left to specialize it with.
+pub type SpecializedShader<'a> = (Cow<'a, [u32]>, HashMap<String, f64>);
+
+/// The SPIR-V and pipeline constants to create `variant`'s pipeline with.
+///
+/// `wgpu` can't specialize the workgroup size of a SPIR-V shader, so the
+/// [`spec_constants::WORKGROUP_X`] and [`spec_constants::WORKGROUP_Y`] constants are
+/// taken out and written into the `LocalSize` of the entry point instead. Without them,
+/// the compiled shader is used as is.
+pub fn specialized_shader<T: Gpu + ?Sized>(
+    variant: &T,
+) -> Result<SpecializedShader<'_>, MatrixMultiplyError> {
+    let mut constants = variant.pipeline_constants();
+    let spirv = variant.compiled_shader();
+    let size = take_workgroup_size(&mut constants);
+    if size == [None, None] {
+        return Ok((Cow::Borrowed(spirv), constants));
+    }
+
+    let mut spirv = spirv.to_vec();
+    if !set_local_size(&mut spirv, variant.entry_point(), size) {
+        return Err(MatrixMultiplyError::ShaderCompilation(format!(
+            "{} has no LocalSize to specialize",
+            variant.entry_point()
+        )));
+    }
+    Ok((Cow::Owned(spirv), constants))
+}
+
+/// Removes the workgroup size from `constants`, returning its `x` and `y`.
+fn take_workgroup_size(constants: &mut HashMap<String, f64>) -> [Option<u32>; 2] {
+    [spec_constants::WORKGROUP_X, spec_constants::WORKGROUP_Y]
+        .map(|id| constants.remove(&id.to_string()).map(|value| value as u32))
+}
+
+/// Overwrites the `x` and `y` of the `LocalSize` execution mode of `entry_point`,
+/// returning whether it was found.
+fn set_local_size(spirv: &mut [u32], entry_point: &str, size: [Option<u32>; 2]) -> bool {
+    const OP_ENTRY_POINT: u32 = 15;
+    const OP_EXECUTION_MODE: u32 = 16;
+    const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+    // Every instruction, as the offset of its first operand and its operand count.
+    let mut instructions = Vec::new();
+    // Instructions follow the five word header.
+    let mut offset = 5;
+    while let Some(&first) = spirv.get(offset) {
+        let length = ((first >> 16) as usize).max(1);
+        if offset + length > spirv.len() {
+            break;
+        }
+        instructions.push((first & 0xffff, offset + 1, length - 1));
+        offset += length;
+    }
+
+    let function = instructions.iter().find_map(|&(opcode, start, count)| {
+        // OpEntryPoint <execution model> <function> <name> <interface>...
+        let operands = &spirv[start..start + count];
+        (opcode == OP_ENTRY_POINT
+            && operands.len() > 2
+            && literal_string(&operands[2..]) == entry_point)
+            .then(|| operands[1])
+    });
+    let Some(function) = function else {
+        return false;
+    };
+
+    for &(opcode, start, count) in &instructions {
+        let operands = &mut spirv[start..start + count];
+        if let (OP_EXECUTION_MODE, [target, EXECUTION_MODE_LOCAL_SIZE, x, y, _]) =
+            (opcode, &mut *operands)
+        {
+            if *target == function {
+                *x = size[0].unwrap_or(*x);
+                *y = size[1].unwrap_or(*y);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Decodes a nul-terminated SPIR-V literal string, packed four bytes to a word.
+fn literal_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The default value of every 32-bit specialization constant in `spirv`, keyed by its
+/// ID like [`Gpu::pipeline_constants`].
+fn spec_constant_defaults(spirv: &[u32]) -> HashMap<String, f64> {
+    const OP_TYPE_INT: u32 = 21;
+    const OP_TYPE_FLOAT: u32 = 22;
+    const OP_SPEC_CONSTANT: u32 = 50;
+    const OP_DECORATE: u32 = 71;
+    const DECORATION_SPEC_ID: u32 = 1;
+
+    let mut spec_ids = HashMap::new();
+    let mut types = HashMap::new();
+    let mut constants = Vec::new();
+    // Instructions follow the five word header.
+    let mut offset = 5;
+    while let Some(&first) = spirv.get(offset) {
+        let length = ((first >> 16) as usize).max(1);
+        let Some(operands) = spirv.get(offset + 1..offset + length) else {
+            break;
+        };
+        match (first & 0xffff, operands) {
+            (OP_DECORATE, &[target, DECORATION_SPEC_ID, id, ..]) => {
+                spec_ids.insert(target, id);
+            }
+            (OP_TYPE_INT, &[result, 32, signed]) => {
+                types.insert(result, (OP_TYPE_INT, signed == 1));
+            }
+            (OP_TYPE_FLOAT, &[result, 32, ..]) => {
+                types.insert(result, (OP_TYPE_FLOAT, false));
+            }
+            (OP_SPEC_CONSTANT, &[result_type, result, value]) => {
+                constants.push((result_type, result, value));
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+
+    constants
+        .into_iter()
+        .filter_map(|(result_type, result, value)| {
+            let value = match types.get(&result_type)? {
+                (OP_TYPE_FLOAT, _) => f32::from_bits(value) as f64,
+                (_, true) => value as i32 as f64,
+                (_, false) => value as f64,
+            };
+            Some((spec_ids.get(&result)?.to_string(), value))
+        })
+        .collect()
+}
+
 /// Defines the bind group layout for the compute pipeline.
-fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+pub(crate) fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("Matrix Multiply Bind Group Layout"),
         entries: &[
@@ -289,7 +953,7 @@ fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
 }
 
 /// Sets up the pipeline layout using the bind group layout.
-fn create_pipeline_layout(
+pub(crate) fn create_pipeline_layout(
     device: &wgpu::Device,
     bind_group_layout: &wgpu::BindGroupLayout,
 ) -> wgpu::PipelineLayout {
@@ -305,17 +969,36 @@ fn create_compute_pipeline(
     device: &wgpu::Device,
     pipeline_layout: &wgpu::PipelineLayout,
     shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    constants: &HashMap<String, f64>,
 ) -> wgpu::ComputePipeline {
     device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
         label: Some("Matrix Multiply Pipeline"),
         layout: Some(pipeline_layout),
         module: shader,
-        entry_point: Some(SHADER_ENTRY_POINT),
-        compilation_options: Default::default(),
+        entry_point: Some(entry_point),
+        compilation_options: wgpu::PipelineCompilationOptions {
+            constants,
+            ..Default::default()
+        },
         cache: Default::default(),
     })
 }
 
+/// Runs `create` and turns any validation error it raised into a
+/// [`MatrixMultiplyError::ShaderCompilation`].
+fn validated<R>(
+    device: &wgpu::Device,
+    create: impl FnOnce() -> R,
+) -> Result<R, MatrixMultiplyError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = create();
+    match block_on(device.pop_error_scope()) {
+        Some(error) => Err(MatrixMultiplyError::ShaderCompilation(error.to_string())),
+        None => Ok(result),
+    }
+}
+
 /// Binds the allocated buffers to the shader's bindings.
 fn create_bind_group(
     device: &wgpu::Device,
@@ -388,23 +1071,226 @@ fn create_buffer(
     })
 }
 
-/// Safely casts a byte slice to a `u32` slice, padding with zeros if necessary.
-///
-/// Panics if the byte slice cannot be aligned even after padding.
-fn pad_and_cast_spirv(bytes: &[u8]) -> Vec<u32> {
-    use bytemuck::cast_slice;
-    let mut padded = bytes.to_vec();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_features() {
+        assert_eq!(required_features(&[], &[]), wgpu::Features::empty());
+        assert_eq!(
+            required_features(
+                &["Int64", "VulkanMemoryModel"],
+                &["SPV_KHR_vulkan_memory_model"]
+            ),
+            wgpu::Features::SHADER_INT64
+        );
+        assert_eq!(
+            required_features(&["GroupNonUniformArithmetic"], &[]),
+            wgpu::Features::SUBGROUP | wgpu::Features::SPIRV_SHADER_PASSTHROUGH
+        );
+        assert_eq!(
+            required_features(&[], &["SPV_KHR_8bit_storage"]),
+            wgpu::Features::SPIRV_SHADER_PASSTHROUGH
+        );
+    }
+
+    /// A module holding nothing but a `u32` specialization constant with ID 0 and a
+    /// default of 4, like the tile size of `tiling_1d`.
+    const SPECIALIZED: &[u32] = &[
+        0x0723_0203,
+        0x0001_0500,
+        0,
+        3,
+        0,
+        // OpDecorate %2 SpecId 0
+        (4 << 16) | 71,
+        2,
+        1,
+        0,
+        // %1 = OpTypeInt 32 0
+        (4 << 16) | 21,
+        1,
+        32,
+        0,
+        // %2 = OpSpecConstant %1 4
+        (4 << 16) | 50,
+        1,
+        2,
+        4,
+    ];
+
+    /// A module with a `main` entry point whose `LocalSize` is 16 by 16.
+    const LOCAL_SIZE: &[u32] = &[
+        0x0723_0203,
+        0x0001_0500,
+        0,
+        2,
+        0,
+        // OpEntryPoint GLCompute %1 "main"
+        (5 << 16) | 15,
+        5,
+        1,
+        u32::from_le_bytes(*b"main"),
+        0,
+        // OpExecutionMode %1 LocalSize 16 16 1
+        (6 << 16) | 16,
+        1,
+        17,
+        16,
+        16,
+        1,
+    ];
+
+    /// A kernel whose workgroup size is specialized, like the tiling kernels.
+    struct Resized {
+        x: u32,
+        y: u32,
+    }
+
+    impl Gpu for Resized {
+        fn compiled_shader(&self) -> &[u32] {
+            LOCAL_SIZE
+        }
+
+        fn entry_point(&self) -> &'static str {
+            "main"
+        }
+
+        fn pipeline_constants(&self) -> HashMap<String, f64> {
+            HashMap::from([
+                (spec_constants::TILE_SIZE.to_string(), 2.0),
+                (spec_constants::WORKGROUP_X.to_string(), self.x as f64),
+                (spec_constants::WORKGROUP_Y.to_string(), self.y as f64),
+            ])
+        }
+    }
 
-    // Pad with zeros to make the length a multiple of 4
-    while padded.len() % 4 != 0 {
-        padded.push(0);
+    /// A kernel compiled with capabilities beyond the defaults, as declared with
+    /// `gpu_build::Requirements`, and a tile size that can be specialized.
+    struct HalfPrecision {
+        tile_size: u32,
+        /// Whether the shader counts as compiled into the binary, see
+        /// [`Gpu::shader_checked`].
+        checked: bool,
     }
 
-    // Ensure the starting pointer is aligned to 4 bytes
-    if padded.as_ptr() as usize % std::mem::align_of::<u32>() != 0 {
-        panic!("Shader binary is not 4-byte aligned even after padding.");
+    impl Display for HalfPrecision {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "half_precision")
+        }
+    }
+
+    impl Gpu for HalfPrecision {
+        fn compiled_shader(&self) -> &[u32] {
+            SPECIALIZED
+        }
+
+        fn entry_point(&self) -> &'static str {
+            "matmul_half_precision"
+        }
+
+        fn capabilities(&self) -> &'static [&'static str] {
+            &["Float16", "Int64"]
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["SPV_KHR_16bit_storage"]
+        }
+
+        fn pipeline_constants(&self) -> HashMap<String, f64> {
+            // The workgroup size can be specialized even when passed through.
+            HashMap::from([
+                ("0".to_string(), self.tile_size as f64),
+                (spec_constants::WORKGROUP_X.to_string(), 8.0),
+                (spec_constants::WORKGROUP_Y.to_string(), 32.0),
+            ])
+        }
+
+        fn shader_checked(&self) -> bool {
+            self.checked
+        }
     }
 
-    // Safe to cast since we've ensured alignment and length
-    cast_slice::<u8, u32>(&padded).to_vec()
+    #[test]
+    fn test_check_features() {
+        let adapter = "llvmpipe (Vulkan, Cpu)";
+        let everything = wgpu::Features::SHADER_F16
+            | wgpu::Features::SHADER_INT64
+            | wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
+        let half_precision = HalfPrecision {
+            tile_size: 4,
+            checked: true,
+        };
+        assert!(check_available_features(&half_precision, everything, &adapter).unwrap());
+
+        let error =
+            check_available_features(&half_precision, wgpu::Features::SHADER_INT64, &adapter)
+                .unwrap_err();
+        let MatrixMultiplyError::UnsupportedVariant {
+            variant,
+            adapter: unsupported_adapter,
+            missing,
+        } = error
+        else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(variant, "half_precision");
+        assert_eq!(unsupported_adapter, adapter);
+        assert_eq!(missing, "SHADER_F16, SPIRV_SHADER_PASSTHROUGH");
+    }
+
+    #[test]
+    fn test_passthrough_rejects_specialization() {
+        assert_eq!(
+            spec_constant_defaults(SPECIALIZED),
+            HashMap::from([("0".to_string(), 4.0)])
+        );
+
+        let everything = wgpu::Features::SHADER_F16
+            | wgpu::Features::SHADER_INT64
+            | wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
+        let half_precision = HalfPrecision {
+            tile_size: 2,
+            checked: true,
+        };
+        let error = check_available_features(&half_precision, everything, &"").unwrap_err();
+        let MatrixMultiplyError::UnsupportedPipelineConstants { variant, constants } = error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(variant, "half_precision");
+        assert_eq!(constants, "0 = 2");
+    }
+
+    #[test]
+    fn test_passthrough_rejects_unchecked_shaders() {
+        let everything = wgpu::Features::SHADER_F16
+            | wgpu::Features::SHADER_INT64
+            | wgpu::Features::SPIRV_SHADER_PASSTHROUGH;
+        let loaded = HalfPrecision {
+            tile_size: 4,
+            checked: false,
+        };
+        let error = check_available_features(&loaded, everything, &"").unwrap_err();
+        let MatrixMultiplyError::UncheckedPassthrough { variant } = error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(variant, "half_precision");
+    }
+
+    #[test]
+    fn test_specialize_workgroup() {
+        let (spirv, constants) = specialized_shader(&Resized { x: 8, y: 32 }).unwrap();
+        assert_eq!(spirv[spirv.len() - 3..], [8, 32, 1]);
+        assert_eq!(
+            constants,
+            HashMap::from([(spec_constants::TILE_SIZE.to_string(), 2.0)])
+        );
+
+        let mut spirv = LOCAL_SIZE.to_vec();
+        assert!(set_local_size(&mut spirv, "main", [Some(64), None]));
+        assert_eq!(spirv[spirv.len() - 3..], [64, 16, 1]);
+        assert!(!set_local_size(&mut spirv, "other", [Some(8), Some(8)]));
+        assert_eq!(spirv[spirv.len() - 3..], [64, 16, 1]);
+    }
 }
@@ -0,0 +1,255 @@
+//! Choosing which GPU (adapter) the `wgpu` backend runs on, and sharing one device
+//! between variants.
+
+use crate::backends::wgpu::{
+    create_bind_group_layout, create_instance, create_pipeline_layout, create_shader_module,
+    request_adapter, request_device_and_queue,
+};
+use crate::MatrixMultiplyError;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Which adapter to use when more than one is available.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdapterSelection {
+    /// Let `wgpu` pick based on the power preference.
+    #[default]
+    Default,
+    /// The adapter at this position in [`enumerate_adapters`].
+    Index(usize),
+    /// The first adapter whose name contains this string (case-insensitive).
+    Name(String),
+}
+
+/// Options for finding a GPU and creating a device on it.
+///
+/// ```no_run
+/// use matmul::device::DeviceOptions;
+///
+/// let options = DeviceOptions::new().adapter_name("nvidia");
+/// let multiplier = matmul::tiling_2d::wgpu_with(&options).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DeviceOptions {
+    pub(crate) backends: Option<wgpu::Backends>,
+    pub(crate) power_preference: wgpu::PowerPreference,
+    pub(crate) adapter: AdapterSelection,
+    pub(crate) force_fallback_adapter: bool,
+}
+
+impl Default for DeviceOptions {
+    fn default() -> Self {
+        Self {
+            backends: None,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            adapter: AdapterSelection::Default,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+impl DeviceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts which backends are searched. Defaults to `WGPU_BACKEND` if set,
+    /// otherwise Vulkan and Metal.
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = Some(backends);
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn adapter(mut self, adapter: AdapterSelection) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    pub fn adapter_index(self, index: usize) -> Self {
+        self.adapter(AdapterSelection::Index(index))
+    }
+
+    pub fn adapter_name(self, name: impl Into<String>) -> Self {
+        self.adapter(AdapterSelection::Name(name.into()))
+    }
+
+    /// Only consider the fallback (software) adapter, such as lavapipe or WARP.
+    pub fn force_fallback_adapter(mut self, force: bool) -> Self {
+        self.force_fallback_adapter = force;
+        self
+    }
+
+    pub(crate) fn backends_or_default(&self) -> wgpu::Backends {
+        self.backends.unwrap_or_else(|| {
+            wgpu::util::backend_bits_from_env()
+                .unwrap_or(wgpu::Backends::VULKAN | wgpu::Backends::METAL)
+        })
+    }
+}
+
+/// Information about an adapter that can be selected with [`DeviceOptions`].
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub vendor: u32,
+    pub device: u32,
+    pub device_type: wgpu::DeviceType,
+    pub driver: String,
+    pub driver_info: String,
+    pub backend: wgpu::Backend,
+    pub limits: wgpu::Limits,
+    pub features: wgpu::Features,
+}
+
+impl AdapterInfo {
+    pub(crate) fn new(index: usize, adapter: &wgpu::Adapter) -> Self {
+        let info = adapter.get_info();
+        Self {
+            index,
+            name: info.name,
+            vendor: info.vendor,
+            device: info.device,
+            device_type: info.device_type,
+            driver: info.driver,
+            driver_info: info.driver_info,
+            backend: info.backend,
+            limits: adapter.limits(),
+            features: adapter.features(),
+        }
+    }
+}
+
+impl Display for AdapterInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, {:?}, vendor 0x{:04x}, device 0x{:04x})",
+            self.index, self.name, self.backend, self.device_type, self.vendor, self.device
+        )
+    }
+}
+
+/// Lists the adapters that `options` can select from, in index order.
+pub fn enumerate_adapters(options: &DeviceOptions) -> Vec<AdapterInfo> {
+    let instance = futures::executor::block_on(create_instance(options));
+    instance
+        .enumerate_adapters(options.backends_or_default())
+        .iter()
+        .enumerate()
+        .map(|(index, adapter)| AdapterInfo::new(index, adapter))
+        .collect()
+}
+
+/// A GPU device and queue that any number of variants can share.
+///
+/// Creating the instance, adapter and device is the slow part of setting up the `wgpu`
+/// backend, so create one context and build each variant's multiplier from it:
+///
+/// ```no_run
+/// use matmul::device::{DeviceOptions, GpuContext};
+/// use std::sync::Arc;
+///
+/// let context = futures::executor::block_on(GpuContext::new(&DeviceOptions::default()));
+/// let context = Arc::new(context.unwrap());
+/// let isomorphic = matmul::isomorphic::wgpu_from_context(&context).unwrap();
+/// let tiling_2d = matmul::tiling_2d::wgpu_from_context(&context).unwrap();
+/// ```
+pub struct GpuContext {
+    pub(crate) device: wgpu::Device,
+    pub(crate) queue: wgpu::Queue,
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) pipeline_layout: wgpu::PipelineLayout,
+    /// Shader modules that have already been created, keyed by the length and hash of
+    /// their SPIR-V. All kernels are in one module, so this usually holds one entry.
+    shader_modules: Mutex<HashMap<(usize, u64), Arc<wgpu::ShaderModule>>>,
+    adapter_info: AdapterInfo,
+}
+
+impl GpuContext {
+    /// Finds the adapter chosen by `options` and creates a device on it.
+    pub async fn new(options: &DeviceOptions) -> Result<Self, MatrixMultiplyError> {
+        // Set up WGPU to talk to the system's GPUs and manage rendering or compute tasks.
+        let instance = create_instance(options).await;
+
+        // Find a GPU.
+        let adapter = request_adapter(&instance, options).await?;
+        let index = instance
+            .enumerate_adapters(options.backends_or_default())
+            .iter()
+            .position(|candidate| candidate.get_info() == adapter.get_info())
+            .unwrap_or_default();
+        let adapter_info = AdapterInfo::new(index, &adapter);
+
+        // Get access to the GPU and its command system for sending tasks.
+        let (device, queue) = request_device_and_queue(&adapter).await?;
+
+        // Define how the GPU will connect data and resources to the GPU program. Every
+        // variant uses the same bindings, so this is shared by all of their pipelines.
+        let bind_group_layout = create_bind_group_layout(&device);
+
+        // Specify how the GPU pipeline organizes its resources and GPU programs.
+        let pipeline_layout = create_pipeline_layout(&device, &bind_group_layout);
+
+        Ok(Self {
+            device,
+            queue,
+            bind_group_layout,
+            pipeline_layout,
+            shader_modules: Mutex::default(),
+            adapter_info,
+        })
+    }
+
+    /// Returns the shader module for `spirv`, creating it the first time it is used.
+    pub(crate) fn shader_module(
+        &self,
+        spirv: &[u32],
+        passthrough: bool,
+    ) -> Arc<wgpu::ShaderModule> {
+        // SPIR-V loaded at runtime can't be told apart by its address, so hash it. This is
+        // only done when a multiplier is created.
+        let mut hasher = DefaultHasher::new();
+        spirv.hash(&mut hasher);
+        let key = (spirv.len(), hasher.finish());
+        let mut shader_modules = self
+            .shader_modules
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        shader_modules
+            .entry(key)
+            .or_insert_with(|| Arc::new(create_shader_module(&self.device, spirv, passthrough)))
+            .clone()
+    }
+
+    /// Forgets the shader modules created so far, so the next multiplier created pays
+    /// for creating its module again, as it would on a new context.
+    pub fn clear_shader_modules(&self) {
+        self.shader_modules
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// The adapter the device was created on.
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+}
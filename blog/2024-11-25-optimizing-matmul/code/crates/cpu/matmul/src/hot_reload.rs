@@ -0,0 +1,304 @@
+//! Loading kernels from SPIR-V files at runtime, so a kernel can be edited and rebuilt
+//! without rebuilding the program that runs it.
+//!
+//! ```no_run
+//! use matmul::hot_reload::{self, SpirvFile};
+//! use matmul::variants;
+//!
+//! let variant = SpirvFile::new("target/kernels.spv", variants::Tiling2d::default())?;
+//! let mut multiplier = hot_reload::wgpu(variant)?;
+//! loop {
+//!     if let Err(error) = multiplier.reload_if_changed() {
+//!         eprintln!("{error}");
+//!     }
+//!     # break;
+//! }
+//! # Ok::<(), matmul::MatrixMultiplyError>(())
+//! ```
+
+use crate::backends::wgpu::MatrixMultiplier;
+use crate::device::{DeviceOptions, GpuContext};
+use crate::{Gpu, GridComputation, MatrixMultiplyError};
+use glam::UVec3;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The first word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// A variant whose shader is read from a file instead of the one compiled into the
+/// binary.
+///
+/// Everything but the shader itself, like the entry point and how work is dispatched,
+/// comes from the wrapped variant. The file is watched, and `reload_if_changed` on the
+/// multiplier running it picks up changes to it.
+///
+/// The file isn't checked like the kernels compiled into the binary, so variants whose
+/// shader has to be passed through to the driver without validation can't be loaded
+/// this way, see [`Gpu::shader_checked`].
+pub struct SpirvFile<T> {
+    path: PathBuf,
+    variant: T,
+    spirv: Vec<u32>,
+    /// The shader `spirv` replaced, until the new one is known to compile.
+    previous: Option<Vec<u32>>,
+    changed: Arc<AtomicBool>,
+    _watcher: RecommendedWatcher,
+}
+
+impl<T> SpirvFile<T> {
+    /// Reads the SPIR-V in `path` and starts watching it for changes.
+    pub fn new(path: impl Into<PathBuf>, variant: T) -> Result<Self, MatrixMultiplyError> {
+        let path = path.into();
+        let spirv = read_spirv(&path)?;
+        let changed = Arc::new(AtomicBool::new(false));
+        let watcher = watch(&path, changed.clone())?;
+
+        Ok(Self {
+            path,
+            variant,
+            spirv,
+            previous: None,
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<T: Display> Display for SpirvFile<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.variant, self.path.display())
+    }
+}
+
+impl<T: Gpu> Gpu for SpirvFile<T> {
+    fn compiled_shader(&self) -> &[u32] {
+        &self.spirv
+    }
+
+    fn entry_point(&self) -> &'static str {
+        self.variant.entry_point()
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        self.variant.capabilities()
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.variant.extensions()
+    }
+
+    fn pipeline_constants(&self) -> HashMap<String, f64> {
+        self.variant.pipeline_constants()
+    }
+
+    fn shader_changed(&self) -> bool {
+        self.changed.load(Ordering::Acquire)
+    }
+
+    fn reload_shader(&mut self) -> Result<(), MatrixMultiplyError> {
+        // Cleared first so a write that lands while reading is not missed.
+        self.changed.store(false, Ordering::Release);
+        let spirv = read_spirv(&self.path)?;
+        self.previous = Some(std::mem::replace(&mut self.spirv, spirv));
+        Ok(())
+    }
+
+    fn revert_shader(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            self.spirv = previous;
+        }
+    }
+    fn shader_checked(&self) -> bool {
+        false
+    }
+}
+
+impl<T: GridComputation> GridComputation for SpirvFile<T> {
+    fn workgroup(&self) -> UVec3 {
+        self.variant.workgroup()
+    }
+
+    fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+        self.variant.dispatch_count(m, n)
+    }
+
+    fn tile(&self) -> (u32, u32) {
+        self.variant.tile()
+    }
+}
+
+pub fn wgpu<T>(variant: SpirvFile<T>) -> Result<MatrixMultiplier<SpirvFile<T>>, MatrixMultiplyError>
+where
+    T: Gpu + GridComputation + Display + Send,
+{
+    futures::executor::block_on(MatrixMultiplier::with_options(
+        variant,
+        &DeviceOptions::default(),
+    ))
+}
+
+pub fn wgpu_from_context<T>(
+    context: &Arc<GpuContext>,
+    variant: SpirvFile<T>,
+) -> Result<MatrixMultiplier<SpirvFile<T>>, MatrixMultiplyError>
+where
+    T: Gpu + GridComputation + Display + Send,
+{
+    MatrixMultiplier::from_context(context.clone(), variant)
+}
+
+/// Reads a SPIR-V module, checking that it at least looks like one.
+fn read_spirv(path: &Path) -> Result<Vec<u32>, MatrixMultiplyError> {
+    let bytes = std::fs::read(path)
+        .map_err(|error| MatrixMultiplyError::ShaderLoad(format!("{}: {error}", path.display())))?;
+    if bytes.is_empty() || bytes.len() % 4 != 0 {
+        return Err(MatrixMultiplyError::ShaderLoad(format!(
+            "{}: {} bytes is not a whole number of 32-bit words",
+            path.display(),
+            bytes.len()
+        )));
+    }
+
+    let spirv = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect::<Vec<_>>();
+    if spirv[0] != SPIRV_MAGIC {
+        return Err(MatrixMultiplyError::ShaderLoad(format!(
+            "{}: not a SPIR-V module",
+            path.display()
+        )));
+    }
+    Ok(spirv)
+}
+
+/// Sets `changed` whenever `path` is written, created or removed.
+fn watch(path: &Path, changed: Arc<AtomicBool>) -> Result<RecommendedWatcher, MatrixMultiplyError> {
+    let watch_error = |error: notify::Error| {
+        MatrixMultiplyError::ShaderLoad(format!("{}: {error}", path.display()))
+    };
+
+    // Build tools often replace the file rather than writing to it, which a watch on the
+    // file itself would not survive, so watch its directory instead.
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event
+            .paths
+            .iter()
+            .any(|changed| changed.file_name() == file_name.as_deref())
+        {
+            changed.store(true, Ordering::Release);
+        }
+    })
+    .map_err(watch_error)?;
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file in a directory of its own, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let directory = std::env::temp_dir()
+                .join(format!("matmul-hot-reload-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&directory).unwrap();
+            let path = directory.join("kernel.spv");
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    struct Kernel;
+
+    impl Gpu for Kernel {
+        fn compiled_shader(&self) -> &[u32] {
+            &[]
+        }
+
+        fn entry_point(&self) -> &'static str {
+            "matmul"
+        }
+    }
+
+    fn module(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_rejects_files_that_are_not_spirv() {
+        let missing = std::env::temp_dir().join("matmul-hot-reload-missing.spv");
+        assert!(matches!(
+            read_spirv(&missing),
+            Err(MatrixMultiplyError::ShaderLoad(_))
+        ));
+
+        let truncated = TempFile::new("truncated", &[0x03, 0x02, 0x23]);
+        assert!(matches!(
+            read_spirv(&truncated.0),
+            Err(MatrixMultiplyError::ShaderLoad(_))
+        ));
+
+        let text = TempFile::new("text", b"fn matmul() {}\n\n\n\n");
+        assert!(matches!(
+            read_spirv(&text.0),
+            Err(MatrixMultiplyError::ShaderLoad(_))
+        ));
+    }
+
+    #[test]
+    fn test_reload_reads_the_file_again() {
+        let file = TempFile::new("reload", &module(&[SPIRV_MAGIC, 1]));
+        let mut variant = SpirvFile::new(&file.0, Kernel).unwrap();
+        assert_eq!(variant.spirv, [SPIRV_MAGIC, 1]);
+
+        std::fs::write(&file.0, module(&[SPIRV_MAGIC, 2])).unwrap();
+        variant.reload_shader().unwrap();
+        assert_eq!(variant.spirv, [SPIRV_MAGIC, 2]);
+
+        // A broken file leaves the last good shader in place.
+        std::fs::write(&file.0, b"oops").unwrap();
+        assert!(variant.reload_shader().is_err());
+        assert_eq!(variant.spirv, [SPIRV_MAGIC, 2]);
+
+        // So does one that loads but doesn't compile.
+        std::fs::write(&file.0, module(&[SPIRV_MAGIC, 3])).unwrap();
+        variant.reload_shader().unwrap();
+        variant.revert_shader();
+        assert_eq!(variant.spirv, [SPIRV_MAGIC, 2]);
+    }
+}
@@ -0,0 +1,271 @@
+//! Picks the CPU or the GPU for each multiplication based on its size.
+//!
+//! Small multiplications are dominated by the fixed cost of talking to the GPU, so they
+//! run faster on the CPU. [`HybridMatrixMultiplier`] estimates the time on each backend
+//! with a [`CostModel`] and routes each call to the cheapest one.
+
+use crate::device::{DeviceOptions, GpuContext};
+use crate::registry::{self, Backend, VariantInfo};
+use crate::{DynMatrixMultiply, MatrixMultiplyError};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use tracing::{trace, warn};
+
+/// Rough performance numbers used to estimate how long a multiplication takes on each
+/// backend. The defaults are conservative guesses for a desktop machine; measure your
+/// own hardware for better routing.
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    /// Fixed cost of submitting work to the GPU and reading the result back, in
+    /// seconds.
+    pub gpu_overhead: f64,
+    /// Sustained floating point operations per second on the GPU.
+    pub gpu_flops: f64,
+    /// Bytes per second copied between the CPU and the GPU.
+    pub transfer_bandwidth: f64,
+    /// Fixed cost of spreading work across threads, in seconds.
+    pub cpu_multi_overhead: f64,
+    /// Floating point operations per second on one CPU thread.
+    pub cpu_single_flops: f64,
+    /// Floating point operations per second using every CPU thread.
+    pub cpu_multi_flops: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        let cpu_single_flops = 1e9;
+        Self {
+            gpu_overhead: 500e-6,
+            gpu_flops: 200e9,
+            transfer_bandwidth: 4e9,
+            cpu_multi_overhead: 50e-6,
+            cpu_single_flops,
+            cpu_multi_flops: cpu_single_flops * rayon::current_num_threads() as f64,
+        }
+    }
+}
+
+impl CostModel {
+    /// The number of floating point operations in an `m x k` by `k x n` multiplication.
+    pub fn flops(m: u32, k: u32, n: u32) -> f64 {
+        2.0 * m as f64 * k as f64 * n as f64
+    }
+
+    /// The number of bytes copied to and from the GPU: both inputs and the result.
+    pub fn transfer_bytes(m: u32, k: u32, n: u32) -> f64 {
+        let elements = m as f64 * k as f64 + k as f64 * n as f64 + m as f64 * n as f64;
+        elements * std::mem::size_of::<f32>() as f64
+    }
+
+    /// Estimated time in seconds to run the multiplication on `backend`.
+    pub fn estimate(&self, backend: Backend, m: u32, k: u32, n: u32) -> f64 {
+        let flops = Self::flops(m, k, n);
+        match backend {
+            Backend::Wgpu => {
+                self.gpu_overhead
+                    + Self::transfer_bytes(m, k, n) / self.transfer_bandwidth
+                    + flops / self.gpu_flops
+            }
+            Backend::CpuSingle => flops / self.cpu_single_flops,
+            Backend::CpuMulti => self.cpu_multi_overhead + flops / self.cpu_multi_flops,
+        }
+    }
+
+    /// The backend with the lowest estimated time among `available`.
+    pub fn choose(&self, available: &[Backend], m: u32, k: u32, n: u32) -> Option<Backend> {
+        available.iter().copied().min_by(|a, b| {
+            self.estimate(*a, m, k, n)
+                .total_cmp(&self.estimate(*b, m, k, n))
+        })
+    }
+}
+
+/// Builds a [`HybridMatrixMultiplier`].
+pub struct HybridMatrixMultiplierBuilder {
+    cpu_variant: String,
+    gpu_variant: Option<String>,
+    context: Option<Arc<GpuContext>>,
+    device_options: DeviceOptions,
+    cost_model: CostModel,
+}
+
+impl HybridMatrixMultiplierBuilder {
+    /// The variant run on the CPU backends. Defaults to `isomorphic`.
+    pub fn cpu_variant(mut self, name: impl Into<String>) -> Self {
+        self.cpu_variant = name.into();
+        self
+    }
+
+    /// The variant run on the GPU. Defaults to `tiling_2d`.
+    pub fn gpu_variant(mut self, name: impl Into<String>) -> Self {
+        self.gpu_variant = Some(name.into());
+        self
+    }
+
+    /// Never use the GPU.
+    pub fn cpu_only(mut self) -> Self {
+        self.gpu_variant = None;
+        self
+    }
+
+    /// Runs the GPU variant on an existing device.
+    pub fn context(mut self, context: Arc<GpuContext>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// How to find a GPU when no context is given.
+    pub fn device_options(mut self, options: DeviceOptions) -> Self {
+        self.device_options = options;
+        self
+    }
+
+    pub fn cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+
+    /// Creates the multiplier. If the GPU cannot be initialized the multiplier still
+    /// works, it just always runs on the CPU.
+    pub fn build(self) -> Result<HybridMatrixMultiplier, MatrixMultiplyError> {
+        let single = registry::find(&format!("{}:{}", self.cpu_variant, Backend::CpuSingle))?;
+        let multi = registry::find(&format!("{}:{}", self.cpu_variant, Backend::CpuMulti))?;
+
+        let gpu = match &self.gpu_variant {
+            Some(name) => {
+                let info = registry::find(&format!("{}:{}", name, Backend::Wgpu))?;
+                match self.create_gpu(&info) {
+                    Ok(multiplier) => Some((info, multiplier)),
+                    Err(error) => {
+                        warn!(%error, "GPU unavailable, falling back to the CPU");
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        Ok(HybridMatrixMultiplier {
+            single: single.create(None)?,
+            multi: multi.create(None)?,
+            gpu,
+            cost_model: self.cost_model,
+        })
+    }
+
+    fn create_gpu(
+        &self,
+        info: &VariantInfo,
+    ) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError> {
+        let context = match &self.context {
+            Some(context) => context.clone(),
+            None => Arc::new(futures::executor::block_on(GpuContext::new(
+                &self.device_options,
+            ))?),
+        };
+        info.create(Some(&context))
+    }
+}
+
+/// Multiplies on whichever of the single-threaded CPU, multi-threaded CPU or GPU
+/// backends the [`CostModel`] expects to be fastest.
+pub struct HybridMatrixMultiplier {
+    single: Box<dyn DynMatrixMultiply>,
+    multi: Box<dyn DynMatrixMultiply>,
+    gpu: Option<(VariantInfo, Box<dyn DynMatrixMultiply>)>,
+    cost_model: CostModel,
+}
+
+impl HybridMatrixMultiplier {
+    pub fn builder() -> HybridMatrixMultiplierBuilder {
+        HybridMatrixMultiplierBuilder {
+            cpu_variant: "isomorphic".to_string(),
+            gpu_variant: Some("tiling_2d".to_string()),
+            context: None,
+            device_options: DeviceOptions::default(),
+            cost_model: CostModel::default(),
+        }
+    }
+
+    /// Whether the GPU was initialized and can be routed to.
+    pub fn has_gpu(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    /// The backend a multiplication of this size will run on.
+    pub fn backend_for(&self, m: u32, k: u32, n: u32) -> Backend {
+        let mut available = vec![Backend::CpuSingle, Backend::CpuMulti];
+        if let Some((info, _)) = &self.gpu {
+            if info.supports(m, k, n) {
+                available.push(Backend::Wgpu);
+            }
+        }
+        self.cost_model
+            .choose(&available, m, k, n)
+            .unwrap_or(Backend::CpuMulti)
+    }
+}
+
+impl Display for HybridMatrixMultiplier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "hybrid")
+    }
+}
+
+impl DynMatrixMultiply for HybridMatrixMultiplier {
+    fn multiply(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<Vec<f32>, MatrixMultiplyError> {
+        let multiplier = match (self.backend_for(m, k, n), &self.gpu) {
+            (Backend::Wgpu, Some((_, gpu))) => gpu,
+            (Backend::CpuSingle, _) => &self.single,
+            _ => &self.multi,
+        };
+        trace!(%multiplier, m, k, n, "Routing multiplication");
+        multiplier.multiply(a, b, m, k, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_model_routes_by_size() {
+        let model = CostModel {
+            cpu_multi_flops: 8e9,
+            ..Default::default()
+        };
+        let all = Backend::ALL;
+
+        assert_eq!(model.choose(&all, 2, 2, 2), Some(Backend::CpuSingle));
+        assert_eq!(model.choose(&all, 4096, 4096, 4096), Some(Backend::Wgpu));
+        assert_eq!(
+            model.choose(&[Backend::CpuSingle, Backend::CpuMulti], 4096, 4096, 4096),
+            Some(Backend::CpuMulti)
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "isomorphic", feature = "tiling_2d"))]
+    fn test_falls_back_to_cpu_when_gpu_fails() {
+        let multiplier = HybridMatrixMultiplier::builder()
+            .device_options(DeviceOptions::new().adapter_name("no such adapter"))
+            .build()
+            .expect("Failed to create");
+        assert!(!multiplier.has_gpu());
+        assert_ne!(multiplier.backend_for(4096, 4096, 4096), Backend::Wgpu);
+
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+    }
+}
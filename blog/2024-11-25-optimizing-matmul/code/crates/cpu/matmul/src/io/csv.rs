@@ -0,0 +1,77 @@
+//! Comma-separated values, one matrix row per line, without a header.
+
+use super::{dimension, parse_number, stream_error, Matrix};
+use crate::MatrixMultiplyError;
+use std::io::{BufRead, Write};
+
+/// Reads a matrix with one row per line. Blank lines are skipped and every row must have
+/// the same number of values.
+pub fn read_csv(reader: impl BufRead) -> Result<Matrix, MatrixMultiplyError> {
+    let mut rows = 0;
+    let mut cols = None;
+    let mut data = Vec::new();
+    for (index, text) in reader.lines().enumerate() {
+        let (line, text) = (index + 1, text.map_err(stream_error)?);
+        if text.trim().is_empty() {
+            continue;
+        }
+        let before = data.len();
+        for value in text.split(',') {
+            data.push(parse_number(value, line)?);
+        }
+        let row_length = data.len() - before;
+        match cols {
+            None => cols = Some(row_length),
+            Some(cols) if cols != row_length => {
+                return Err(error(
+                    line,
+                    format!("expected {cols} values, found {row_length}"),
+                ))
+            }
+            Some(_) => {}
+        }
+        rows += 1;
+    }
+    Matrix::new(dimension(rows)?, dimension(cols.unwrap_or(0))?, data)
+}
+
+/// Writes `matrix` with one row per line.
+pub fn write_csv(mut writer: impl Write, matrix: &Matrix) -> Result<(), MatrixMultiplyError> {
+    if matrix.cols == 0 {
+        return Ok(());
+    }
+    for row in matrix.data.chunks(matrix.cols as usize) {
+        let row = row
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(writer, "{row}").map_err(stream_error)?;
+    }
+    Ok(())
+}
+
+fn error(line: usize, message: impl std::fmt::Display) -> MatrixMultiplyError {
+    MatrixMultiplyError::MatrixIo(format!("invalid CSV file, line {line}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv() {
+        let matrix = read_csv(&b"1, 2.5,-3\n\n4e2,5,6\n"[..]).unwrap();
+        assert_eq!(
+            matrix,
+            Matrix::new(2, 3, vec![1.0, 2.5, -3.0, 400.0, 5.0, 6.0]).unwrap()
+        );
+
+        let mut written = Vec::new();
+        write_csv(&mut written, &matrix).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), "1,2.5,-3\n400,5,6\n");
+
+        assert!(read_csv(&b"1,2\n3\n"[..]).is_err());
+        assert!(read_csv(&b"1,x\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! The MatrixMarket exchange format written by `scipy.io.mmwrite`, described in
+//! <https://math.nist.gov/MatrixMarket/formats.html>.
+
+use super::{dimension, elements, parse_number, stream_error, Matrix};
+use crate::MatrixMultiplyError;
+use std::io::{BufRead, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Layout {
+    /// Every element, column by column.
+    Array,
+    /// `row col value` for each nonzero element, counting from 1.
+    Coordinate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Symmetry {
+    General,
+    /// Only the lower triangle is stored, mirrored into the upper one.
+    Symmetric,
+    /// Only the part below the diagonal is stored, mirrored and negated.
+    SkewSymmetric,
+}
+
+/// The most elements [`read_matrix_market`] expands a sparse (`coordinate`) file to,
+/// 1 GiB of `f32`s. A sparse file can describe a matrix far larger than itself, so
+/// without a limit a few bytes could ask for any amount of memory.
+pub const DEFAULT_SPARSE_LIMIT: usize = 1 << 28;
+
+/// Reads a `real`, `integer` or `pattern` matrix, dense or sparse, which may be stored
+/// as symmetric or skew-symmetric. Sparse matrices are limited to
+/// [`DEFAULT_SPARSE_LIMIT`] elements, see [`read_matrix_market_with_limit`].
+pub fn read_matrix_market(reader: impl BufRead) -> Result<Matrix, MatrixMultiplyError> {
+    read_matrix_market_with_limit(reader, DEFAULT_SPARSE_LIMIT)
+}
+
+/// Like [`read_matrix_market`], but fails on a sparse matrix with more than
+/// `sparse_limit` elements once it is made dense.
+pub fn read_matrix_market_with_limit(
+    reader: impl BufRead,
+    sparse_limit: usize,
+) -> Result<Matrix, MatrixMultiplyError> {
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+
+    let (_, banner) = lines.next().ok_or_else(|| error(1, "the file is empty"))?;
+    let banner = banner.map_err(stream_error)?.to_lowercase();
+    let (layout, pattern, symmetry) = match banner.split_whitespace().collect::<Vec<_>>()[..] {
+        ["%%matrixmarket", "matrix", layout, field, symmetry] => {
+            let layout = match layout {
+                "array" => Layout::Array,
+                "coordinate" => Layout::Coordinate,
+                _ => return Err(error(1, format!("unsupported layout {layout}"))),
+            };
+            let pattern = match field {
+                "real" | "integer" | "double" => false,
+                "pattern" if layout == Layout::Coordinate => true,
+                _ => return Err(error(1, format!("unsupported {layout:?} field {field}"))),
+            };
+            let symmetry = match symmetry {
+                "general" => Symmetry::General,
+                "symmetric" => Symmetry::Symmetric,
+                "skew-symmetric" => Symmetry::SkewSymmetric,
+                _ => return Err(error(1, format!("unsupported symmetry {symmetry}"))),
+            };
+            (layout, pattern, symmetry)
+        }
+        _ => return Err(error(1, "expected a `%%MatrixMarket matrix` banner")),
+    };
+
+    // Everything after the banner is numbers, with comments and blank lines skipped.
+    let mut entries = Vec::new();
+    for (line, text) in lines {
+        let text = text.map_err(stream_error)?;
+        let text = text.trim();
+        if !text.is_empty() && !text.starts_with('%') {
+            entries.push((line, text.to_string()));
+        }
+    }
+    let mut entries = entries.into_iter();
+
+    let (size_line, size) = entries
+        .next()
+        .ok_or_else(|| error(1, "the size line is missing"))?;
+    let size = size
+        .split_whitespace()
+        .map(|number| parse_number::<usize>(number, size_line))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (rows, cols, nonzeros) = match (layout, &size[..]) {
+        (Layout::Array, &[rows, cols]) => (rows, cols, None),
+        (Layout::Coordinate, &[rows, cols, nonzeros]) => (rows, cols, Some(nonzeros)),
+        _ => return Err(error(size_line, "unexpected number of sizes")),
+    };
+    if symmetry != Symmetry::General && rows != cols {
+        return Err(error(
+            size_line,
+            format!("a {rows}x{cols} matrix can't be symmetric"),
+        ));
+    }
+    let shape = (dimension(rows)?, dimension(cols)?);
+    let dense = elements(shape.0, shape.1)?;
+    let expected = nonzeros.unwrap_or_else(|| stored_elements(rows, cols, symmetry));
+    // Every stored element is on a line of its own, so a size line that is too large
+    // fails before anything is allocated for it.
+    if entries.len() < expected {
+        return Err(error(
+            size_line,
+            format!("expected {expected} elements, found {}", entries.len()),
+        ));
+    }
+    if layout == Layout::Coordinate && dense > sparse_limit {
+        return Err(error(
+            size_line,
+            format!(
+                "a sparse {rows}x{cols} matrix is larger than the limit of {sparse_limit} elements"
+            ),
+        ));
+    }
+
+    // Built column by column, like the array layout, then transposed.
+    let mut data = Vec::new();
+    data.try_reserve_exact(dense)
+        .map_err(|_| error(size_line, format!("no memory for a {rows}x{cols} matrix")))?;
+    data.resize(dense, 0.0f32);
+    let mut stored = 0;
+    let mut array_positions = stored_positions(rows, cols, symmetry);
+    for (line, entry) in entries {
+        let fields = entry.split_whitespace().collect::<Vec<_>>();
+        let (row, col, value) = match (layout, &fields[..]) {
+            (Layout::Array, &[value]) => {
+                let (row, col) = array_positions
+                    .next()
+                    .ok_or_else(|| error(line, "more elements than the size line says"))?;
+                (row, col, parse_number(value, line)?)
+            }
+            (Layout::Coordinate, &[row, col, ref value @ ..]) => {
+                let value = match (pattern, value) {
+                    (true, []) => 1.0,
+                    (false, &[value]) => parse_number(value, line)?,
+                    _ => return Err(error(line, "unexpected number of fields")),
+                };
+                let (row, col) = (
+                    parse_number::<usize>(row, line)?,
+                    parse_number::<usize>(col, line)?,
+                );
+                if !(1..=rows).contains(&row) || !(1..=cols).contains(&col) {
+                    return Err(error(line, format!("({row}, {col}) is out of bounds")));
+                }
+                (row - 1, col - 1, value)
+            }
+            _ => return Err(error(line, "unexpected number of fields")),
+        };
+        data[col * rows + row] = value;
+        match symmetry {
+            Symmetry::General => {}
+            Symmetry::Symmetric => data[row * rows + col] = value,
+            Symmetry::SkewSymmetric => data[row * rows + col] = -value,
+        }
+        stored += 1;
+    }
+    if stored != expected {
+        return Err(error(
+            size_line,
+            format!("expected {expected} elements, found {stored}"),
+        ));
+    }
+
+    Matrix::from_column_major(shape.0, shape.1, data)
+}
+
+/// Writes `matrix` as a dense, general `real` array.
+pub fn write_matrix_market(
+    mut writer: impl Write,
+    matrix: &Matrix,
+) -> Result<(), MatrixMultiplyError> {
+    writeln!(writer, "%%MatrixMarket matrix array real general").map_err(stream_error)?;
+    writeln!(writer, "{} {}", matrix.rows, matrix.cols).map_err(stream_error)?;
+    for value in matrix.transpose().data {
+        writeln!(writer, "{value:e}").map_err(stream_error)?;
+    }
+    Ok(())
+}
+
+/// How many elements an array with `symmetry` stores.
+fn stored_elements(rows: usize, cols: usize, symmetry: Symmetry) -> usize {
+    match symmetry {
+        Symmetry::General => rows * cols,
+        Symmetry::Symmetric => rows * (rows + 1) / 2,
+        Symmetry::SkewSymmetric => rows * rows.saturating_sub(1) / 2,
+    }
+}
+
+/// The `(row, col)` of each element an array with `symmetry` stores, in order.
+fn stored_positions(
+    rows: usize,
+    cols: usize,
+    symmetry: Symmetry,
+) -> impl Iterator<Item = (usize, usize)> {
+    (0..cols).flat_map(move |col| {
+        let first_row = match symmetry {
+            Symmetry::General => 0,
+            Symmetry::Symmetric => col,
+            Symmetry::SkewSymmetric => col + 1,
+        };
+        (first_row..rows).map(move |row| (row, col))
+    })
+}
+
+fn error(line: usize, message: impl std::fmt::Display) -> MatrixMultiplyError {
+    MatrixMultiplyError::MatrixIo(format!("invalid MatrixMarket file, line {line}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Result<Matrix, MatrixMultiplyError> {
+        read_matrix_market(text.as_bytes())
+    }
+
+    #[test]
+    fn test_array() {
+        let matrix =
+            read("%%MatrixMarket matrix array real general\n% a comment\n2 3\n1\n4\n2\n5\n3\n6\n")
+                .unwrap();
+        assert_eq!(
+            matrix,
+            Matrix::new(2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap()
+        );
+
+        let mut written = Vec::new();
+        write_matrix_market(&mut written, &matrix).unwrap();
+        assert_eq!(read_matrix_market(&written[..]).unwrap(), matrix);
+    }
+
+    #[test]
+    fn test_coordinate() {
+        let matrix = read(
+            "%%MatrixMarket matrix coordinate real symmetric\n3 3 3\n1 1 2.0\n3 1 -1\n2 2 4e0\n",
+        )
+        .unwrap();
+        #[rustfmt::skip]
+        assert_eq!(matrix.data, [
+            2.0, 0.0, -1.0,
+            0.0, 4.0, 0.0,
+            -1.0, 0.0, 0.0,
+        ]);
+
+        let matrix =
+            read("%%MatrixMarket matrix coordinate pattern skew-symmetric\n2 2 1\n2 1\n").unwrap();
+        assert_eq!(matrix.data, [0.0, -1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for text in [
+            "",
+            "%%MatrixMarket matrix coordinate complex general\n1 1 1\n1 1 1 0\n",
+            "%%MatrixMarket matrix array real general\n2 2\n1\n2\n3\n",
+            "%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1.0\n",
+            "%%MatrixMarket matrix array real symmetric\n2 3\n1\n2\n3\n4\n5\n",
+            "%%MatrixMarket matrix array real general\n65536 65535\n1\n",
+            "%%MatrixMarket matrix coordinate real general\n65536 65536 1\n1 1 1.0\n",
+            "%%MatrixMarket matrix coordinate real general\n65535 65535 1\n1 1 1.0\n",
+            "%%MatrixMarket matrix coordinate real general\n2 2 1000000000\n1 1 1.0\n",
+        ] {
+            assert!(
+                matches!(read(text), Err(MatrixMultiplyError::MatrixIo(_))),
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparse_limit() {
+        let text = "%%MatrixMarket matrix coordinate real general\n2 3 1\n1 1 1.0\n";
+        assert!(read_matrix_market_with_limit(text.as_bytes(), 6).is_ok());
+        assert!(matches!(
+            read_matrix_market_with_limit(text.as_bytes(), 5),
+            Err(MatrixMultiplyError::MatrixIo(_))
+        ));
+    }
+}
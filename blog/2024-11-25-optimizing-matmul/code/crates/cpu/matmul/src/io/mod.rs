@@ -0,0 +1,208 @@
+//! Reading and writing matrices, so variants can be run on real data.
+//!
+//! Three formats are supported, chosen by file extension:
+//!
+//! - `.npy`, as written by `numpy.save`, with `f16`, `f32` or `f64` elements in C or
+//!   Fortran order.
+//! - `.mtx`, MatrixMarket, in dense (`array`) or sparse (`coordinate`) form, as written
+//!   by `scipy.io.mmwrite`.
+//! - `.csv`, one row per line.
+//!
+//! ```no_run
+//! use matmul::io;
+//!
+//! let a = io::read("a.npy")?;
+//! let b = io::read("b.mtx")?;
+//! let multiplier = matmul::registry::create("tiling_2d")?;
+//! let result = multiplier.multiply(&a.data, &b.data, a.rows, a.cols, b.cols)?;
+//! io::write("c.npy", &io::Matrix::new(a.rows, b.cols, result)?)?;
+//! # Ok::<(), matmul::MatrixMultiplyError>(())
+//! ```
+
+mod csv;
+mod matrix_market;
+mod npy;
+
+use crate::MatrixMultiplyError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+pub use self::csv::{read_csv, write_csv};
+pub use self::matrix_market::{
+    read_matrix_market, read_matrix_market_with_limit, write_matrix_market, DEFAULT_SPARSE_LIMIT,
+};
+pub use self::npy::{read_npy, write_npy, Dtype};
+
+/// A dense matrix of `f32`s in row-major order, the layout every variant expects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    pub rows: u32,
+    pub cols: u32,
+    pub data: Vec<f32>,
+}
+
+impl Matrix {
+    /// Fails if `data` doesn't have `rows * cols` elements.
+    pub fn new(rows: u32, cols: u32, data: Vec<f32>) -> Result<Self, MatrixMultiplyError> {
+        if data.len() != rows as usize * cols as usize {
+            return Err(MatrixMultiplyError::MatrixSize(format!(
+                "{} elements can't form a {rows}x{cols} matrix",
+                data.len()
+            )));
+        }
+        Ok(Self { rows, cols, data })
+    }
+
+    /// Builds a matrix from elements in column-major order.
+    pub(crate) fn from_column_major(
+        rows: u32,
+        cols: u32,
+        data: Vec<f32>,
+    ) -> Result<Self, MatrixMultiplyError> {
+        let transposed = Matrix::new(cols, rows, data)?;
+        Ok(transposed.transpose())
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let (rows, cols) = (self.rows as usize, self.cols as usize);
+        let data = (0..cols)
+            .flat_map(|col| (0..rows).map(move |row| self.data[row * cols + col]))
+            .collect();
+        Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+}
+
+/// The file formats matrices can be read from and written to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Npy,
+    MatrixMarket,
+    Csv,
+}
+
+impl Format {
+    /// The format for `path`'s extension.
+    pub fn from_path(path: &Path) -> Result<Self, MatrixMultiplyError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("npy") => Ok(Format::Npy),
+            Some("mtx") => Ok(Format::MatrixMarket),
+            Some("csv") => Ok(Format::Csv),
+            _ => Err(io_error(
+                path,
+                "unknown file type, expected .npy, .mtx or .csv",
+            )),
+        }
+    }
+}
+
+/// Reads a matrix in the format given by the file extension.
+pub fn read(path: impl AsRef<Path>) -> Result<Matrix, MatrixMultiplyError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    let file = BufReader::new(File::open(path).map_err(|error| io_error(path, error))?);
+    let matrix = match format {
+        Format::Npy => read_npy(file),
+        Format::MatrixMarket => read_matrix_market(file),
+        Format::Csv => read_csv(file),
+    };
+    // Errors from the readers don't know which file they came from.
+    matrix.map_err(|error| match error {
+        MatrixMultiplyError::MatrixIo(message) => io_error(path, message),
+        error => error,
+    })
+}
+
+/// Writes a matrix in the format given by the file extension. `.npy` files hold `f32`s.
+pub fn write(path: impl AsRef<Path>, matrix: &Matrix) -> Result<(), MatrixMultiplyError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)?;
+    let mut file = BufWriter::new(File::create(path).map_err(|error| io_error(path, error))?);
+    match format {
+        Format::Npy => write_npy(&mut file, matrix, Dtype::F32),
+        Format::MatrixMarket => write_matrix_market(&mut file, matrix),
+        Format::Csv => write_csv(&mut file, matrix),
+    }?;
+    file.flush().map_err(|error| io_error(path, error))
+}
+
+fn io_error(path: &Path, error: impl std::fmt::Display) -> MatrixMultiplyError {
+    MatrixMultiplyError::MatrixIo(format!("{}: {error}", path.display()))
+}
+
+/// Converts an error from the underlying reader or writer.
+pub(crate) fn stream_error(error: std::io::Error) -> MatrixMultiplyError {
+    MatrixMultiplyError::MatrixIo(error.to_string())
+}
+
+/// Parses one number, saying where it was on failure.
+pub(crate) fn parse_number<T: std::str::FromStr>(
+    text: &str,
+    line: usize,
+) -> Result<T, MatrixMultiplyError> {
+    text.trim().parse().map_err(|_| {
+        MatrixMultiplyError::MatrixIo(format!("line {line}: `{}` is not a number", text.trim()))
+    })
+}
+
+/// Converts a dimension read from a file, which must fit the `u32`s variants take.
+pub(crate) fn dimension(size: usize) -> Result<u32, MatrixMultiplyError> {
+    u32::try_from(size)
+        .map_err(|_| MatrixMultiplyError::MatrixIo(format!("dimension {size} is too large")))
+}
+
+/// The number of elements in a `rows` by `cols` matrix, which the kernels must be able
+/// to index with a `u32`.
+pub(crate) fn elements(rows: u32, cols: u32) -> Result<usize, MatrixMultiplyError> {
+    rows.checked_mul(cols)
+        .map(|elements| elements as usize)
+        .ok_or_else(|| {
+            MatrixMultiplyError::MatrixIo(format!("a {rows}x{cols} matrix is too large"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transpose() {
+        let matrix = Matrix::new(2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let transposed = matrix.transpose();
+        assert_eq!((transposed.rows, transposed.cols), (3, 2));
+        assert_eq!(transposed.data, [1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(transposed.transpose(), matrix);
+    }
+
+    #[test]
+    fn test_elements() {
+        assert_eq!(elements(3, 5).unwrap(), 15);
+        assert_eq!(elements(65536, 65535).unwrap(), 65536 * 65535);
+        assert!(matches!(
+            elements(65536, 65536),
+            Err(MatrixMultiplyError::MatrixIo(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_files() {
+        let matrix = Matrix::new(2, 3, vec![1.5, -2.0, 0.0, 1e-8, 3.25, 1e20]).unwrap();
+        let directory = std::env::temp_dir().join(format!("matmul-io-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in ["matrix.npy", "matrix.mtx", "matrix.csv"] {
+            let path = directory.join(name);
+            write(&path, &matrix).unwrap();
+            assert_eq!(read(&path).unwrap(), matrix, "{name}");
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(
+            read(directory.join("matrix.txt")),
+            Err(MatrixMultiplyError::MatrixIo(_))
+        ));
+    }
+}
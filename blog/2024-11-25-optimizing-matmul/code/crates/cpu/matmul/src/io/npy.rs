@@ -0,0 +1,326 @@
+//! The `.npy` format written by `numpy.save`, described in
+//! <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html>.
+
+use super::{dimension, elements, stream_error, Matrix};
+use crate::MatrixMultiplyError;
+use half::f16;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Headers are padded so the data that follows is aligned to this many bytes.
+const ALIGNMENT: usize = 64;
+
+/// The element types `.npy` files can hold. They are converted to and from `f32`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Dtype {
+    F16,
+    #[default]
+    F32,
+    F64,
+}
+
+impl Dtype {
+    fn size(self) -> usize {
+        match self {
+            Dtype::F16 => 2,
+            Dtype::F32 => 4,
+            Dtype::F64 => 8,
+        }
+    }
+
+    fn descr(self) -> &'static str {
+        match self {
+            Dtype::F16 => "<f2",
+            Dtype::F32 => "<f4",
+            Dtype::F64 => "<f8",
+        }
+    }
+}
+
+/// The parts of the header dictionary needed to read the data.
+#[derive(Debug, PartialEq)]
+struct Header {
+    dtype: Dtype,
+    big_endian: bool,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+/// Reads a 2-D array of `f16`, `f32` or `f64` elements in either byte order.
+pub fn read_npy(mut reader: impl Read) -> Result<Matrix, MatrixMultiplyError> {
+    let mut preamble = [0; 8];
+    reader.read_exact(&mut preamble).map_err(stream_error)?;
+    if &preamble[..6] != MAGIC {
+        return Err(error("not an .npy file"));
+    }
+    let header_length = match preamble[6] {
+        1 => {
+            let mut length = [0; 2];
+            reader.read_exact(&mut length).map_err(stream_error)?;
+            u16::from_le_bytes(length) as usize
+        }
+        2 | 3 => {
+            let mut length = [0; 4];
+            reader.read_exact(&mut length).map_err(stream_error)?;
+            u32::from_le_bytes(length) as usize
+        }
+        version => return Err(error(format!("unsupported version {version}"))),
+    };
+    let mut header = vec![0; header_length];
+    reader.read_exact(&mut header).map_err(stream_error)?;
+    let header = parse_header(&String::from_utf8_lossy(&header))?;
+
+    let [rows, cols] = header.shape[..] else {
+        return Err(error(format!(
+            "expected a 2-D array, found shape {:?}",
+            header.shape
+        )));
+    };
+    let (rows, cols) = (dimension(rows)?, dimension(cols)?);
+    let size = elements(rows, cols)?
+        .checked_mul(header.dtype.size())
+        .ok_or_else(|| error(format!("a {rows}x{cols} array is too large")))?;
+
+    // Read what is there rather than allocating what the header claims, so a corrupt
+    // shape fails on the length of the file.
+    let mut bytes = Vec::new();
+    reader
+        .take(size as u64)
+        .read_to_end(&mut bytes)
+        .map_err(stream_error)?;
+    if bytes.len() != size {
+        return Err(error(format!(
+            "a {rows}x{cols} array needs {size} bytes of data, found {}",
+            bytes.len()
+        )));
+    }
+    let data = decode(&bytes, header.dtype, header.big_endian);
+
+    if header.fortran_order {
+        Matrix::from_column_major(rows, cols, data)
+    } else {
+        Matrix::new(rows, cols, data)
+    }
+}
+
+/// Writes `matrix` in C order with elements of type `dtype`, which loses precision for
+/// [`Dtype::F16`].
+pub fn write_npy(
+    mut writer: impl Write,
+    matrix: &Matrix,
+    dtype: Dtype,
+) -> Result<(), MatrixMultiplyError> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        dtype.descr(),
+        matrix.rows,
+        matrix.cols
+    );
+    // The preamble is 10 bytes and the header ends with a newline.
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(ALIGNMENT) - unpadded,
+    ));
+    header.push('\n');
+    let header_length = u16::try_from(header.len()).map_err(|_| error("the header is too long"))?;
+
+    writer.write_all(MAGIC).map_err(stream_error)?;
+    writer.write_all(&[1, 0]).map_err(stream_error)?;
+    writer
+        .write_all(&header_length.to_le_bytes())
+        .map_err(stream_error)?;
+    writer.write_all(header.as_bytes()).map_err(stream_error)?;
+    writer
+        .write_all(&encode(&matrix.data, dtype))
+        .map_err(stream_error)
+}
+
+fn decode(bytes: &[u8], dtype: Dtype, big_endian: bool) -> Vec<f32> {
+    macro_rules! decode {
+        ($type:ty, $size:literal, $convert:expr) => {
+            bytes
+                .chunks_exact($size)
+                .map(|chunk| {
+                    let chunk: [u8; $size] = chunk.try_into().expect("chunks are exact");
+                    let value = if big_endian {
+                        <$type>::from_be_bytes(chunk)
+                    } else {
+                        <$type>::from_le_bytes(chunk)
+                    };
+                    $convert(value)
+                })
+                .collect()
+        };
+    }
+    match dtype {
+        Dtype::F16 => decode!(f16, 2, f16::to_f32),
+        Dtype::F32 => decode!(f32, 4, std::convert::identity),
+        Dtype::F64 => decode!(f64, 8, |value: f64| value as f32),
+    }
+}
+
+fn encode(data: &[f32], dtype: Dtype) -> Vec<u8> {
+    match dtype {
+        Dtype::F16 => data
+            .iter()
+            .flat_map(|&value| f16::from_f32(value).to_le_bytes())
+            .collect(),
+        Dtype::F32 => data.iter().flat_map(|value| value.to_le_bytes()).collect(),
+        Dtype::F64 => data
+            .iter()
+            .flat_map(|&value| (value as f64).to_le_bytes())
+            .collect(),
+    }
+}
+
+/// Parses the Python dictionary literal numpy writes, such as
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`.
+fn parse_header(header: &str) -> Result<Header, MatrixMultiplyError> {
+    let descr = value(header, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|descr| descr.split_once('\''))
+        .map(|(descr, _)| descr)
+        .ok_or_else(|| error(format!("invalid descr {descr}")))?;
+    let (big_endian, dtype) = match descr.split_at_checked(1) {
+        Some(("<", dtype)) => (false, dtype),
+        Some((">", dtype)) => (true, dtype),
+        Some(("=", dtype)) => (cfg!(target_endian = "big"), dtype),
+        _ => return Err(error(format!("unsupported dtype '{descr}'"))),
+    };
+    let dtype = match dtype {
+        "f2" => Dtype::F16,
+        "f4" => Dtype::F32,
+        "f8" => Dtype::F64,
+        _ => return Err(error(format!("unsupported dtype '{descr}'"))),
+    };
+
+    let fortran_order = value(header, "fortran_order")?;
+    let fortran_order = if fortran_order.starts_with("True") {
+        true
+    } else if fortran_order.starts_with("False") {
+        false
+    } else {
+        return Err(error(format!("invalid fortran_order {fortran_order}")));
+    };
+
+    let shape = value(header, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|shape| shape.split_once(')'))
+        .map(|(shape, _)| shape)
+        .ok_or_else(|| error(format!("invalid shape {shape}")))?;
+    let shape = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|size| !size.is_empty())
+        .map(|size| {
+            size.parse()
+                .map_err(|_| error(format!("invalid shape ({shape})")))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Header {
+        dtype,
+        big_endian,
+        fortran_order,
+        shape,
+    })
+}
+
+/// The text following `'key':` in the header.
+fn value<'a>(header: &'a str, key: &str) -> Result<&'a str, MatrixMultiplyError> {
+    let quoted = format!("'{key}'");
+    let (_, rest) = header
+        .split_once(&quoted)
+        .ok_or_else(|| error(format!("the header has no {key}")))?;
+    rest.trim_start()
+        .strip_prefix(':')
+        .map(str::trim_start)
+        .ok_or_else(|| error(format!("the header has no value for {key}")))
+}
+
+fn error(message: impl std::fmt::Display) -> MatrixMultiplyError {
+    MatrixMultiplyError::MatrixIo(format!("invalid .npy file, {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip_dtypes() {
+        let matrix = Matrix::new(2, 3, vec![1.0, -2.5, 0.0, 0.125, 3.0, 65504.0]).unwrap();
+        for dtype in [Dtype::F16, Dtype::F32, Dtype::F64] {
+            let mut bytes = Vec::new();
+            write_npy(&mut bytes, &matrix, dtype).unwrap();
+            let data_offset = bytes.len() - 6 * dtype.size();
+            assert_eq!(data_offset % ALIGNMENT, 0, "{dtype:?}");
+            assert_eq!(read_npy(&bytes[..]).unwrap(), matrix, "{dtype:?}");
+        }
+    }
+
+    #[test]
+    fn test_fortran_order_big_endian() {
+        // numpy.asfortranarray([[1, 2, 3], [4, 5, 6]], dtype='>f8')
+        let data = [1.0f64, 4.0, 2.0, 5.0, 3.0, 6.0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect::<Vec<_>>();
+        let bytes = npy(
+            "{'descr': '>f8', 'fortran_order': True, 'shape': (2, 3), }\n",
+            &data,
+        );
+        let matrix = read_npy(&bytes[..]).unwrap();
+        assert_eq!((matrix.rows, matrix.cols), (2, 3));
+        assert_eq!(matrix.data, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let vector = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }",
+            &[0; 12],
+        );
+        let integers = npy(
+            "{'descr': '<i4', 'fortran_order': False, 'shape': (1, 1), }",
+            &[0; 4],
+        );
+        let truncated = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2), }",
+            &[0; 12],
+        );
+        let huge = npy(
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (65536, 65535), }",
+            &[0; 8],
+        );
+        let overflowing = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (4294967295, 4294967295), }",
+            &[0; 4],
+        );
+        for bytes in [
+            vector,
+            integers,
+            truncated,
+            huge,
+            overflowing,
+            b"PK\x03\x04".to_vec(),
+        ] {
+            assert!(matches!(
+                read_npy(&bytes[..]),
+                Err(MatrixMultiplyError::MatrixIo(_))
+            ));
+        }
+    }
+}
@@ -1,13 +1,30 @@
 #![allow(opaque_hidden_inferred_bound)]
 
+use device::{DeviceOptions, GpuContext};
 use glam::UVec3;
 use settings::Dimensions;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
+pub use backends::wgpu::{specialized_shader, GpuBuffers, SpecializedShader};
+
+pub mod autotune;
 mod backends;
+pub mod device;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod hybrid;
+pub mod io;
+pub mod registry;
+#[cfg(feature = "roofline")]
+pub mod roofline;
 pub mod variants;
+pub mod verify;
 
 /// Errors that can happen for matrix multiply on the CPU or GPU.
 #[derive(Error, Debug)]
@@ -16,6 +33,8 @@ pub enum MatrixMultiplyError {
     GpuInstanceCreation,
     #[error("Failed to find an appropriate GPU adapter")]
     GpuAdapterRequest,
+    #[error("No GPU adapter matches {0}")]
+    GpuAdapterNotFound(String),
     #[error("Failed to create GPU device and queue")]
     GpuDeviceCreation,
     #[error("Failed to receive data from the GPU")]
@@ -24,6 +43,38 @@ pub enum MatrixMultiplyError {
     GpuBufferMapping,
     #[error("Failed to acquire a lock on the result vector")]
     CpuLockError,
+    #[error("Unknown variant {0:?}")]
+    UnknownVariant(String),
+    #[error("No variant supports a {0}x{1}x{2} multiplication")]
+    NoSupportedVariant(u32, u32, u32),
+    #[error("Failed to load shader: {0}")]
+    ShaderLoad(String),
+    #[error("Failed to compile shader: {0}")]
+    ShaderCompilation(String),
+    #[error("Invalid tile size {0}")]
+    InvalidTileSize(String),
+    #[error("Invalid workgroup size {0}")]
+    InvalidWorkgroupSize(String),
+    #[error("Matrix size mismatch: {0}")]
+    MatrixSize(String),
+    #[error("Failed to read or write matrix: {0}")]
+    MatrixIo(String),
+    #[error("Variant {variant} is unsupported on this adapter ({adapter}), it needs {missing}")]
+    UnsupportedVariant {
+        variant: String,
+        adapter: String,
+        missing: String,
+    },
+    #[error(
+        "Variant {variant} sets specialization constants {constants}, which are ignored \
+         when its shader is passed through to the driver"
+    )]
+    UnsupportedPipelineConstants { variant: String, constants: String },
+    #[error(
+        "Variant {variant} needs its shader passed through to the driver without \
+         validation, which is only allowed for the kernels compiled into the binary"
+    )]
+    UncheckedPassthrough { variant: String },
 }
 
 /// The trait that defines how to multiply two matrices.
@@ -37,6 +88,107 @@ pub trait MatrixMultiply<T>: Display + Sized {
         k: u32,
         n: u32,
     ) -> Result<Vec<f32>, MatrixMultiplyError>;
+
+    /// The steps of [`MatrixMultiply::multiply`], for backends that run them on a
+    /// separate device.
+    fn phased(&self) -> Option<&dyn PhasedMultiply> {
+        None
+    }
+}
+
+/// An object-safe version of [`MatrixMultiply`], so different variants and backends
+/// can be stored and selected at runtime.
+///
+/// Use [`registry::create`] to get one by name, or wrap an existing multiplier with
+/// [`DynAdapter`].
+pub trait DynMatrixMultiply: Display + Send + Sync {
+    fn multiply(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<Vec<f32>, MatrixMultiplyError>;
+
+    /// See [`MatrixMultiply::phased`].
+    fn phased(&self) -> Option<&dyn PhasedMultiply> {
+        None
+    }
+}
+
+/// A GPU multiplication split into the steps [`MatrixMultiply::multiply`] runs in one
+/// go, so each can be timed on its own. The buffers stay on the GPU between steps.
+///
+/// ```no_run
+/// let multiplier = matmul::registry::create("tiling_2d")?;
+/// let phased = multiplier.phased().unwrap();
+/// let buffers = phased.allocate(64, 64, 64);
+/// phased.upload(&buffers, &[1.0; 64 * 64], &[1.0; 64 * 64])?;
+/// let gpu_time = phased.dispatch(&buffers)?;
+/// let result = phased.read_back(&buffers)?;
+/// # Ok::<(), matmul::MatrixMultiplyError>(())
+/// ```
+pub trait PhasedMultiply {
+    /// Creates the buffers for multiplying an `m x k` matrix by a `k x n` matrix.
+    fn allocate(&self, m: u32, k: u32, n: u32) -> GpuBuffers;
+
+    /// Copies both inputs to the GPU and waits for the copy to finish.
+    fn upload(&self, buffers: &GpuBuffers, a: &[f32], b: &[f32])
+        -> Result<(), MatrixMultiplyError>;
+
+    /// Runs the kernel on the uploaded inputs and waits for it to finish. Returns how
+    /// long the GPU spent on it if the adapter supports timestamp queries.
+    fn dispatch(&self, buffers: &GpuBuffers) -> Result<Option<Duration>, MatrixMultiplyError>;
+
+    /// Copies the result of the last dispatch to the CPU.
+    fn read_back(&self, buffers: &GpuBuffers) -> Result<Vec<f32>, MatrixMultiplyError>;
+}
+
+/// Wraps a [`MatrixMultiply`] implementation so it can be used as a
+/// [`DynMatrixMultiply`] trait object.
+pub struct DynAdapter<T, U> {
+    multiplier: U,
+    _variant: PhantomData<fn() -> T>,
+}
+
+impl<T, U: MatrixMultiply<T>> DynAdapter<T, U> {
+    pub fn new(multiplier: U) -> Self {
+        Self {
+            multiplier,
+            _variant: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> U {
+        self.multiplier
+    }
+}
+
+impl<T, U: Display> Display for DynAdapter<T, U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.multiplier.fmt(f)
+    }
+}
+
+impl<T, U> DynMatrixMultiply for DynAdapter<T, U>
+where
+    U: MatrixMultiply<T> + Send + Sync,
+{
+    fn multiply(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<Vec<f32>, MatrixMultiplyError> {
+        self.multiplier.multiply(a, b, m, k, n)
+    }
+
+    fn phased(&self) -> Option<&dyn PhasedMultiply> {
+        self.multiplier.phased()
+    }
 }
 
 /// Matrix multiplication logic that can be run on the CPU.
@@ -53,9 +205,47 @@ pub trait Cpu {
 
 /// Matrix multiplication logic that can be run on the GPU.
 pub trait Gpu {
-    fn compiled_shader(&self) -> &[u8];
-    fn entry_point(&self) -> &'static str {
-        settings::SHADER_ENTRY_POINT
+    /// The compiled SPIR-V, as 32-bit words.
+    fn compiled_shader(&self) -> &[u32];
+    /// The name of the shader function to run.
+    fn entry_point(&self) -> &'static str;
+
+    /// SPIR-V capabilities the shader was compiled with, beyond the defaults.
+    fn capabilities(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// SPIR-V extensions the shader was compiled with.
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Values for the shader's specialization constants, keyed by their ID. See
+    /// [`settings::spec_constants`].
+    fn pipeline_constants(&self) -> HashMap<String, f64> {
+        HashMap::new()
+    }
+
+    /// Whether the source of [`Gpu::compiled_shader`] changed since it was last loaded.
+    fn shader_changed(&self) -> bool {
+        false
+    }
+
+    /// Loads the shader again, so the next call to [`Gpu::compiled_shader`] returns the
+    /// new version.
+    fn reload_shader(&mut self) -> Result<(), MatrixMultiplyError> {
+        Ok(())
+    }
+
+    /// Goes back to the shader from before the last [`Gpu::reload_shader`], after the
+    /// new one failed to compile.
+    fn revert_shader(&mut self) {}
+
+    /// Whether [`Gpu::compiled_shader`] is a kernel compiled into the binary, which
+    /// `gpu_build` checks when it builds them. Shaders loaded at runtime aren't checked,
+    /// so they are never passed through to the driver without validation.
+    fn shader_checked(&self) -> bool {
+        true
     }
 }
 
@@ -63,70 +253,198 @@ pub trait Gpu {
 pub trait GridComputation {
     fn workgroup(&self) -> UVec3;
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3;
+
+    /// The rows and columns of the result each invocation computes.
+    fn tile(&self) -> (u32, u32) {
+        (1, 1)
+    }
 }
 
+#[cfg(feature = "naive")]
 pub mod naive {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Naive>, MatrixMultiplyError> {
-        futures::executor::block_on(backends::wgpu::MatrixMultiplier::new(variants::Naive))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Naive>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(variants::Naive, options))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Naive>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Naive)
     }
 }
 
+#[cfg(feature = "workgroup_256")]
 pub mod workgroup_256 {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Workgroup256>, MatrixMultiplyError> {
-        futures::executor::block_on(backends::wgpu::MatrixMultiplier::new(
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Workgroup256>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
             variants::Workgroup256,
+            options,
         ))
     }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Workgroup256>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Workgroup256)
+    }
 }
 
+#[cfg(feature = "workgroup_2d")]
 pub mod workgroup_2d {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Workgroup2d>, MatrixMultiplyError> {
-        futures::executor::block_on(MatrixMultiplier::new(variants::Workgroup2d))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Workgroup2d>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
+            variants::Workgroup2d,
+            options,
+        ))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Workgroup2d>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Workgroup2d)
     }
 }
 
+#[cfg(feature = "tiling_1d")]
 pub mod tiling_1d {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Tiling1d>, MatrixMultiplyError> {
-        futures::executor::block_on(MatrixMultiplier::new(variants::Tiling1d))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Tiling1d>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
+            variants::Tiling1d::default(),
+            options,
+        ))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Tiling1d>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Tiling1d::default())
+    }
+
+    /// Like [`wgpu_from_context`], with a tile size other than the default.
+    pub fn wgpu_from_variant(
+        context: &Arc<GpuContext>,
+        variant: variants::Tiling1d,
+    ) -> Result<MatrixMultiplier<variants::Tiling1d>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variant)
     }
 }
 
+#[cfg(feature = "tiling_1d_loop")]
 pub mod tiling_1d_loop {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Tiling1dLoop>, MatrixMultiplyError> {
-        futures::executor::block_on(MatrixMultiplier::new(variants::Tiling1dLoop))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Tiling1dLoop>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
+            variants::Tiling1dLoop,
+            options,
+        ))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Tiling1dLoop>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Tiling1dLoop)
     }
 }
 
+#[cfg(feature = "tiling_2d")]
 pub mod tiling_2d {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Tiling2d>, MatrixMultiplyError> {
-        futures::executor::block_on(MatrixMultiplier::new(variants::Tiling2d))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Tiling2d>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
+            variants::Tiling2d::default(),
+            options,
+        ))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Tiling2d>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Tiling2d::default())
+    }
+
+    /// Like [`wgpu_from_context`], with a tile size other than the default.
+    pub fn wgpu_from_variant(
+        context: &Arc<GpuContext>,
+        variant: variants::Tiling2d,
+    ) -> Result<MatrixMultiplier<variants::Tiling2d>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variant)
     }
 }
 
+#[cfg(feature = "isomorphic")]
 pub mod isomorphic {
     use super::*;
     use crate::backends::wgpu::MatrixMultiplier;
 
     pub fn wgpu() -> Result<MatrixMultiplier<variants::Isomorphic>, MatrixMultiplyError> {
-        futures::executor::block_on(MatrixMultiplier::new(variants::Isomorphic))
+        wgpu_with(&DeviceOptions::default())
+    }
+
+    pub fn wgpu_with(
+        options: &DeviceOptions,
+    ) -> Result<MatrixMultiplier<variants::Isomorphic>, MatrixMultiplyError> {
+        futures::executor::block_on(MatrixMultiplier::with_options(
+            variants::Isomorphic,
+            options,
+        ))
+    }
+
+    pub fn wgpu_from_context(
+        context: &Arc<GpuContext>,
+    ) -> Result<MatrixMultiplier<variants::Isomorphic>, MatrixMultiplyError> {
+        MatrixMultiplier::from_context(context.clone(), variants::Isomorphic)
     }
 
     pub mod cpu {
@@ -0,0 +1,548 @@
+//! A list of every variant and backend combination, so they can be looked up and
+//! constructed by name at runtime.
+//!
+//! Variants are identified by `<variant>:<backend>`, for example `tiling_2d:wgpu` or
+//! `isomorphic:cpu:multi`. A bare variant name such as `tiling_2d` means the `wgpu`
+//! backend. Variants whose tile size is a specialization constant can be given another
+//! tile with `@<rows>x<cols>`, as in `tiling_2d@2x8:wgpu`.
+
+use crate::backends::cpu::{MultiThreadedMatMul, SingleThreadedMatMul};
+use crate::backends::wgpu::MatrixMultiplier;
+use crate::device::{DeviceOptions, GpuContext};
+use crate::{
+    variants, Cpu, DynAdapter, DynMatrixMultiply, Gpu, GridComputation, MatrixMultiply,
+    MatrixMultiplyError,
+};
+use glam::UVec3;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Where a variant runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// On the GPU, through `wgpu`.
+    Wgpu,
+    /// On the CPU, with a single thread.
+    CpuSingle,
+    /// On the CPU, with one thread per core.
+    CpuMulti,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Wgpu, Backend::CpuSingle, Backend::CpuMulti];
+
+    pub fn is_gpu(&self) -> bool {
+        *self == Backend::Wgpu
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Wgpu => write!(f, "wgpu"),
+            Backend::CpuSingle => write!(f, "cpu:single"),
+            Backend::CpuMulti => write!(f, "cpu:multi"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = MatrixMultiplyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .into_iter()
+            .find(|backend| backend.to_string() == s)
+            .ok_or_else(|| MatrixMultiplyError::UnknownVariant(s.to_string()))
+    }
+}
+
+/// Creates a variant, with the tile from [`VariantInfo::tile`] for the variants that can
+/// be specialized.
+type Constructor = fn(
+    Option<&Arc<GpuContext>>,
+    (u32, u32),
+) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError>;
+
+/// Metadata about a variant running on a specific backend.
+#[derive(Clone, Debug)]
+pub struct VariantInfo {
+    /// The variant's name, such as `tiling_2d`.
+    pub name: &'static str,
+    pub backend: Backend,
+    /// The number of threads in each workgroup.
+    pub workgroup: UVec3,
+    /// The shader entry point, for GPU variants.
+    pub entry_point: Option<&'static str>,
+    /// The rows and columns of the result each invocation computes, see
+    /// [`GridComputation::tile`].
+    pub tile: (u32, u32),
+    /// The largest tile the variant can be specialized to with [`VariantInfo::with_tile`],
+    /// if its tile size isn't fixed.
+    pub max_tile: Option<(u32, u32)>,
+    /// The largest result matrix (`m * n`) the variant can compute. The naive variants
+    /// dispatch one workgroup per element and run into the 65,535 workgroup limit.
+    pub max_result_elements: Option<u64>,
+    default_tile: (u32, u32),
+    constructor: Constructor,
+}
+
+impl VariantInfo {
+    /// The identifier used to look this variant up, such as `tiling_2d:wgpu`, or
+    /// `tiling_2d@2x8:wgpu` when it is specialized to another tile.
+    pub fn id(&self) -> String {
+        if self.tile == self.default_tile {
+            format!("{}:{}", self.name, self.backend)
+        } else {
+            let (rows, cols) = self.tile;
+            format!("{}@{rows}x{cols}:{}", self.name, self.backend)
+        }
+    }
+
+    /// The same variant specialized to compute a `rows` by `cols` tile per invocation.
+    pub fn with_tile(&self, (rows, cols): (u32, u32)) -> Result<Self, MatrixMultiplyError> {
+        match self.max_tile {
+            Some((max_rows, max_cols))
+                if (1..=max_rows).contains(&rows) && (1..=max_cols).contains(&cols) =>
+            {
+                Ok(Self {
+                    tile: (rows, cols),
+                    ..self.clone()
+                })
+            }
+            Some((max_rows, max_cols)) => Err(MatrixMultiplyError::InvalidTileSize(format!(
+                "{rows}x{cols}, {} supports up to {max_rows}x{max_cols}",
+                self.name
+            ))),
+            None => Err(MatrixMultiplyError::InvalidTileSize(format!(
+                "{rows}x{cols}, {} only computes {}x{} tiles",
+                self.name, self.tile.0, self.tile.1
+            ))),
+        }
+    }
+
+    /// Whether multiplying an `m x k` matrix by a `k x n` matrix is supported and takes a
+    /// reasonable time, see [`RULES`].
+    pub fn practical(&self, m: u32, k: u32, n: u32) -> bool {
+        self.supports(m, k, n)
+            && RULES
+                .iter()
+                .filter(|rule| rule.applies_to(self))
+                .all(|rule| m.max(k).max(n) <= rule.max_dimension)
+    }
+
+    /// Whether the variant can multiply an `m x k` matrix by a `k x n` matrix.
+    pub fn supports(&self, m: u32, _k: u32, n: u32) -> bool {
+        self.max_result_elements
+            .is_none_or(|max| m as u64 * n as u64 <= max)
+    }
+
+    /// Creates the multiplier. GPU variants use `context` if given, otherwise they
+    /// create a device on the default adapter.
+    pub fn create(
+        &self,
+        context: Option<&Arc<GpuContext>>,
+    ) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError> {
+        (self.constructor)(context, self.tile)
+    }
+}
+
+impl Display for VariantInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+/// Which variants a [`Rule`] applies to.
+#[derive(Copy, Clone, Debug)]
+pub enum Applies {
+    /// Every backend of the variant with this name.
+    Variant(&'static str),
+    /// Every variant on this backend.
+    Backend(Backend),
+}
+
+/// A limit on the shapes a variant is worth running on, on top of the ones it can't
+/// multiply at all (see [`VariantInfo::supports`]).
+#[derive(Copy, Clone, Debug)]
+pub struct Rule {
+    pub applies: Applies,
+    /// The largest `m`, `k` or `n` to run.
+    pub max_dimension: u32,
+}
+
+/// Shapes that take too long to be worth benchmarking or tuning.
+pub const RULES: &[Rule] = &[
+    // One invocation per element is already far behind the others at this size.
+    Rule {
+        applies: Applies::Variant("naive"),
+        max_dimension: 128,
+    },
+    // One thread simulating every invocation takes minutes per sample beyond this.
+    Rule {
+        applies: Applies::Backend(Backend::CpuSingle),
+        max_dimension: 1024,
+    },
+];
+
+impl Rule {
+    fn applies_to(&self, info: &VariantInfo) -> bool {
+        match self.applies {
+            Applies::Variant(name) => info.name == name,
+            Applies::Backend(backend) => info.backend == backend,
+        }
+    }
+}
+
+/// All registered variants. Only variants whose Cargo feature is enabled are included.
+pub fn variants() -> Vec<VariantInfo> {
+    #[allow(unused_mut)]
+    let mut variants = Vec::new();
+    #[cfg(feature = "naive")]
+    variants.push(gpu(variants::Naive, "naive", Some(65_535), |context, _| {
+        wgpu(context, variants::Naive)
+    }));
+    #[cfg(feature = "workgroup_256")]
+    variants.push(gpu(
+        variants::Workgroup256,
+        "workgroup_256",
+        Some(65_535 * 256),
+        |context, _| wgpu(context, variants::Workgroup256),
+    ));
+    #[cfg(feature = "workgroup_2d")]
+    variants.push(gpu(
+        variants::Workgroup2d,
+        "workgroup_2d",
+        None,
+        |context, _| wgpu(context, variants::Workgroup2d),
+    ));
+    #[cfg(feature = "tiling_1d")]
+    variants.push(VariantInfo {
+        max_tile: Some((1, settings::MAX_TILE_SIZE)),
+        ..gpu(
+            variants::Tiling1d::default(),
+            "tiling_1d",
+            None,
+            |context, (_, tile_size)| wgpu(context, variants::Tiling1d::new(tile_size)?),
+        )
+    });
+    #[cfg(feature = "tiling_1d_loop")]
+    variants.push(gpu(
+        variants::Tiling1dLoop,
+        "tiling_1d_loop",
+        None,
+        |context, _| wgpu(context, variants::Tiling1dLoop),
+    ));
+    #[cfg(feature = "tiling_2d")]
+    variants.push(VariantInfo {
+        max_tile: Some((settings::MAX_TILE_M, settings::MAX_TILE_N)),
+        ..gpu(
+            variants::Tiling2d::default(),
+            "tiling_2d",
+            None,
+            |context, (tile_m, tile_n)| wgpu(context, variants::Tiling2d::new(tile_m, tile_n)?),
+        )
+    });
+    #[cfg(feature = "isomorphic")]
+    variants.extend([
+        gpu(variants::Isomorphic, "isomorphic", None, |context, _| {
+            wgpu(context, variants::Isomorphic)
+        }),
+        cpu(
+            variants::Isomorphic,
+            "isomorphic",
+            Backend::CpuSingle,
+            |_, _| cpu_single(variants::Isomorphic),
+        ),
+        cpu(
+            variants::Isomorphic,
+            "isomorphic",
+            Backend::CpuMulti,
+            |_, _| cpu_multi(variants::Isomorphic),
+        ),
+    ]);
+    #[cfg(feature = "isomorphic")]
+    {
+        variants.extend(isomorphic_tiled::<1, 1>("isomorphic_1x1"));
+        variants.extend(isomorphic_tiled::<2, 2>("isomorphic_2x2"));
+        variants.extend(isomorphic_tiled::<4, 4>("isomorphic_4x4"));
+        variants.extend(isomorphic_tiled::<8, 8>("isomorphic_8x8"));
+    }
+    variants
+}
+
+/// The isomorphic kernel with a `TILE_M` by `TILE_N` tile, on every backend.
+#[cfg(feature = "isomorphic")]
+fn isomorphic_tiled<const TILE_M: usize, const TILE_N: usize>(
+    name: &'static str,
+) -> [VariantInfo; 3]
+where
+    [[f32; TILE_N]; TILE_M]: Default,
+{
+    let variant = variants::IsomorphicTiled::<TILE_M, TILE_N>;
+    [
+        gpu(variant, name, None, |context, _| {
+            wgpu(context, variants::IsomorphicTiled::<TILE_M, TILE_N>)
+        }),
+        cpu(variant, name, Backend::CpuSingle, |_, _| {
+            cpu_single(variants::IsomorphicTiled::<TILE_M, TILE_N>)
+        }),
+        cpu(variant, name, Backend::CpuMulti, |_, _| {
+            cpu_multi(variants::IsomorphicTiled::<TILE_M, TILE_N>)
+        }),
+    ]
+}
+
+/// Every variant that can be specialized, with each tile whose sides are powers of two
+/// other than its default.
+pub fn tile_configurations() -> Vec<VariantInfo> {
+    let sides = |max: u32| (0..=max.ilog2()).map(|power| 1 << power);
+    variants()
+        .into_iter()
+        .filter_map(|info| Some((info.max_tile?, info)))
+        .flat_map(|((max_rows, max_cols), info)| {
+            sides(max_rows)
+                .flat_map(move |rows| sides(max_cols).map(move |cols| (rows, cols)))
+                .filter(move |&tile| tile != info.default_tile)
+                .map(move |tile| info.with_tile(tile).expect("tile is within the maximum"))
+        })
+        .collect()
+}
+
+/// Finds a variant by its identifier, such as `tiling_2d:wgpu`, `tiling_2d` or
+/// `tiling_2d@2x8:wgpu`.
+pub fn find(id: &str) -> Result<VariantInfo, MatrixMultiplyError> {
+    let unknown = || MatrixMultiplyError::UnknownVariant(id.to_string());
+    let (name, backend) = match id.split_once(':') {
+        Some((name, backend)) => (name, backend.parse()?),
+        None => (id, Backend::Wgpu),
+    };
+    let (name, tile) = match name.split_once('@') {
+        Some((name, tile)) => {
+            let (rows, cols) = tile.split_once('x').ok_or_else(unknown)?;
+            let tile = (
+                rows.parse().map_err(|_| unknown())?,
+                cols.parse().map_err(|_| unknown())?,
+            );
+            (name, Some(tile))
+        }
+        None => (name, None),
+    };
+    let info = variants()
+        .into_iter()
+        .find(|info| info.name == name && info.backend == backend)
+        .ok_or_else(unknown)?;
+    match tile {
+        Some(tile) => info.with_tile(tile),
+        None => Ok(info),
+    }
+}
+
+/// Creates the variant named `id`. GPU variants get their own device on the default
+/// adapter; use [`create_with_context`] to share one.
+pub fn create(id: &str) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError> {
+    find(id)?.create(None)
+}
+
+/// Creates the variant named `id`, running GPU variants on `context`.
+pub fn create_with_context(
+    id: &str,
+    context: &Arc<GpuContext>,
+) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError> {
+    find(id)?.create(Some(context))
+}
+
+fn gpu<T: Gpu + GridComputation>(
+    variant: T,
+    name: &'static str,
+    max_result_elements: Option<u64>,
+    constructor: Constructor,
+) -> VariantInfo {
+    VariantInfo {
+        name,
+        backend: Backend::Wgpu,
+        workgroup: variant.workgroup(),
+        entry_point: Some(variant.entry_point()),
+        tile: variant.tile(),
+        max_tile: None,
+        max_result_elements,
+        default_tile: variant.tile(),
+        constructor,
+    }
+}
+
+#[cfg_attr(not(feature = "isomorphic"), allow(dead_code))]
+fn cpu<T: GridComputation>(
+    variant: T,
+    name: &'static str,
+    backend: Backend,
+    constructor: Constructor,
+) -> VariantInfo {
+    VariantInfo {
+        name,
+        backend,
+        workgroup: variant.workgroup(),
+        entry_point: None,
+        tile: variant.tile(),
+        max_tile: None,
+        max_result_elements: None,
+        default_tile: variant.tile(),
+        constructor,
+    }
+}
+
+fn wgpu<T>(
+    context: Option<&Arc<GpuContext>>,
+    variant: T,
+) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError>
+where
+    T: Gpu + GridComputation + Display + Send + Sync + 'static,
+{
+    let multiplier = match context {
+        Some(context) => MatrixMultiplier::from_context(context.clone(), variant)?,
+        None => futures::executor::block_on(MatrixMultiplier::with_options(
+            variant,
+            &DeviceOptions::default(),
+        ))?,
+    };
+    Ok(Box::new(DynAdapter::new(multiplier)))
+}
+
+#[cfg_attr(not(feature = "isomorphic"), allow(dead_code))]
+fn cpu_single<T>(variant: T) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError>
+where
+    T: Cpu + GridComputation + Display + Send + Sync + 'static,
+{
+    let multiplier = futures::executor::block_on(SingleThreadedMatMul::new(variant))?;
+    Ok(Box::new(DynAdapter::new(multiplier)))
+}
+
+#[cfg_attr(not(feature = "isomorphic"), allow(dead_code))]
+fn cpu_multi<T>(variant: T) -> Result<Box<dyn DynMatrixMultiply>, MatrixMultiplyError>
+where
+    T: Cpu + GridComputation + Display + Send + Sync + 'static,
+{
+    let multiplier = futures::executor::block_on(MultiThreadedMatMul::new(variant))?;
+    Ok(Box::new(DynAdapter::new(multiplier)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_round_trip() {
+        for info in variants() {
+            let found = find(&info.id()).expect("Registered variant not found");
+            assert_eq!(found.name, info.name);
+            assert_eq!(found.backend, info.backend);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tiling_2d")]
+    fn test_bare_name_is_wgpu() {
+        let info = find("tiling_2d").expect("Variant not found");
+        assert_eq!(info.backend, Backend::Wgpu);
+    }
+
+    #[test]
+    fn test_unknown_variant() {
+        assert!(find("tiling_3d:wgpu").is_err());
+        assert!(find("tiling_2d:cpu:gpu").is_err());
+        assert!(find("tiling_2d@2:wgpu").is_err());
+    }
+
+    #[test]
+    fn test_tile_configurations() {
+        for info in tile_configurations() {
+            let found = find(&info.id()).expect("Tile configuration not found");
+            assert_eq!((found.name, found.tile), (info.name, info.tile));
+            assert_ne!(found.id(), find(info.name).unwrap().id());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tiling_2d")]
+    fn test_with_tile() {
+        let info = find("tiling_2d@2x1").expect("Variant not found");
+        assert_eq!(info.tile, (2, 1));
+        assert_eq!(info.id(), "tiling_2d@2x1:wgpu");
+        assert_eq!(find("tiling_2d@4x4").unwrap().id(), "tiling_2d:wgpu");
+        assert!(matches!(
+            find("tiling_2d@99x1"),
+            Err(MatrixMultiplyError::InvalidTileSize(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_practical() {
+        let single = find("isomorphic:cpu:single").unwrap();
+        assert!(single.practical(1024, 1024, 1024));
+        assert!(!single.practical(2048, 1, 1));
+        assert!(find("isomorphic:cpu:multi")
+            .unwrap()
+            .practical(2048, 2048, 2048));
+    }
+
+    /// The `LocalSize` execution mode of `entry_point`, read straight from the SPIR-V
+    /// rather than from the generated `workgroup_sizes`.
+    fn local_size(entry_point: &str) -> [u32; 3] {
+        use rspirv::dr::Operand::{ExecutionMode as Mode, IdRef, LiteralInt32, LiteralString};
+        use rspirv::spirv::ExecutionMode;
+
+        let spirv = compiled_kernels::spirv(entry_point)
+            .unwrap_or_else(|| panic!("{entry_point} is not in the compiled shader"));
+        let module = rspirv::dr::load_words(spirv).expect("Invalid SPIR-V");
+        let function = module
+            .entry_points
+            .iter()
+            .find_map(|instruction| match instruction.operands.as_slice() {
+                [_, IdRef(function), LiteralString(name), ..] if name == entry_point => {
+                    Some(*function)
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("{entry_point} has no OpEntryPoint"));
+        module
+            .execution_modes
+            .iter()
+            .find_map(|instruction| match instruction.operands.as_slice() {
+                [IdRef(target), Mode(mode), LiteralInt32(x), LiteralInt32(y), LiteralInt32(z)]
+                    if *target == function && *mode == ExecutionMode::LocalSize =>
+                {
+                    Some([*x, *y, *z])
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("{entry_point} has no LocalSize"))
+    }
+
+    #[test]
+    fn test_workgroup_matches_kernel() {
+        for info in variants().into_iter().filter(|info| info.backend.is_gpu()) {
+            let entry_point = info
+                .entry_point
+                .expect("GPU variant without an entry point");
+            assert_eq!(
+                info.workgroup,
+                UVec3::from_array(local_size(entry_point)),
+                "{info}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "isomorphic")]
+    fn test_create_cpu_multiplier() {
+        let multiplier = create("isomorphic:cpu:multi").expect("Failed to create");
+        let result = multiplier
+            .multiply(&[1.0, 2.0], &[3.0], 2, 1, 1)
+            .expect("Matrix multiplication failed");
+        assert_eq!(result, vec![3.0, 6.0]);
+    }
+}
@@ -0,0 +1,236 @@
+//! How close each variant comes to the limits of the adapter it runs on.
+//!
+//! A roofline plots the GFLOPS a kernel reaches against its arithmetic intensity, the
+//! FLOPs it does per byte it loads. Below the adapter's ridge point a kernel can't go
+//! faster than memory feeds it, above it the arithmetic units are the limit.
+//! [`Microbenchmark`] measures both limits with the kernels in `crates/gpu/microbench`
+//! and [`arithmetic_intensity`] estimates where a variant sits from its tile size.
+//!
+//! ```no_run
+//! use matmul::device::{DeviceOptions, GpuContext};
+//! use matmul::roofline::{arithmetic_intensity, Microbenchmark};
+//!
+//! let context = futures::executor::block_on(GpuContext::new(&DeviceOptions::default()))?;
+//! let ceilings = Microbenchmark::new().measure(&context)?;
+//! let tiling_2d = matmul::registry::find("tiling_2d")?;
+//! let limit = ceilings.attainable_gflops(arithmetic_intensity(tiling_2d.tile));
+//! # Ok::<(), matmul::MatrixMultiplyError>(())
+//! ```
+
+use crate::backends::wgpu::time_kernel;
+use crate::device::GpuContext;
+use crate::{Gpu, MatrixMultiplyError};
+use serde::Serialize;
+use settings::{Dimensions, FMA_CHAINS};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use tracing::debug;
+
+/// Threads in each workgroup of the microbenchmark kernels, which all have the same size.
+const WORKGROUP_SIZE: u32 = compiled_kernels::workgroup_sizes::MICROBENCH_COPY[0];
+
+/// The measured limits of an adapter.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Ceilings {
+    /// Memory bandwidth, in GB/s.
+    pub bandwidth_gbps: f64,
+    /// Arithmetic throughput, in GFLOPS.
+    pub peak_gflops: f64,
+}
+
+impl Ceilings {
+    /// The most GFLOPS a kernel doing `intensity` FLOPs per byte can reach.
+    pub fn attainable_gflops(&self, intensity: f64) -> f64 {
+        (self.bandwidth_gbps * intensity).min(self.peak_gflops)
+    }
+
+    /// The intensity above which kernels are limited by arithmetic rather than memory.
+    pub fn ridge_point(&self) -> f64 {
+        self.peak_gflops / self.bandwidth_gbps
+    }
+}
+
+/// The FLOPs per byte loaded of a kernel that computes a `(rows, cols)` tile of the
+/// result per invocation, as in [`crate::registry::VariantInfo::tile`].
+///
+/// Each step along `k` loads `rows` elements of `a` and `cols` elements of `b` and does
+/// a multiply and an add for every element of the tile. Caches serve some of those
+/// loads, so real kernels can do better than this.
+pub fn arithmetic_intensity((rows, cols): (u32, u32)) -> f64 {
+    let flops = 2.0 * rows as f64 * cols as f64;
+    let bytes = (rows + cols) as f64 * std::mem::size_of::<f32>() as f64;
+    flops / bytes
+}
+
+/// Measures the [`Ceilings`] of an adapter.
+///
+/// Both are timed on the CPU around a single dispatch, so they are slight underestimates
+/// on adapters that finish the work quickly.
+#[derive(Clone, Debug)]
+pub struct Microbenchmark {
+    copy_bytes: u64,
+    fma_invocations: u32,
+    fma_rounds: u32,
+    repetitions: u32,
+}
+
+impl Default for Microbenchmark {
+    fn default() -> Self {
+        Self {
+            copy_bytes: 64 << 20,
+            fma_invocations: 1 << 20,
+            fma_rounds: 1024,
+            repetitions: 5,
+        }
+    }
+}
+
+impl Microbenchmark {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How much memory to copy when measuring bandwidth. It is capped at the largest
+    /// storage buffer the device allows.
+    pub fn copy_bytes(mut self, bytes: u64) -> Self {
+        self.copy_bytes = bytes;
+        self
+    }
+
+    /// How many invocations run multiply-adds when measuring peak FLOPS, rounded up to a
+    /// whole workgroup.
+    pub fn fma_invocations(mut self, invocations: u32) -> Self {
+        self.fma_invocations = invocations;
+        self
+    }
+
+    /// How many rounds of multiply-adds each invocation runs.
+    pub fn fma_rounds(mut self, rounds: u32) -> Self {
+        self.fma_rounds = rounds;
+        self
+    }
+
+    /// How many times each kernel runs; the fastest is used.
+    pub fn repetitions(mut self, repetitions: u32) -> Self {
+        self.repetitions = repetitions;
+        self
+    }
+
+    pub fn measure(&self, context: &GpuContext) -> Result<Ceilings, MatrixMultiplyError> {
+        let limits = context.device().limits();
+        let element_size = std::mem::size_of::<f32>() as u64;
+
+        let elements = (self.copy_bytes / element_size)
+            .min(limits.max_storage_buffer_binding_size as u64 / element_size)
+            .max(1) as u32;
+        let workgroups = elements
+            .div_ceil(WORKGROUP_SIZE)
+            .min(limits.max_compute_workgroups_per_dimension);
+        let copy_time = time_kernel(
+            context,
+            &Kernel::Copy,
+            Dimensions::new(elements, 0, 0),
+            &vec![1.0; elements as usize],
+            elements as u64,
+            workgroups,
+            self.repetitions,
+        )?;
+        // Every element is read once and written once.
+        let bytes = 2 * elements as u64 * element_size;
+        let bandwidth_gbps = bytes as f64 / copy_time.as_secs_f64() / 1e9;
+        debug!(bytes, ?copy_time, bandwidth_gbps, "Measured bandwidth");
+
+        let workgroups = self
+            .fma_invocations
+            .div_ceil(WORKGROUP_SIZE)
+            .min(limits.max_compute_workgroups_per_dimension);
+        let invocations = workgroups * WORKGROUP_SIZE;
+        // The factors keep the chains from overflowing however many rounds run.
+        let fma_time = time_kernel(
+            context,
+            &Kernel::Fma,
+            Dimensions::new(invocations, self.fma_rounds, 0),
+            &[0.999, 0.001],
+            invocations as u64,
+            workgroups,
+            self.repetitions,
+        )?;
+        let flops = 2.0 * FMA_CHAINS as f64 * self.fma_rounds as f64 * invocations as f64;
+        let peak_gflops = flops / fma_time.as_secs_f64() / 1e9;
+        debug!(flops, ?fma_time, peak_gflops, "Measured peak FLOPS");
+
+        Ok(Ceilings {
+            bandwidth_gbps,
+            peak_gflops,
+        })
+    }
+}
+
+/// The microbenchmark kernels.
+#[derive(Copy, Clone, Debug)]
+enum Kernel {
+    Copy,
+    Fma,
+}
+
+impl Display for Kernel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.entry_point())
+    }
+}
+
+impl Gpu for Kernel {
+    fn compiled_shader(&self) -> &[u32] {
+        match self {
+            Kernel::Copy => compiled_kernels::shaders::MICROBENCH_COPY,
+            Kernel::Fma => compiled_kernels::shaders::MICROBENCH_FMA,
+        }
+    }
+
+    fn entry_point(&self) -> &'static str {
+        match self {
+            Kernel::Copy => compiled_kernels::entry_points::MICROBENCH_COPY,
+            Kernel::Fma => compiled_kernels::entry_points::MICROBENCH_FMA,
+        }
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        match self {
+            Kernel::Copy => compiled_kernels::capabilities::MICROBENCH_COPY,
+            Kernel::Fma => compiled_kernels::capabilities::MICROBENCH_FMA,
+        }
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Kernel::Copy => compiled_kernels::extensions::MICROBENCH_COPY,
+            Kernel::Fma => compiled_kernels::extensions::MICROBENCH_FMA,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic_intensity() {
+        assert_eq!(arithmetic_intensity((1, 1)), 0.25);
+        assert_eq!(arithmetic_intensity((1, 4)), 0.4);
+        assert_eq!(arithmetic_intensity((4, 4)), 1.0);
+        assert_eq!(arithmetic_intensity((8, 8)), 2.0);
+    }
+
+    #[test]
+    fn test_ceilings() {
+        let ceilings = Ceilings {
+            bandwidth_gbps: 100.0,
+            peak_gflops: 1000.0,
+        };
+        assert_eq!(ceilings.ridge_point(), 10.0);
+        assert_eq!(ceilings.attainable_gflops(0.25), 25.0);
+        assert_eq!(ceilings.attainable_gflops(10.0), 1000.0);
+        assert_eq!(ceilings.attainable_gflops(100.0), 1000.0);
+    }
+}
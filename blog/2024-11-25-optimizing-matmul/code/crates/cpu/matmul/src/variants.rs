@@ -1,31 +1,56 @@
 //! Different implementations of matrix multiplication and the metadata that defines how
 //! they run.
 
-use crate::{Cpu, Gpu, GridComputation};
+#[cfg(feature = "isomorphic")]
+use crate::Cpu;
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+use crate::MatrixMultiplyError;
+use crate::{Gpu, GridComputation};
 use glam::UVec3;
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+use settings::spec_constants;
+#[cfg(feature = "isomorphic")]
 use settings::Dimensions;
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
 
 /// Naive GPU implementation of matrix multiplication.
+#[cfg(feature = "naive")]
 pub struct Naive;
 
+#[cfg(feature = "naive")]
 impl Display for Naive {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "naive")
     }
 }
 
+#[cfg(feature = "naive")]
 impl Gpu for Naive {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_naive::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_NAIVE
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_NAIVE
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_NAIVE
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_NAIVE
     }
 }
 
+#[cfg(feature = "naive")]
 impl GridComputation for Naive {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(1, 1, 1)
+        UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_NAIVE)
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
@@ -34,23 +59,39 @@ impl GridComputation for Naive {
 }
 
 /// GPU implementation of matrix multiplication with a workgroup of 256.
+#[cfg(feature = "workgroup_256")]
 pub struct Workgroup256;
 
+#[cfg(feature = "workgroup_256")]
 impl Display for Workgroup256 {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "workgroup_256")
     }
 }
 
+#[cfg(feature = "workgroup_256")]
 impl Gpu for Workgroup256 {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_workgroup_256::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_WORKGROUP_256
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_WORKGROUP_256
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_WORKGROUP_256
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_WORKGROUP_256
     }
 }
 
+#[cfg(feature = "workgroup_256")]
 impl GridComputation for Workgroup256 {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(256, 1, 1)
+        UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_WORKGROUP_256)
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
@@ -65,139 +106,536 @@ impl GridComputation for Workgroup256 {
 }
 
 /// GPU implementation of matrix multiplication with a two-dimensional workgroup.
+#[cfg(feature = "workgroup_2d")]
 pub struct Workgroup2d;
 
+#[cfg(feature = "workgroup_2d")]
 impl Display for Workgroup2d {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "workgroup_2d")
     }
 }
 
+#[cfg(feature = "workgroup_2d")]
 impl Gpu for Workgroup2d {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_workgroup_2d::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_WORKGROUP_2D
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_WORKGROUP_2D
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_WORKGROUP_2D
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_WORKGROUP_2D
     }
 }
 
+#[cfg(feature = "workgroup_2d")]
 impl GridComputation for Workgroup2d {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(16, 16, 1)
+        UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_WORKGROUP_2D)
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
-        let w = self.workgroup();
-        let workgroup_size = w.x + w.y;
-        let x = ((m as f32) / (workgroup_size as f32)).ceil() as u32;
-        let y = ((n as f32) / (workgroup_size as f32)).ceil() as u32;
-        UVec3::new(x, y, 1)
+        // Each thread computes one element, `x` walks the rows and `y` the columns.
+        let workgroup = self.workgroup();
+        UVec3::new(m.div_ceil(workgroup.x), n.div_ceil(workgroup.y), 1)
     }
 }
 
 /// GPU implementation of matrix multiplication with one-dimensional tiling.
-pub struct Tiling1d;
+///
+/// The tile and workgroup sizes are specialization constants, so they can be changed
+/// without recompiling the kernel.
+#[cfg(feature = "tiling_1d")]
+pub struct Tiling1d {
+    tile_size: u32,
+    workgroup: UVec3,
+}
+
+#[cfg(feature = "tiling_1d")]
+impl Tiling1d {
+    /// Computes `tile_size` elements of a row per thread, up to
+    /// [`settings::MAX_TILE_SIZE`].
+    pub fn new(tile_size: u32) -> Result<Self, MatrixMultiplyError> {
+        if !(1..=settings::MAX_TILE_SIZE).contains(&tile_size) {
+            return Err(MatrixMultiplyError::InvalidTileSize(format!(
+                "1x{tile_size}, the tiling_1d kernel supports up to 1x{}",
+                settings::MAX_TILE_SIZE
+            )));
+        }
+        Ok(Self {
+            tile_size,
+            ..Self::default()
+        })
+    }
+
+    /// Runs `x` by `y` invocations per workgroup instead of the kernel's default, up to
+    /// [`settings::MAX_WORKGROUP_INVOCATIONS`].
+    pub fn with_workgroup(self, x: u32, y: u32) -> Result<Self, MatrixMultiplyError> {
+        Ok(Self {
+            workgroup: workgroup_size("tiling_1d", x, y)?,
+            ..self
+        })
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+}
 
+#[cfg(feature = "tiling_1d")]
+impl Default for Tiling1d {
+    fn default() -> Self {
+        Self {
+            tile_size: settings::TILE_SIZE,
+            workgroup: UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_TILING_1D),
+        }
+    }
+}
+
+#[cfg(feature = "tiling_1d")]
 impl Display for Tiling1d {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "tiling_1d")
+        write!(f, "tiling_1d")?;
+        let tile = (self.tile_size != settings::TILE_SIZE).then(|| format!("1x{}", self.tile_size));
+        write_specialization(
+            f,
+            tile,
+            self.workgroup,
+            compiled_kernels::workgroup_sizes::MATMUL_TILING_1D,
+        )
     }
 }
 
+#[cfg(feature = "tiling_1d")]
 impl Gpu for Tiling1d {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_tiling_1d::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_TILING_1D
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_TILING_1D
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_TILING_1D
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_TILING_1D
+    }
+
+    fn pipeline_constants(&self) -> HashMap<String, f64> {
+        let mut constants = workgroup_constants(self.workgroup);
+        constants.insert(spec_constants::TILE_SIZE.to_string(), self.tile_size as f64);
+        constants
     }
 }
 
+#[cfg(feature = "tiling_1d")]
 impl GridComputation for Tiling1d {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(16, 16, 1)
+        self.workgroup
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+        // Each thread computes `tile_size` columns of one row.
         let workgroup = self.workgroup();
         UVec3::new(
-            (m + workgroup.x - 1) / workgroup.x,
-            (n + workgroup.y - 1) / workgroup.y,
+            n.div_ceil(workgroup.x * self.tile_size),
+            m.div_ceil(workgroup.y),
             1,
         )
     }
+
+    fn tile(&self) -> (u32, u32) {
+        (1, self.tile_size)
+    }
 }
 
 /// GPU implementation of matrix multiplication with one-dimensional tiling (using loops).
+#[cfg(feature = "tiling_1d_loop")]
 pub struct Tiling1dLoop;
 
+#[cfg(feature = "tiling_1d_loop")]
 impl Display for Tiling1dLoop {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "tiling_1d_loop")
     }
 }
 
+#[cfg(feature = "tiling_1d_loop")]
 impl Gpu for Tiling1dLoop {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_tiling_1d_loop::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_TILING_1D_LOOP
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_TILING_1D_LOOP
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_TILING_1D_LOOP
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_TILING_1D_LOOP
     }
 }
 
+#[cfg(feature = "tiling_1d_loop")]
 impl GridComputation for Tiling1dLoop {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(16, 16, 1)
+        UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_TILING_1D_LOOP)
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+        // Each thread computes `TILE_SIZE` columns of one row.
         let workgroup = self.workgroup();
         UVec3::new(
-            (m + workgroup.x - 1) / workgroup.x,
-            (n + workgroup.y - 1) / workgroup.y,
+            n.div_ceil(workgroup.x * settings::TILE_SIZE),
+            m.div_ceil(workgroup.y),
             1,
         )
     }
+
+    fn tile(&self) -> (u32, u32) {
+        (1, settings::TILE_SIZE)
+    }
 }
 
 /// GPU implementation of matrix multiplication with two-dimensional tiling.
-pub struct Tiling2d;
+///
+/// The tile and workgroup sizes are specialization constants, so they can be changed
+/// without recompiling the kernel.
+#[cfg(feature = "tiling_2d")]
+pub struct Tiling2d {
+    tile_m: u32,
+    tile_n: u32,
+    workgroup: UVec3,
+}
 
+#[cfg(feature = "tiling_2d")]
+impl Tiling2d {
+    /// Computes a `tile_m` by `tile_n` tile per thread, up to
+    /// [`settings::MAX_TILE_M`] by [`settings::MAX_TILE_N`].
+    pub fn new(tile_m: u32, tile_n: u32) -> Result<Self, MatrixMultiplyError> {
+        if !(1..=settings::MAX_TILE_M).contains(&tile_m)
+            || !(1..=settings::MAX_TILE_N).contains(&tile_n)
+        {
+            return Err(MatrixMultiplyError::InvalidTileSize(format!(
+                "{tile_m}x{tile_n}, the tiling_2d kernel supports up to {}x{}",
+                settings::MAX_TILE_M,
+                settings::MAX_TILE_N
+            )));
+        }
+        Ok(Self {
+            tile_m,
+            tile_n,
+            ..Self::default()
+        })
+    }
+
+    /// Runs `x` by `y` invocations per workgroup instead of the kernel's default, up to
+    /// [`settings::MAX_WORKGROUP_INVOCATIONS`].
+    pub fn with_workgroup(self, x: u32, y: u32) -> Result<Self, MatrixMultiplyError> {
+        Ok(Self {
+            workgroup: workgroup_size("tiling_2d", x, y)?,
+            ..self
+        })
+    }
+
+    pub fn tile_m(&self) -> u32 {
+        self.tile_m
+    }
+
+    pub fn tile_n(&self) -> u32 {
+        self.tile_n
+    }
+}
+
+#[cfg(feature = "tiling_2d")]
+impl Default for Tiling2d {
+    fn default() -> Self {
+        Self {
+            tile_m: settings::TILE_M,
+            tile_n: settings::TILE_N,
+            workgroup: UVec3::from_array(compiled_kernels::workgroup_sizes::MATMUL_TILING_2D),
+        }
+    }
+}
+
+#[cfg(feature = "tiling_2d")]
 impl Display for Tiling2d {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "tiling_2d")
+        write!(f, "tiling_2d")?;
+        let tile = ((self.tile_m, self.tile_n) != (settings::TILE_M, settings::TILE_N))
+            .then(|| format!("{}x{}", self.tile_m, self.tile_n));
+        write_specialization(
+            f,
+            tile,
+            self.workgroup,
+            compiled_kernels::workgroup_sizes::MATMUL_TILING_2D,
+        )
     }
 }
 
+#[cfg(feature = "tiling_2d")]
 impl Gpu for Tiling2d {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_tiling_2d::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        compiled_kernels::shaders::MATMUL_TILING_2D
+    }
+
+    fn entry_point(&self) -> &'static str {
+        compiled_kernels::entry_points::MATMUL_TILING_2D
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        compiled_kernels::capabilities::MATMUL_TILING_2D
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        compiled_kernels::extensions::MATMUL_TILING_2D
+    }
+
+    fn pipeline_constants(&self) -> HashMap<String, f64> {
+        let mut constants = workgroup_constants(self.workgroup);
+        constants.insert(spec_constants::TILE_M.to_string(), self.tile_m as f64);
+        constants.insert(spec_constants::TILE_N.to_string(), self.tile_n as f64);
+        constants
     }
 }
 
+#[cfg(feature = "tiling_2d")]
 impl GridComputation for Tiling2d {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(16, 16, 1)
+        self.workgroup
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+        // Each thread computes a `tile_m` by `tile_n` block of the result.
         let workgroup = self.workgroup();
         UVec3::new(
-            (m + workgroup.x - 1) / workgroup.x,
-            (n + workgroup.y - 1) / workgroup.y,
+            n.div_ceil(workgroup.x * self.tile_n),
+            m.div_ceil(workgroup.y * self.tile_m),
             1,
         )
     }
+
+    fn tile(&self) -> (u32, u32) {
+        (self.tile_m, self.tile_n)
+    }
+}
+
+/// Checks a workgroup size for one of the tiling kernels, which can be specialized to
+/// any size with up to [`settings::MAX_WORKGROUP_INVOCATIONS`] invocations.
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+fn workgroup_size(kernel: &str, x: u32, y: u32) -> Result<UVec3, MatrixMultiplyError> {
+    let invocations = x.saturating_mul(y);
+    if invocations == 0 || invocations > settings::MAX_WORKGROUP_INVOCATIONS {
+        return Err(MatrixMultiplyError::InvalidWorkgroupSize(format!(
+            "{x}x{y}, the {kernel} kernel supports up to {} invocations",
+            settings::MAX_WORKGROUP_INVOCATIONS
+        )));
+    }
+    Ok(UVec3::new(x, y, 1))
+}
+
+/// The pipeline constants that specialize a tiling kernel to `workgroup`.
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+fn workgroup_constants(workgroup: UVec3) -> HashMap<String, f64> {
+    HashMap::from([
+        (spec_constants::WORKGROUP_X.to_string(), workgroup.x as f64),
+        (spec_constants::WORKGROUP_Y.to_string(), workgroup.y as f64),
+    ])
+}
+
+/// Writes the tile and workgroup sizes a tiling kernel was specialized to, when they
+/// aren't its defaults, e.g. ` (2x8, 8x32 workgroup)`.
+#[cfg(any(feature = "tiling_1d", feature = "tiling_2d"))]
+fn write_specialization(
+    f: &mut Formatter<'_>,
+    tile: Option<String>,
+    workgroup: UVec3,
+    default_workgroup: [u32; 3],
+) -> fmt::Result {
+    let mut sizes = Vec::from_iter(tile);
+    if workgroup.to_array() != default_workgroup {
+        sizes.push(format!("{}x{} workgroup", workgroup.x, workgroup.y));
+    }
+    if !sizes.is_empty() {
+        write!(f, " ({})", sizes.join(", "))?;
+    }
+    Ok(())
 }
 
 /// GPU implementation of matrix multiplication that runs on both the CPU and GPU.
+///
+/// Each invocation computes a `TILE_M` by `TILE_N` block of the result. The CPU can run
+/// any tile size, but the GPU only has entry points for 1x1, 2x2, 4x4 and 8x8; using
+/// another size on the GPU fails to compile.
+///
+/// ```
+/// use matmul::Gpu;
+/// use matmul::variants::IsomorphicTiled;
+///
+/// assert_eq!(IsomorphicTiled::<8, 8>.entry_point(), "matmul_isomorphic_8x8");
+/// ```
+///
+/// ```compile_fail,E0080
+/// use matmul::Gpu;
+/// use matmul::variants::IsomorphicTiled;
+///
+/// IsomorphicTiled::<3, 3>.entry_point();
+/// ```
+#[cfg(feature = "isomorphic")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IsomorphicTiled<const TILE_M: usize, const TILE_N: usize>;
+
+/// The shader and entry point compiled for one tile size of [`IsomorphicTiled`].
+#[cfg(feature = "isomorphic")]
+struct CompiledTile {
+    shader: &'static [u32],
+    entry_point: &'static str,
+    workgroup: [u32; 3],
+    capabilities: &'static [&'static str],
+    extensions: &'static [&'static str],
+}
+
+#[cfg(feature = "isomorphic")]
+macro_rules! compiled_tile {
+    ($name:ident) => {
+        CompiledTile {
+            shader: compiled_kernels::shaders::$name,
+            entry_point: compiled_kernels::entry_points::$name,
+            workgroup: compiled_kernels::workgroup_sizes::$name,
+            capabilities: compiled_kernels::capabilities::$name,
+            extensions: compiled_kernels::extensions::$name,
+        }
+    };
+}
+
+#[cfg(feature = "isomorphic")]
+impl<const TILE_M: usize, const TILE_N: usize> IsomorphicTiled<TILE_M, TILE_N> {
+    // Evaluated when the GPU methods are instantiated, so an unsupported size is a
+    // compile error rather than a panic.
+    const COMPILED: CompiledTile = match (TILE_M, TILE_N) {
+        (1, 1) => compiled_tile!(MATMUL_ISOMORPHIC_1X1),
+        (2, 2) => compiled_tile!(MATMUL_ISOMORPHIC_2X2),
+        (4, 4) => compiled_tile!(MATMUL_ISOMORPHIC_4X4),
+        (8, 8) => compiled_tile!(MATMUL_ISOMORPHIC_8X8),
+        _ => panic!("no isomorphic entry point is compiled for this tile size"),
+    };
+}
+
+#[cfg(feature = "isomorphic")]
+impl<const TILE_M: usize, const TILE_N: usize> Display for IsomorphicTiled<TILE_M, TILE_N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "isomorphic_{TILE_M}x{TILE_N}")
+    }
+}
+
+#[cfg(feature = "isomorphic")]
+impl<const TILE_M: usize, const TILE_N: usize> Gpu for IsomorphicTiled<TILE_M, TILE_N> {
+    fn compiled_shader(&self) -> &[u32] {
+        Self::COMPILED.shader
+    }
+
+    fn entry_point(&self) -> &'static str {
+        Self::COMPILED.entry_point
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        Self::COMPILED.capabilities
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        Self::COMPILED.extensions
+    }
+}
+
+#[cfg(feature = "isomorphic")]
+impl<const TILE_M: usize, const TILE_N: usize> Cpu for IsomorphicTiled<TILE_M, TILE_N>
+where
+    [[f32; TILE_N]; TILE_M]: Default,
+{
+    fn call(
+        &self,
+        global_id: UVec3,
+        dimensions: &Dimensions,
+        a: &[f32],
+        b: &[f32],
+        results: &mut [f32],
+    ) {
+        ::isomorphic::matmul::<TILE_M, TILE_N>(global_id, dimensions, a, b, results);
+    }
+}
+
+#[cfg(feature = "isomorphic")]
+impl<const TILE_M: usize, const TILE_N: usize> GridComputation for IsomorphicTiled<TILE_M, TILE_N> {
+    fn workgroup(&self) -> UVec3 {
+        UVec3::from_array(Self::COMPILED.workgroup)
+    }
+
+    fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+        // Each thread computes a `TILE_M` by `TILE_N` block of the result.
+        let workgroup = self.workgroup();
+        UVec3::new(
+            n.div_ceil(workgroup.x * TILE_N as u32),
+            m.div_ceil(workgroup.y * TILE_M as u32),
+            1,
+        )
+    }
+
+    fn tile(&self) -> (u32, u32) {
+        (TILE_M as u32, TILE_N as u32)
+    }
+}
+
+/// The tile size `Isomorphic` runs with.
+#[cfg(feature = "isomorphic")]
+static DEFAULT_TILING: IsomorphicTiled<
+    { settings::TILE_M as usize },
+    { settings::TILE_N as usize },
+> = IsomorphicTiled;
+
+/// The isomorphic kernel with the tile size from `settings`.
+#[cfg(feature = "isomorphic")]
 pub struct Isomorphic;
 
+#[cfg(feature = "isomorphic")]
 impl Display for Isomorphic {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "isomorphic")
     }
 }
 
+#[cfg(feature = "isomorphic")]
 impl Gpu for Isomorphic {
-    fn compiled_shader(&self) -> &[u8] {
-        compiled_isomorphic::SHADER_BINARY
+    fn compiled_shader(&self) -> &[u32] {
+        DEFAULT_TILING.compiled_shader()
+    }
+
+    fn entry_point(&self) -> &'static str {
+        DEFAULT_TILING.entry_point()
+    }
+
+    fn capabilities(&self) -> &'static [&'static str] {
+        DEFAULT_TILING.capabilities()
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        DEFAULT_TILING.extensions()
     }
 }
 
+#[cfg(feature = "isomorphic")]
 impl Cpu for Isomorphic {
     fn call(
         &self,
@@ -207,21 +645,187 @@ impl Cpu for Isomorphic {
         b: &[f32],
         results: &mut [f32],
     ) {
-        ::isomorphic::matmul(global_id, &dimensions, &a, &b, results);
+        DEFAULT_TILING.call(global_id, dimensions, a, b, results);
     }
 }
 
+#[cfg(feature = "isomorphic")]
 impl GridComputation for Isomorphic {
     fn workgroup(&self) -> UVec3 {
-        UVec3::new(16, 16, 1)
+        DEFAULT_TILING.workgroup()
     }
 
     fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
-        let workgroup = self.workgroup();
-        UVec3::new(
-            (m + workgroup.x - 1) / workgroup.x,
-            (n + workgroup.y - 1) / workgroup.y,
-            1,
-        )
+        DEFAULT_TILING.dispatch_count(m, n)
+    }
+
+    fn tile(&self) -> (u32, u32) {
+        DEFAULT_TILING.tile()
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "naive",
+        feature = "workgroup_256",
+        feature = "workgroup_2d",
+        feature = "tiling_1d",
+        feature = "tiling_1d_loop",
+        feature = "tiling_2d"
+    )
+))]
+mod tests {
+    use super::*;
+    use crate::backends::cpu::SingleThreadedMatMul;
+    use crate::verify::Verifier;
+    use crate::{Cpu, MatrixMultiply};
+    use futures::executor::block_on;
+    use settings::Dimensions;
+
+    /// A variant whose GPU kernel runs on the CPU backend, so its dispatch geometry can
+    /// be checked without an adapter.
+    struct Simulated<V, K> {
+        variant: V,
+        kernel: K,
+    }
+
+    impl<V: Display, K> Display for Simulated<V, K> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            self.variant.fmt(f)
+        }
+    }
+
+    impl<V, K> Cpu for Simulated<V, K>
+    where
+        K: Fn(UVec3, &Dimensions, &[f32], &[f32], &mut [f32]),
+    {
+        fn call(
+            &self,
+            global_id: UVec3,
+            dimensions: &Dimensions,
+            a: &[f32],
+            b: &[f32],
+            results: &mut [f32],
+        ) {
+            (self.kernel)(global_id, dimensions, a, b, results);
+        }
+    }
+
+    impl<V: GridComputation, K> GridComputation for Simulated<V, K> {
+        fn workgroup(&self) -> UVec3 {
+            self.variant.workgroup()
+        }
+
+        fn dispatch_count(&self, m: u32, n: u32) -> UVec3 {
+            self.variant.dispatch_count(m, n)
+        }
+    }
+
+    /// Checks that dispatching `variant` covers the whole result, for shapes taller,
+    /// wider and less even than a workgroup.
+    fn check_dispatch<V, K>(variant: V, kernel: K)
+    where
+        V: GridComputation + Display + Send + Sync,
+        K: Fn(UVec3, &Dimensions, &[f32], &[f32], &mut [f32]) + Send + Sync,
+    {
+        let multiplier =
+            block_on(SingleThreadedMatMul::new(Simulated { variant, kernel })).unwrap();
+        for (m, k, n) in [(1, 1, 1), (70, 5, 3), (3, 5, 70), (33, 17, 31)] {
+            let a = (0..m * k).map(|i| (i % 7) as f32 - 3.0).collect::<Vec<_>>();
+            let b = (0..k * n).map(|i| (i % 5) as f32 - 2.0).collect::<Vec<_>>();
+            let result = multiplier.multiply(&a, &b, m, k, n).unwrap();
+            let report = Verifier::new().verify(&a, &b, &result, m, k, n).unwrap();
+            assert!(report.passed(), "{multiplier} {m}x{k}x{n}: {report}");
+        }
+    }
+
+    #[cfg(feature = "naive")]
+    #[test]
+    fn test_naive_dispatch() {
+        check_dispatch(Naive, naive::matmul_naive);
+    }
+
+    #[cfg(feature = "workgroup_256")]
+    #[test]
+    fn test_workgroup_256_dispatch() {
+        check_dispatch(Workgroup256, workgroup_256::matmul_workgroup_256);
+    }
+
+    #[cfg(feature = "workgroup_2d")]
+    #[test]
+    fn test_workgroup_2d_dispatch() {
+        check_dispatch(Workgroup2d, workgroup_2d::matmul_workgroup_2d);
+    }
+
+    #[cfg(feature = "tiling_1d")]
+    #[test]
+    fn test_tiling_1d_dispatch() {
+        for tile_size in 1..=settings::MAX_TILE_SIZE {
+            check_dispatch(Tiling1d::new(tile_size).unwrap(), move |id, d, a, b, r| {
+                tiling_1d::matmul_tiling_1d(id, d, a, b, r, tile_size)
+            });
+        }
+    }
+
+    #[cfg(feature = "tiling_1d_loop")]
+    #[test]
+    fn test_tiling_1d_loop_dispatch() {
+        check_dispatch(Tiling1dLoop, tiling_1d_loop::matmul_tiling_1d_loop);
+    }
+
+    #[cfg(feature = "tiling_2d")]
+    #[test]
+    fn test_tiling_2d_dispatch() {
+        for (tile_m, tile_n) in [(1, 1), (1, 4), (3, 2), (4, 4), (8, 2), (8, 8)] {
+            check_dispatch(
+                Tiling2d::new(tile_m, tile_n).unwrap(),
+                move |id, d, a, b, r| tiling_2d::matmul_tiling_2d(id, d, a, b, r, tile_m, tile_n),
+            );
+        }
+    }
+
+    #[cfg(feature = "tiling_1d")]
+    #[test]
+    fn test_tiling_1d_workgroup_dispatch() {
+        for (x, y) in [(1, 1), (64, 1), (8, 32)] {
+            let variant = Tiling1d::new(3).unwrap().with_workgroup(x, y).unwrap();
+            assert_eq!(variant.workgroup(), UVec3::new(x, y, 1));
+            check_dispatch(variant, |id, d, a, b, r| {
+                tiling_1d::matmul_tiling_1d(id, d, a, b, r, 3)
+            });
+        }
+    }
+
+    #[cfg(feature = "tiling_2d")]
+    #[test]
+    fn test_tiling_2d_workgroup_dispatch() {
+        for (x, y) in [(1, 1), (32, 2), (4, 64)] {
+            let variant = Tiling2d::new(2, 8).unwrap().with_workgroup(x, y).unwrap();
+            assert_eq!(variant.workgroup(), UVec3::new(x, y, 1));
+            check_dispatch(variant, |id, d, a, b, r| {
+                tiling_2d::matmul_tiling_2d(id, d, a, b, r, 2, 8)
+            });
+        }
+    }
+
+    #[cfg(feature = "tiling_2d")]
+    #[test]
+    fn test_invalid_workgroup() {
+        for (x, y) in [(0, 16), (16, 0), (32, 16), (u32::MAX, 2)] {
+            assert!(matches!(
+                Tiling2d::default().with_workgroup(x, y),
+                Err(MatrixMultiplyError::InvalidWorkgroupSize(_))
+            ));
+        }
+        let variant = Tiling2d::new(2, 8).unwrap().with_workgroup(8, 32).unwrap();
+        assert_eq!(variant.to_string(), "tiling_2d (2x8, 8x32 workgroup)");
+        assert_eq!(
+            Tiling2d::default()
+                .with_workgroup(8, 32)
+                .unwrap()
+                .to_string(),
+            "tiling_2d (8x32 workgroup)"
+        );
     }
 }
@@ -0,0 +1,385 @@
+//! Checking a multiplication's result against a reference computed in `f64`.
+//!
+//! ```
+//! use matmul::verify::{Tolerance, Verifier};
+//!
+//! let (a, b) = ([1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]);
+//! let result = [19.0, 22.0, 43.0, 50.0];
+//! let report = Verifier::new()
+//!     .tolerance(Tolerance::default().relative(1e-6))
+//!     .verify(&a, &b, &result, 2, 2, 2)?;
+//! assert!(report.passed(), "{report}");
+//! # Ok::<(), matmul::MatrixMultiplyError>(())
+//! ```
+
+use crate::MatrixMultiplyError;
+use rayon::prelude::*;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// The number of buckets in [`Report::ulp_histogram`].
+pub const ULP_BUCKETS: usize = 12;
+
+/// How far a result may be from the reference.
+///
+/// Rounding error in a dot product grows with its length and with the size of the
+/// terms being summed, so an element passes when
+/// `|actual - expected| <= absolute + relative * k * sum(|a[i][l] * b[l][j]|)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    /// Allows a couple of `f32` roundings per term, which any summation order should
+    /// stay within.
+    fn default() -> Self {
+        Self {
+            absolute: 1e-6,
+            relative: 2.0 * f32::EPSILON as f64,
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn absolute(mut self, absolute: f64) -> Self {
+        self.absolute = absolute;
+        self
+    }
+
+    /// The allowed error per term, relative to the size of the terms.
+    pub fn relative(mut self, relative: f64) -> Self {
+        self.relative = relative;
+        self
+    }
+
+    /// The largest error allowed for a dot product of `k` terms whose absolute values
+    /// sum to `magnitude`.
+    pub fn allowed(&self, k: u32, magnitude: f64) -> f64 {
+        self.absolute + self.relative * k as f64 * magnitude
+    }
+}
+
+/// Compares results to a reference. See the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    tolerance: Tolerance,
+    max_reported: usize,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self {
+            tolerance: Tolerance::default(),
+            max_reported: 10,
+        }
+    }
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// How many mismatching elements to list in the report. Defaults to 10.
+    pub fn max_reported(mut self, max_reported: usize) -> Self {
+        self.max_reported = max_reported;
+        self
+    }
+
+    /// Checks every element of `result`, the `m x n` product of the `m x k` matrix `a`
+    /// and the `k x n` matrix `b`, all in row-major order.
+    pub fn verify(
+        &self,
+        a: &[f32],
+        b: &[f32],
+        result: &[f32],
+        m: u32,
+        k: u32,
+        n: u32,
+    ) -> Result<Report, MatrixMultiplyError> {
+        check_len("a", a, m, k)?;
+        check_len("b", b, k, n)?;
+        check_len("result", result, m, n)?;
+
+        let (m, k, n) = (m as usize, k as usize, n as usize);
+        // Rows are independent, so check them in parallel and merge the reports in
+        // order so the first mismatches listed are the first in the matrix.
+        let rows = (0..m)
+            .into_par_iter()
+            .map(|row| {
+                let mut report = Report::default();
+                for col in 0..n {
+                    let (expected, magnitude) = (0..k)
+                        .map(|l| a[row * k + l] as f64 * b[l * n + col] as f64)
+                        .fold((0.0, 0.0), |(sum, magnitude), term| {
+                            (sum + term, magnitude + term.abs())
+                        });
+                    let allowed = self.tolerance.allowed(k as u32, magnitude);
+                    report.check(
+                        row as u32,
+                        col as u32,
+                        expected,
+                        result[row * n + col],
+                        allowed,
+                        self.max_reported,
+                    );
+                }
+                report
+            })
+            .collect::<Vec<_>>();
+
+        Ok(rows.into_iter().fold(Report::default(), |mut report, row| {
+            report.merge(row, self.max_reported);
+            report
+        }))
+    }
+}
+
+fn check_len(name: &str, matrix: &[f32], rows: u32, cols: u32) -> Result<(), MatrixMultiplyError> {
+    let expected = rows as usize * cols as usize;
+    if matrix.len() != expected {
+        return Err(MatrixMultiplyError::MatrixSize(format!(
+            "{name} has {} elements, a {rows}x{cols} matrix has {expected}",
+            matrix.len()
+        )));
+    }
+    Ok(())
+}
+
+/// An element outside the tolerance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub row: u32,
+    pub col: u32,
+    pub expected: f64,
+    pub actual: f32,
+    /// The largest error the tolerance allowed for this element.
+    pub allowed: f64,
+}
+
+/// How far a result is from the reference.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// The number of elements checked.
+    pub checked: usize,
+    /// The number of elements outside the tolerance, including any that are NaN.
+    pub mismatches: usize,
+    pub max_abs_error: f64,
+    /// The largest error relative to the expected value, over elements whose expected
+    /// value is not zero.
+    pub max_rel_error: f64,
+    /// The largest distance in units in the last place between an element and the
+    /// reference rounded to `f32`.
+    pub max_ulps: u64,
+    /// How many elements are how many ULPs away from the reference. Bucket 0 counts
+    /// exact matches and bucket `i` counts distances in `2^(i-1)..2^i`; the last bucket
+    /// also counts everything further away.
+    pub ulp_histogram: [usize; ULP_BUCKETS],
+    /// The first mismatching elements, in row-major order.
+    pub first_mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.mismatches == 0
+    }
+
+    fn check(
+        &mut self,
+        row: u32,
+        col: u32,
+        expected: f64,
+        actual: f32,
+        allowed: f64,
+        max_reported: usize,
+    ) {
+        self.checked += 1;
+
+        let ulps = ulp_distance(actual, expected as f32);
+        self.max_ulps = self.max_ulps.max(ulps);
+        self.ulp_histogram[ulp_bucket(ulps)] += 1;
+
+        let abs_error = (actual as f64 - expected).abs();
+        self.max_abs_error = max_or_nan(self.max_abs_error, abs_error);
+        if expected != 0.0 {
+            self.max_rel_error = max_or_nan(self.max_rel_error, abs_error / expected.abs());
+        }
+
+        if abs_error.is_nan() || abs_error > allowed {
+            self.mismatches += 1;
+            if self.first_mismatches.len() < max_reported {
+                self.first_mismatches.push(Mismatch {
+                    row,
+                    col,
+                    expected,
+                    actual,
+                    allowed,
+                });
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Report, max_reported: usize) {
+        self.checked += other.checked;
+        self.mismatches += other.mismatches;
+        self.max_abs_error = max_or_nan(self.max_abs_error, other.max_abs_error);
+        self.max_rel_error = max_or_nan(self.max_rel_error, other.max_rel_error);
+        self.max_ulps = self.max_ulps.max(other.max_ulps);
+        for (bucket, count) in self.ulp_histogram.iter_mut().zip(other.ulp_histogram) {
+            *bucket += count;
+        }
+        let remaining = max_reported.saturating_sub(self.first_mismatches.len());
+        self.first_mismatches
+            .extend(other.first_mismatches.into_iter().take(remaining));
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} elements mismatched, max abs error {:e}, max rel error {:e}, max {} ULPs",
+            self.mismatches, self.checked, self.max_abs_error, self.max_rel_error, self.max_ulps
+        )?;
+        for mismatch in &self.first_mismatches {
+            write!(
+                f,
+                "\n  [{}, {}]: expected {}, got {} (allowed error {:e})",
+                mismatch.row, mismatch.col, mismatch.expected, mismatch.actual, mismatch.allowed
+            )?;
+        }
+        if self.mismatches > self.first_mismatches.len() {
+            write!(
+                f,
+                "\n  and {} more",
+                self.mismatches - self.first_mismatches.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `max` that keeps NaN, so one NaN element shows up in the report.
+fn max_or_nan(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+/// The number of representable `f32`s between `a` and `b`. NaN is infinitely far from
+/// everything.
+fn ulp_distance(a: f32, b: f32) -> u64 {
+    if a.is_nan() || b.is_nan() {
+        return u64::MAX;
+    }
+    // Maps floats onto integers in the same order, with -0.0 and 0.0 both at 0.
+    let ordered = |x: f32| {
+        let bits = x.to_bits() as i32;
+        if bits < 0 {
+            -((bits & i32::MAX) as i64)
+        } else {
+            bits as i64
+        }
+    };
+    ordered(a).abs_diff(ordered(b))
+}
+
+fn ulp_bucket(ulps: u64) -> usize {
+    let bucket = (u64::BITS - ulps.leading_zeros()) as usize;
+    bucket.min(ULP_BUCKETS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(size: u32) -> Vec<f32> {
+        (0..size * size)
+            .map(|i| if i % (size + 1) == 0 { 1.0 } else { 0.0 })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_result_passes() {
+        let a = (0..12).map(|i| i as f32).collect::<Vec<_>>();
+        let report = Verifier::new()
+            .verify(&a, &identity(4), &a, 3, 4, 4)
+            .unwrap();
+        assert!(report.passed(), "{report}");
+        assert_eq!(report.checked, 12);
+        assert_eq!(report.max_ulps, 0);
+        assert_eq!(report.ulp_histogram[0], 12);
+    }
+
+    #[test]
+    fn test_reports_mismatches_in_order() {
+        let a = vec![1.0; 9];
+        let mut result = a.clone();
+        result[1] = 1.5;
+        result[7] = f32::NAN;
+        result[8] = 1.0 + f32::EPSILON;
+
+        let report = Verifier::new()
+            .max_reported(1)
+            .verify(&a, &identity(3), &result, 3, 3, 3)
+            .unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.mismatches, 2);
+        assert_eq!(
+            report.first_mismatches,
+            [Mismatch {
+                row: 0,
+                col: 1,
+                expected: 1.0,
+                actual: 1.5,
+                allowed: Tolerance::default().allowed(3, 1.0),
+            }]
+        );
+        assert!(report.max_abs_error.is_nan());
+        // The last element is one ULP off but within the tolerance.
+        assert_eq!(report.ulp_histogram[1], 1);
+        assert_eq!(report.ulp_histogram[ULP_BUCKETS - 1], 2);
+    }
+
+    #[test]
+    fn test_zero_expected_is_not_divided_by() {
+        let a = vec![0.0; 4];
+        let report = Verifier::new()
+            .verify(&a, &a, &[0.0, 0.0, 0.0, 1e-9], 2, 2, 2)
+            .unwrap();
+        assert!(report.passed(), "{report}");
+        assert_eq!(report.max_rel_error, 0.0);
+        assert_eq!(report.max_abs_error, 1e-9_f32 as f64);
+    }
+
+    #[test]
+    fn test_wrong_sizes() {
+        assert!(matches!(
+            Verifier::new().verify(&[1.0; 4], &[1.0; 4], &[1.0; 3], 2, 2, 2),
+            Err(MatrixMultiplyError::MatrixSize(_))
+        ));
+    }
+
+    #[test]
+    fn test_ulp_distance() {
+        assert_eq!(ulp_distance(1.0, 1.0), 0);
+        assert_eq!(ulp_distance(-0.0, 0.0), 0);
+        assert_eq!(ulp_distance(1.0, 1.0 + f32::EPSILON), 1);
+        assert_eq!(ulp_distance(f32::from_bits(1), -f32::from_bits(1)), 2);
+        assert_eq!(ulp_distance(f32::NAN, 1.0), u64::MAX);
+        assert_eq!(ulp_bucket(0), 0);
+        assert_eq!(ulp_bucket(1), 1);
+        assert_eq!(ulp_bucket(3), 2);
+        assert_eq!(ulp_bucket(u64::MAX), ULP_BUCKETS - 1);
+    }
+}
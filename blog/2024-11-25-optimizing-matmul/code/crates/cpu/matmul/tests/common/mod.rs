@@ -0,0 +1,97 @@
+//! Finding a GPU for the integration tests.
+//!
+//! CI machines have no GPU, so setting `MATMUL_TEST_SOFTWARE_GPU` makes the tests run on
+//! a software adapter instead:
+//!
+//! - `MATMUL_TEST_SOFTWARE_GPU=1` asks `wgpu` for its fallback adapter, such as llvmpipe,
+//!   lavapipe or WARP.
+//! - `MATMUL_TEST_SOFTWARE_GPU=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json` uses the
+//!   Vulkan driver of that ICD manifest, for machines where it isn't installed
+//!   system-wide. The loader only reads it from its own variables, so export
+//!   `VK_DRIVER_FILES` (or `VK_ICD_FILENAMES` for older loaders) with the same path; the
+//!   tests don't set them, as changing the environment of a running test binary races
+//!   with the other threads reading it.
+//!
+//! Otherwise the default adapter is used, and tests that need a GPU skip it and say why
+//! when there is none. With `MATMUL_TEST_SOFTWARE_GPU` set they fail instead, so a CI
+//! job that lost its software adapter doesn't quietly pass without running anything.
+
+use futures::executor::block_on;
+use matmul::device::{DeviceOptions, GpuContext};
+use matmul::registry::VariantInfo;
+use matmul::{DynMatrixMultiply, MatrixMultiplyError};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+pub const SOFTWARE_GPU: &str = "MATMUL_TEST_SOFTWARE_GPU";
+
+/// The context every GPU test in this binary shares, or `None` after saying why there
+/// isn't one.
+///
+/// # Panics
+///
+/// If [`SOFTWARE_GPU`] is set but doesn't lead to an adapter.
+pub fn gpu_context() -> Option<Arc<GpuContext>> {
+    static CONTEXT: OnceLock<Result<Arc<GpuContext>, String>> = OnceLock::new();
+    let context = CONTEXT
+        .get_or_init(|| {
+            let software = std::env::var_os(SOFTWARE_GPU);
+            let options = match &software {
+                Some(value) => software_options(Path::new(value))?,
+                None => DeviceOptions::default(),
+            };
+            block_on(GpuContext::new(&options))
+                .map(Arc::new)
+                .map_err(|error| match software {
+                    Some(_) => format!("{SOFTWARE_GPU} is set but no software adapter: {error}"),
+                    None => {
+                        format!("no adapter ({error}), set {SOFTWARE_GPU} to use a software one")
+                    }
+                })
+        })
+        .clone();
+    match context {
+        Ok(context) => Some(context),
+        Err(reason) if std::env::var_os(SOFTWARE_GPU).is_some() => panic!("{reason}"),
+        Err(reason) => {
+            eprintln!("Skipping GPU tests: {reason}");
+            None
+        }
+    }
+}
+
+/// Creates the variant, or returns `None` after saying why if the adapter lacks
+/// something it needs. Any other error fails the test.
+pub fn create(
+    info: &VariantInfo,
+    context: Option<&Arc<GpuContext>>,
+) -> Option<Box<dyn DynMatrixMultiply>> {
+    match info.create(context) {
+        Ok(multiplier) => Some(multiplier),
+        Err(error @ MatrixMultiplyError::UnsupportedVariant { .. }) => {
+            eprintln!("Skipping {info}: {error}");
+            None
+        }
+        Err(error) => panic!("Failed to create {info}: {error}"),
+    }
+}
+
+fn software_options(value: &Path) -> Result<DeviceOptions, String> {
+    let options = DeviceOptions::new().force_fallback_adapter(true);
+    if !value.is_file() {
+        return Ok(options);
+    }
+
+    // `VK_ICD_FILENAMES` is the name older loaders know.
+    let exported = ["VK_DRIVER_FILES", "VK_ICD_FILENAMES"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|files| Path::new(&files) == value));
+    if !exported {
+        return Err(format!(
+            "{SOFTWARE_GPU} is a Vulkan ICD manifest, also export VK_DRIVER_FILES={} so the \
+             loader uses it",
+            value.display()
+        ));
+    }
+    Ok(options.backends(wgpu::Backends::VULKAN))
+}
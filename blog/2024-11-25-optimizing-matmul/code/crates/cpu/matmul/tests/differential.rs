@@ -0,0 +1,100 @@
+//! Differential tests of every variant against the `f64` reference in
+//! [`matmul::verify`], on random shapes and values.
+//!
+//! The variants that run on the CPU, enabled with the `isomorphic` feature, are always
+//! tested. GPU variants join when an adapter is found, including a software one such as
+//! lavapipe (see [`common`]), and are skipped otherwise. A variant is only left out when
+//! the adapter lacks something it needs; failing to create it for any other reason
+//! fails the test.
+//!
+//! Results are allowed the [`Tolerance::default`] error: a couple of `f32` roundings per
+//! term, relative to the size of the terms. That holds for any summation order, so it
+//! doesn't need loosening for large or cancelling values.
+
+mod common;
+
+use matmul::registry::{self, VariantInfo};
+use matmul::verify::{Tolerance, Verifier};
+use matmul::DynMatrixMultiply;
+use proptest::prelude::*;
+use std::sync::OnceLock;
+
+type Variant = (VariantInfo, Box<dyn DynMatrixMultiply>);
+
+/// Every variant that can run here, created once for all cases.
+fn variants() -> &'static [Variant] {
+    static VARIANTS: OnceLock<Vec<Variant>> = OnceLock::new();
+    VARIANTS.get_or_init(|| {
+        let context = common::gpu_context();
+
+        let mut variants = Vec::new();
+        for info in registry::variants() {
+            if info.backend.is_gpu() && context.is_none() {
+                continue;
+            }
+            if let Some(multiplier) = common::create(&info, context.as_ref()) {
+                variants.push((info, multiplier));
+            }
+        }
+        variants
+    })
+}
+
+/// Sizes that catch mistakes at the edges: 1, primes, and sizes just off the multiples
+/// of the workgroup and tile sizes.
+fn dimension() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        Just(1),
+        prop::sample::select(vec![2, 3, 5, 7, 13, 17, 31, 61, 67, 127]),
+        (1u32..8).prop_flat_map(|i| prop::sample::select(vec![16 * i - 1, 16 * i + 1])),
+        (1u32..32).prop_map(|i| 4 * i + 2),
+        1u32..128,
+    ]
+}
+
+/// Mostly small values of either sign, with zeros, large magnitudes and tiny ones mixed
+/// in. The largest products still sum far below `f32::MAX`.
+fn value() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        4 => -1.0f32..1.0,
+        1 => Just(0.0),
+        1 => -1e6f32..1e6,
+        1 => prop::sample::select(vec![1e8, -1e8, 1e-8, -1e-8, -0.0]),
+    ]
+}
+
+/// A shape and matrices of that shape.
+fn problem() -> impl Strategy<Value = ((u32, u32, u32), Vec<f32>, Vec<f32>)> {
+    (dimension(), dimension(), dimension()).prop_flat_map(|(m, k, n)| {
+        (
+            Just((m, k, n)),
+            prop::collection::vec(value(), (m * k) as usize),
+            prop::collection::vec(value(), (k * n) as usize),
+        )
+    })
+}
+
+proptest! {
+    // The CPU backends simulate every invocation, so each case is slow.
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn every_variant_matches_the_reference(((m, k, n), a, b) in problem()) {
+        let verifier = Verifier::new().tolerance(Tolerance::default());
+        for (info, multiplier) in variants() {
+            if !info.supports(m, k, n) {
+                continue;
+            }
+            let result = multiplier.multiply(&a, &b, m, k, n);
+            prop_assert!(result.is_ok(), "{info} {m}x{k}x{n}: {:?}", result.err());
+            let report = verifier.verify(&a, &b, &result.unwrap(), m, k, n).unwrap();
+            prop_assert!(report.passed(), "{info} {m}x{k}x{n}: {report}");
+        }
+    }
+}
+
+#[cfg(feature = "isomorphic")]
+#[test]
+fn cpu_variants_are_tested() {
+    assert!(variants().iter().any(|(info, _)| !info.backend.is_gpu()));
+}
@@ -0,0 +1,70 @@
+//! Runs every GPU variant over a sweep of shapes on whatever adapter [`common`] finds.
+//!
+//! This is how CI, which has no GPU, tests the `wgpu` backend: with
+//! `MATMUL_TEST_SOFTWARE_GPU` set, the variants run on a software adapter such as
+//! lavapipe, and the test fails if it can't be found. Without the variable or an
+//! adapter, the test is skipped.
+
+mod common;
+
+use matmul::registry;
+use matmul::verify::{Tolerance, Verifier};
+
+/// Square sizes up to a few workgroups, 1, primes, and sizes just off the multiples of
+/// the workgroup and tile sizes, as `(m, k, n)`.
+const SHAPES: &[(u32, u32, u32)] = &[
+    (1, 1, 1),
+    (2, 2, 2),
+    (16, 16, 16),
+    (64, 64, 64),
+    (256, 256, 256),
+    (1, 64, 1),
+    (3, 5, 7),
+    (31, 127, 13),
+    (15, 17, 33),
+    (63, 65, 1),
+    (130, 7, 66),
+    (257, 31, 129),
+];
+
+/// Small integers, so every product and sum is exact in `f32`.
+fn matrix(rows: u32, cols: u32, seed: u32) -> Vec<f32> {
+    (0..rows * cols)
+        .map(|i| ((i * 7 + seed) % 17) as f32 - 8.0)
+        .collect()
+}
+
+#[test]
+fn every_gpu_variant_multiplies_every_shape() {
+    let Some(context) = common::gpu_context() else {
+        return;
+    };
+    eprintln!("Running on {}", context.adapter_info());
+
+    let verifier = Verifier::new().tolerance(Tolerance::default());
+    let mut failures = Vec::new();
+    for info in registry::variants()
+        .into_iter()
+        .filter(|info| info.backend.is_gpu())
+    {
+        // A software adapter may lack a feature that a variant needs.
+        let Some(multiplier) = common::create(&info, Some(&context)) else {
+            continue;
+        };
+        for &(m, k, n) in SHAPES.iter().filter(|&&(m, k, n)| info.supports(m, k, n)) {
+            let (a, b) = (matrix(m, k, 1), matrix(k, n, 2));
+            let result = match multiplier.multiply(&a, &b, m, k, n) {
+                Ok(result) => result,
+                Err(error) => {
+                    failures.push(format!("{info} {m}x{k}x{n}: {error}"));
+                    continue;
+                }
+            };
+            let report = verifier.verify(&a, &b, &result, m, k, n).unwrap();
+            if !report.passed() {
+                failures.push(format!("{info} {m}x{k}x{n}: {report}"));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,206 @@
+//! Validates the compiled kernels without a GPU.
+//!
+//! `wgpu` only checks shaders when a pipeline is created on a real device, so bad SPIR-V
+//! would otherwise go unnoticed in CI. These tests parse each variant's entry point with
+//! naga's SPIR-V frontend, validate it under different capability profiles and translate
+//! it to the shading languages of the other `wgpu` backends.
+
+use glam::UVec3;
+use matmul::registry::{self, VariantInfo};
+use matmul::{Gpu, GridComputation};
+use naga::back::pipeline_constants::process_overrides;
+use naga::back::PipelineConstants;
+use naga::back::{hlsl, msl, wgsl};
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+
+/// Capabilities available to shaders on WebGPU, which supports no optional features.
+const WEBGPU: Capabilities = Capabilities::empty();
+
+/// Capabilities commonly available on Vulkan 1.2 desktop drivers.
+const VULKAN_1_2: Capabilities = Capabilities::PUSH_CONSTANT
+    .union(Capabilities::FLOAT64)
+    .union(Capabilities::SHADER_INT64)
+    .union(Capabilities::PRIMITIVE_INDEX)
+    .union(Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING)
+    .union(Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING)
+    .union(Capabilities::SAMPLER_NON_UNIFORM_INDEXING)
+    .union(Capabilities::CLIP_DISTANCE)
+    .union(Capabilities::CULL_DISTANCE)
+    .union(Capabilities::MULTIVIEW)
+    .union(Capabilities::SUBGROUP)
+    .union(Capabilities::SUBGROUP_BARRIER);
+
+/// Every GPU variant in the registry.
+fn gpu_variants() -> Vec<VariantInfo> {
+    registry::variants()
+        .into_iter()
+        .filter(|info| info.backend.is_gpu())
+        .collect()
+}
+
+/// Parses the compiled shader, keeping only the entry point used by `info`.
+fn parse(info: &VariantInfo) -> Result<naga::Module, String> {
+    let entry_point = info
+        .entry_point
+        .ok_or_else(|| "no entry point".to_string())?;
+    parse_entry_point(entry_point)
+}
+
+fn parse_entry_point(entry_point: &str) -> Result<naga::Module, String> {
+    let spirv = compiled_kernels::spirv(entry_point)
+        .ok_or_else(|| format!("entry point {entry_point} not compiled"))?;
+    parse_spirv(spirv, entry_point)
+}
+
+fn parse_spirv(spirv: &[u32], entry_point: &str) -> Result<naga::Module, String> {
+    let mut module = naga::front::spv::parse_u8_slice(
+        bytemuck::cast_slice(spirv),
+        &naga::front::spv::Options::default(),
+    )
+    .map_err(|error| format!("parse: {error}"))?;
+
+    module
+        .entry_points
+        .retain(|candidate| candidate.name == entry_point);
+    if module.entry_points.is_empty() {
+        return Err(format!("entry point {entry_point} not found"));
+    }
+    Ok(module)
+}
+
+fn validate(module: &naga::Module, capabilities: Capabilities) -> Result<ModuleInfo, String> {
+    Validator::new(ValidationFlags::all(), capabilities)
+        .validate(module)
+        .map_err(|error| format!("validate: {}", error.emit_to_string("")))
+}
+
+/// Replaces the specialization constants with `constants`, or their defaults, the way
+/// `wgpu` does before translating a shader for its backend.
+fn specialize(
+    module: &naga::Module,
+    constants: &PipelineConstants,
+) -> Result<(naga::Module, ModuleInfo), String> {
+    let info = validate(module, Capabilities::all())?;
+    let (module, info) = process_overrides(module, &info, constants)
+        .map_err(|error| format!("specialize: {error}"))?;
+    Ok((module.into_owned(), info.into_owned()))
+}
+
+/// Runs `check` on every GPU variant and fails with a report of every variant that did
+/// not pass, rather than stopping at the first one.
+fn check_all(target: &str, check: impl Fn(&naga::Module) -> Result<(), String>) {
+    let failures = gpu_variants()
+        .iter()
+        .filter_map(|info| {
+            parse(info)
+                .and_then(|module| check(&module))
+                .err()
+                .map(|error| format!("{info}: {error}"))
+        })
+        .collect::<Vec<_>>();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} variants failed {target}:\n{}",
+        failures.len(),
+        gpu_variants().len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_validate_webgpu() {
+    check_all("WebGPU validation", |module| {
+        validate(module, WEBGPU).map(|_| ())
+    });
+}
+
+#[test]
+fn test_validate_vulkan_1_2() {
+    check_all("Vulkan 1.2 validation", |module| {
+        validate(module, VULKAN_1_2).map(|_| ())
+    });
+}
+
+#[test]
+fn test_translate_wgsl() {
+    check_all("translation to WGSL", |module| {
+        let (module, info) = specialize(module, &PipelineConstants::default())?;
+        wgsl::write_string(&module, &info, wgsl::WriterFlags::empty())
+            .map(|_| ())
+            .map_err(|error| format!("wgsl: {error}"))
+    });
+}
+
+#[test]
+fn test_translate_msl() {
+    check_all("translation to MSL", |module| {
+        let (module, info) = specialize(module, &PipelineConstants::default())?;
+        let options = msl::Options {
+            lang_version: (2, 0),
+            ..Default::default()
+        };
+        msl::write_string(&module, &info, &options, &msl::PipelineOptions::default())
+            .map(|_| ())
+            .map_err(|error| format!("msl: {error}"))
+    });
+}
+
+#[test]
+fn test_translate_hlsl() {
+    check_all("translation to HLSL", |module| {
+        let (module, info) = specialize(module, &PipelineConstants::default())?;
+        let options = hlsl::Options::default();
+        let mut output = String::new();
+        hlsl::Writer::new(&mut output, &options)
+            .write(&module, &info, None)
+            .map(|_| ())
+            .map_err(|error| format!("hlsl: {error}"))
+    });
+}
+
+/// Checks that the tiling kernels can be specialized with tile and workgroup sizes other
+/// than their defaults, the way the `wgpu` backend does.
+#[test]
+fn test_specialize_tile_sizes() {
+    fn specialized<T: Gpu + GridComputation + 'static>(variant: T) -> (Box<dyn Gpu>, UVec3) {
+        let workgroup = variant.workgroup();
+        (Box::new(variant), workgroup)
+    }
+    let specialized: Vec<(Box<dyn Gpu>, UVec3)> = vec![
+        #[cfg(feature = "tiling_1d")]
+        specialized(matmul::variants::Tiling1d::new(2).unwrap()),
+        #[cfg(feature = "tiling_1d")]
+        specialized(
+            matmul::variants::Tiling1d::new(settings::MAX_TILE_SIZE)
+                .and_then(|variant| variant.with_workgroup(64, 1))
+                .unwrap(),
+        ),
+        #[cfg(feature = "tiling_2d")]
+        specialized(matmul::variants::Tiling2d::new(2, 1).unwrap()),
+        #[cfg(feature = "tiling_2d")]
+        specialized(
+            matmul::variants::Tiling2d::new(settings::MAX_TILE_M, settings::MAX_TILE_N)
+                .and_then(|variant| variant.with_workgroup(8, 32))
+                .unwrap(),
+        ),
+    ];
+
+    for (variant, workgroup) in specialized {
+        let entry_point = variant.entry_point();
+        let result = matmul::specialized_shader(&*variant)
+            .map_err(|error| error.to_string())
+            .and_then(|(spirv, constants)| {
+                let module = parse_spirv(&spirv, entry_point)?;
+                let size = module.entry_points[0].workgroup_size;
+                if size != workgroup.to_array() {
+                    return Err(format!("workgroup {size:?}, expected {workgroup}"));
+                }
+                specialize(&module, &constants)
+            })
+            .and_then(|(module, _)| validate(&module, WEBGPU));
+        if let Err(error) = result {
+            panic!("{entry_point}: {error}");
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Every kernel, gathered into one crate so they are compiled into a single SPIR-V
+//! module with one entry point per variant.
+//!
+//! Each kernel still lives in its own crate. Re-exporting the entry points here links
+//! them into this crate's module. Each kernel is behind a feature of the same name so
+//! builds only pay for the kernels they use.
+
+#![no_std]
+
+#[cfg(feature = "isomorphic")]
+pub use isomorphic::{
+    matmul_isomorphic_1x1, matmul_isomorphic_2x2, matmul_isomorphic_4x4, matmul_isomorphic_8x8,
+};
+#[cfg(feature = "microbench")]
+pub use microbench::{microbench_copy, microbench_fma};
+#[cfg(feature = "naive")]
+pub use naive::matmul_naive;
+#[cfg(feature = "tiling_1d")]
+pub use tiling_1d::matmul_tiling_1d;
+#[cfg(feature = "tiling_1d_loop")]
+pub use tiling_1d_loop::matmul_tiling_1d_loop;
+#[cfg(feature = "tiling_2d")]
+pub use tiling_2d::matmul_tiling_2d;
+#[cfg(feature = "workgroup_256")]
+pub use workgroup_256::matmul_workgroup_256;
+#[cfg(feature = "workgroup_2d")]
+pub use workgroup_2d::matmul_workgroup_2d;
@@ -0,0 +1,89 @@
+//! Kernels that measure the limits of an adapter rather than multiply matrices, used to
+//! draw the roofline the variants are compared against.
+//!
+//! They take the same bindings as the matmul kernels so they run with the same pipeline
+//! layout, but `dimensions` means something different to each.
+
+#![no_std]
+
+use settings::{Dimensions, FMA_CHAINS};
+use spirv_std::glam::UVec3;
+use spirv_std::spirv;
+
+/// Declares [`WORKGROUP_SIZE`] and gives every kernel in it a workgroup of that size.
+/// `#[spirv(compute(threads(..)))]` only takes literals, so this keeps the size written
+/// once.
+macro_rules! workgroup_size {
+    ($size:tt; $($kernel:item)*) => {
+        /// The invocations in each workgroup of every kernel, along `x`.
+        pub const WORKGROUP_SIZE: u32 = $size;
+
+        $(
+            #[spirv(compute(threads($size)))]
+            $kernel
+        )*
+    };
+}
+
+workgroup_size! {
+    256;
+
+    /// Copies the first `dimensions.m` elements of `a` to `result`, to measure memory
+    /// bandwidth. Each invocation strides over the buffer by the size of the whole grid,
+    /// so any number of workgroups can copy any amount of memory.
+    pub fn microbench_copy(
+        #[spirv(global_invocation_id)] global_id: UVec3,
+        #[spirv(num_workgroups)] num_workgroups: UVec3,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] dimensions: &Dimensions,
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] a: &[f32],
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] _b: &[f32],
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] result: &mut [f32],
+    ) {
+        let stride = num_workgroups.x * WORKGROUP_SIZE;
+        let mut index = global_id.x;
+        while index < dimensions.m {
+            result[index as usize] = a[index as usize];
+            index += stride;
+        }
+    }
+
+    /// Runs `dimensions.k` rounds of [`FMA_CHAINS`] independent multiply-adds in each of
+    /// the first `dimensions.m` invocations, to measure peak arithmetic throughput.
+    ///
+    /// The factors come from `a[0]` and `a[1]` so the compiler can't fold the loop, and
+    /// the sums are written to `result` so it can't remove it.
+    // Like the tiling kernels, this indexes the array rather than iterating over it,
+    // which rust-gpu compiles more reliably.
+    #[allow(clippy::needless_range_loop)]
+    pub fn microbench_fma(
+        #[spirv(global_invocation_id)] global_id: UVec3,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] dimensions: &Dimensions,
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] a: &[f32],
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] _b: &[f32],
+        #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] result: &mut [f32],
+    ) {
+        let index = global_id.x as usize;
+        if index >= dimensions.m as usize {
+            return;
+        }
+
+        let scale = a[0];
+        let offset = a[1];
+        let mut chains: [f32; FMA_CHAINS as usize] = Default::default();
+        for chain in 0..FMA_CHAINS as usize {
+            chains[chain] = (index + chain) as f32;
+        }
+
+        for _ in 0..dimensions.k {
+            for chain in 0..FMA_CHAINS as usize {
+                chains[chain] = chains[chain] * scale + offset;
+            }
+        }
+
+        let mut sum = 0.0;
+        for chain in 0..FMA_CHAINS as usize {
+            sum += chains[chain];
+        }
+        result[index] = sum;
+    }
+}
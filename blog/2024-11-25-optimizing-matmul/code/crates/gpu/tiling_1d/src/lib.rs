@@ -1,20 +1,22 @@
 #![no_std]
 
 use settings::Dimensions;
-use settings::TILE_SIZE;
+use settings::MAX_TILE_SIZE;
 use spirv_std::glam::UVec3;
 use spirv_std::spirv;
 
 #[spirv(compute(threads(16, 16)))]
-pub fn matmul(
+pub fn matmul_tiling_1d(
     #[spirv(global_invocation_id)] global_id: UVec3,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] dimensions: &Dimensions,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] a: &[f32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] b: &[f32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] result: &mut [f32],
+    #[spirv(spec_constant(id = 0, default = 4))] tile_size: u32,
 ) {
+    let tile_size = tile_size.clamp(1, MAX_TILE_SIZE);
     let row = global_id.y as usize;
-    let col = (global_id.x * TILE_SIZE) as usize;
+    let col = (global_id.x * tile_size) as usize;
 
     if row >= dimensions.m as usize || col >= dimensions.n as usize {
         return;
@@ -24,33 +26,61 @@ pub fn matmul(
     let mut sum01: f32 = 0.0;
     let mut sum02: f32 = 0.0;
     let mut sum03: f32 = 0.0;
+    let mut sum04: f32 = 0.0;
+    let mut sum05: f32 = 0.0;
+    let mut sum06: f32 = 0.0;
+    let mut sum07: f32 = 0.0;
 
     for i in 0..dimensions.k as usize {
         let a_elem = a[row * dimensions.k as usize + i];
         if col < dimensions.n as usize {
             sum00 += a_elem * b[i * dimensions.n as usize + col];
         }
-        if col + 1 < dimensions.n as usize {
+        if tile_size > 1 && col + 1 < dimensions.n as usize {
             sum01 += a_elem * b[i * dimensions.n as usize + col + 1];
         }
-        if col + 2 < dimensions.n as usize {
+        if tile_size > 2 && col + 2 < dimensions.n as usize {
             sum02 += a_elem * b[i * dimensions.n as usize + col + 2];
         }
-        if col + 3 < dimensions.n as usize {
+        if tile_size > 3 && col + 3 < dimensions.n as usize {
             sum03 += a_elem * b[i * dimensions.n as usize + col + 3];
         }
+        if tile_size > 4 && col + 4 < dimensions.n as usize {
+            sum04 += a_elem * b[i * dimensions.n as usize + col + 4];
+        }
+        if tile_size > 5 && col + 5 < dimensions.n as usize {
+            sum05 += a_elem * b[i * dimensions.n as usize + col + 5];
+        }
+        if tile_size > 6 && col + 6 < dimensions.n as usize {
+            sum06 += a_elem * b[i * dimensions.n as usize + col + 6];
+        }
+        if tile_size > 7 && col + 7 < dimensions.n as usize {
+            sum07 += a_elem * b[i * dimensions.n as usize + col + 7];
+        }
     }
 
     if col < dimensions.n as usize {
         result[row * dimensions.n as usize + col] = sum00;
     }
-    if col + 1 < dimensions.n as usize {
+    if tile_size > 1 && col + 1 < dimensions.n as usize {
         result[row * dimensions.n as usize + col + 1] = sum01;
     }
-    if col + 2 < dimensions.n as usize {
+    if tile_size > 2 && col + 2 < dimensions.n as usize {
         result[row * dimensions.n as usize + col + 2] = sum02;
     }
-    if col + 3 < dimensions.n as usize {
+    if tile_size > 3 && col + 3 < dimensions.n as usize {
         result[row * dimensions.n as usize + col + 3] = sum03;
     }
+    if tile_size > 4 && col + 4 < dimensions.n as usize {
+        result[row * dimensions.n as usize + col + 4] = sum04;
+    }
+    if tile_size > 5 && col + 5 < dimensions.n as usize {
+        result[row * dimensions.n as usize + col + 5] = sum05;
+    }
+    if tile_size > 6 && col + 6 < dimensions.n as usize {
+        result[row * dimensions.n as usize + col + 6] = sum06;
+    }
+    if tile_size > 7 && col + 7 < dimensions.n as usize {
+        result[row * dimensions.n as usize + col + 7] = sum07;
+    }
 }
@@ -6,7 +6,7 @@ use spirv_std::glam::UVec3;
 use spirv_std::spirv;
 
 #[spirv(compute(threads(16, 16)))]
-pub fn matmul(
+pub fn matmul_tiling_1d_loop(
     #[spirv(global_invocation_id)] global_id: UVec3,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] dimensions: &Dimensions,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] a: &[f32],
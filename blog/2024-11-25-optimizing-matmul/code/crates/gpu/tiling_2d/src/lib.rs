@@ -1,37 +1,41 @@
 #![no_std]
 
 use settings::Dimensions;
-use settings::{TILE_M, TILE_N};
+use settings::{MAX_TILE_M, MAX_TILE_N};
 
 use spirv_std::glam::UVec3;
 use spirv_std::spirv;
 
 #[spirv(compute(threads(16, 16)))]
-pub fn matmul(
+pub fn matmul_tiling_2d(
     #[spirv(global_invocation_id)] global_id: UVec3,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] dimensions: &Dimensions,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] a: &[f32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] b: &[f32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] result: &mut [f32],
+    #[spirv(spec_constant(id = 1, default = 4))] tile_m: u32,
+    #[spirv(spec_constant(id = 2, default = 4))] tile_n: u32,
 ) {
-    let row = (global_id.y * TILE_M) as usize;
-    let col = (global_id.x * TILE_N) as usize;
+    let tile_m = tile_m.clamp(1, MAX_TILE_M);
+    let tile_n = tile_n.clamp(1, MAX_TILE_N);
+    let row = (global_id.y * tile_m) as usize;
+    let col = (global_id.x * tile_n) as usize;
 
-    // Initialize sums array to zeros
+    // Initialize sums array to zeros, with room for the largest tile
     // Note: This is uglier than it needs to be to work around
     // https://github.com/Rust-GPU/rust-gpu/issues/46
-    let mut sums: [[f32; TILE_N as usize]; TILE_M as usize] = Default::default();
+    let mut sums: [[f32; MAX_TILE_N as usize]; MAX_TILE_M as usize] = Default::default();
 
     // Compute the 2D tile
     for k in 0..dimensions.k as usize {
-        for i in 0..TILE_M as usize {
+        for i in 0..tile_m as usize {
             let a_element = if row + i < dimensions.m as usize {
                 a[(row + i) * dimensions.k as usize + k]
             } else {
                 0.0
             };
 
-            for j in 0..TILE_N as usize {
+            for j in 0..tile_n as usize {
                 let b_element = if col + j < dimensions.n as usize {
                     b[k * dimensions.n as usize + (col + j)]
                 } else {
@@ -44,8 +48,8 @@ pub fn matmul(
     }
 
     // Write results
-    for i in 0..TILE_M as usize {
-        for j in 0..TILE_N as usize {
+    for i in 0..tile_m as usize {
+        for j in 0..tile_n as usize {
             let output_row = row + i;
             let output_col = col + j;
 
@@ -7,7 +7,6 @@
 #![no_std]
 
 use settings::Dimensions;
-use settings::{TILE_M, TILE_N};
 
 #[cfg(target_arch = "spirv")]
 use spirv_std::spirv;
@@ -20,43 +19,69 @@ use glam;
 
 use glam::UVec3;
 
-#[cfg_attr(target_arch = "spirv", spirv(compute(threads(16, 16))))]
-pub fn matmul(
-    #[cfg_attr(target_arch = "spirv", spirv(global_invocation_id))] global_id: UVec3,
-    #[cfg_attr(target_arch = "spirv", spirv(uniform, descriptor_set = 0, binding = 0))]
+/// Declares a GPU entry point that computes `$tile_m x $tile_n` tiles of the result.
+///
+/// Entry points can't be generic, so each tile size gets one of its own that calls
+/// [`matmul`] with the tile size filled in.
+macro_rules! entry_point {
+    ($name:ident, $tile_m:literal, $tile_n:literal) => {
+        #[cfg_attr(target_arch = "spirv", spirv(compute(threads(16, 16))))]
+        pub fn $name(
+            #[cfg_attr(target_arch = "spirv", spirv(global_invocation_id))] global_id: UVec3,
+            #[cfg_attr(target_arch = "spirv", spirv(uniform, descriptor_set = 0, binding = 0))]
+            dimensions: &Dimensions,
+            #[cfg_attr(
+                target_arch = "spirv",
+                spirv(storage_buffer, descriptor_set = 0, binding = 1)
+            )]
+            a: &[f32],
+            #[cfg_attr(
+                target_arch = "spirv",
+                spirv(storage_buffer, descriptor_set = 0, binding = 2)
+            )]
+            b: &[f32],
+            #[cfg_attr(
+                target_arch = "spirv",
+                spirv(storage_buffer, descriptor_set = 0, binding = 3)
+            )]
+            result: &mut [f32],
+        ) {
+            matmul::<$tile_m, $tile_n>(global_id, dimensions, a, b, result);
+        }
+    };
+}
+
+entry_point!(matmul_isomorphic_1x1, 1, 1);
+entry_point!(matmul_isomorphic_2x2, 2, 2);
+entry_point!(matmul_isomorphic_4x4, 4, 4);
+entry_point!(matmul_isomorphic_8x8, 8, 8);
+
+/// Computes one `TILE_M x TILE_N` tile of the result for the invocation at `global_id`.
+pub fn matmul<const TILE_M: usize, const TILE_N: usize>(
+    global_id: UVec3,
     dimensions: &Dimensions,
-    #[cfg_attr(
-        target_arch = "spirv",
-        spirv(storage_buffer, descriptor_set = 0, binding = 1)
-    )]
     a: &[f32],
-    #[cfg_attr(
-        target_arch = "spirv",
-        spirv(storage_buffer, descriptor_set = 0, binding = 2)
-    )]
     b: &[f32],
-    #[cfg_attr(
-        target_arch = "spirv",
-        spirv(storage_buffer, descriptor_set = 0, binding = 3)
-    )]
     result: &mut [f32],
-) {
+) where
+    [[f32; TILE_N]; TILE_M]: Default,
+{
     let row = (global_id.y * TILE_M as u32) as usize;
     let col = (global_id.x * TILE_N as u32) as usize;
 
     // Initialize sums array to zeros
-    let mut sums: [[f32; TILE_N as usize]; TILE_M as usize] = Default::default();
+    let mut sums: [[f32; TILE_N]; TILE_M] = Default::default();
 
     // Compute the 2D tile
     for k in 0..dimensions.k as usize {
-        for i in 0..TILE_M as usize {
+        for i in 0..TILE_M {
             let a_element = if row + i < dimensions.m as usize {
                 a[(row + i) * dimensions.k as usize + k]
             } else {
                 0.0
             };
 
-            for j in 0..TILE_N as usize {
+            for j in 0..TILE_N {
                 let b_element = if col + j < dimensions.n as usize {
                     b[k * dimensions.n as usize + (col + j as usize)]
                 } else {
@@ -69,8 +94,8 @@ pub fn matmul(
     }
 
     // Write results
-    for i in 0..TILE_M as usize {
-        for j in 0..TILE_N as usize {
+    for i in 0..TILE_M {
+        for j in 0..TILE_N {
             let output_row = row + i as usize;
             let output_col = col + j as usize;
 
@@ -34,11 +34,39 @@ impl Dimensions {
     }
 }
 
-// Tiling configurations
+// Tiling configurations. The `tiling_1d` and `tiling_2d` kernels take their tile size as
+// a specialization constant, so these are only their defaults.
 pub const TILE_SIZE: u32 = 4;
 pub const TILE_M: u32 = 4;
 pub const TILE_N: u32 = 4;
 
+// The largest tiles the specialized kernels have room for. The kernels size their
+// accumulators, and so their registers, for these, whatever tile they are specialized to.
+pub const MAX_TILE_SIZE: u32 = 8;
+pub const MAX_TILE_M: u32 = 8;
+pub const MAX_TILE_N: u32 = 8;
+
+/// The most invocations a workgroup of the specialized kernels may have, the least every
+/// WebGPU adapter supports.
+pub const MAX_WORKGROUP_INVOCATIONS: u32 = 256;
+
+/// Independent multiply-add chains each invocation of the `microbench_fma` kernel runs,
+/// enough to hide the latency of each one.
+pub const FMA_CHAINS: u32 = 8;
+
+/// IDs of the specialization constants, as in `#[spirv(spec_constant(id = ..))]`.
+pub mod spec_constants {
+    pub const TILE_SIZE: u32 = 0;
+    pub const TILE_M: u32 = 1;
+    pub const TILE_N: u32 = 2;
+
+    // The workgroup size of the tiling kernels. `#[spirv(compute(threads(..)))]` only
+    // takes literals, so these aren't declared by the kernels: the CPU side applies them
+    // by rewriting the `LocalSize` of the entry point before creating the shader module.
+    pub const WORKGROUP_X: u32 = 3;
+    pub const WORKGROUP_Y: u32 = 4;
+}
+
 // Buffer layout information
 #[derive(Copy, Clone, Debug)]
 pub struct BufferLayout {
@@ -64,5 +92,3 @@ impl BufferLayout {
         readonly: false,
     };
 }
-
-pub const SHADER_ENTRY_POINT: &str = "matmul";
@@ -56,8 +56,8 @@ export const RustNaiveWorkgroupCount: React.FC = () => (
   <Snippet
     language="rust"
     className="text-xs"
-    lines="26-34"
-    hash="8abb43d"
+    lines="51-59"
+    hash="c95e50e"
     title="Calculating on the CPU how many workgroup dispatches are needed"
   >
     {RustWorkgroupCount}
@@ -68,8 +68,8 @@ export const RustNaiveDispatch: React.FC = () => (
   <Snippet
     language="rust"
     className="text-xs"
-    lines="152,154"
-    hash="cbb5295"
+    lines="237,239"
+    hash="8ea4739"
     strip_leading_spaces
     title="Using wgpu on the CPU to dispatch workgroups to the GPU"
   >
@@ -78,7 +78,7 @@ export const RustNaiveDispatch: React.FC = () => (
 );
 
 export const RustNaiveWorkgroup: React.FC = () => (
-  <Snippet language="rust" className="text-xs" lines="7" hash="7762339">
+  <Snippet language="rust" className="text-xs" lines="7" hash="dbc06b4">
     {RustKernelSource}
   </Snippet>
 );
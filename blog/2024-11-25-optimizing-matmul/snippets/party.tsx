@@ -7,7 +7,7 @@ import RustWgpuBackend from "!!raw-loader!../code/crates/cpu/matmul/src/backends
 import RustCpuBackendSource from "!!raw-loader!../code/crates/cpu/matmul/src/backends/cpu.rs";
 
 export const RustPartySettings: React.FC = () => (
-  <Snippet language="rust" className="text-xs" lines="3,9,11" hash="47bb656">
+  <Snippet language="rust" className="text-xs" lines="3,9,11" hash="cbf58df">
     {RustKernelSource}
   </Snippet>
 );
@@ -19,7 +19,7 @@ export const RustIsomorphic: React.FC = () => (
 );
 
 export const RustIsomorphicGlam: React.FC = () => (
-  <Snippet language="rust" lines="15-19" hash="a3dbf2f" className="text-xs">
+  <Snippet language="rust" lines="14-18" hash="03aa960" className="text-xs">
     {RustIsomorphicSource}
   </Snippet>
 );
@@ -39,8 +39,8 @@ export const RustIsomorphicDeps: React.FC = () => (
 export const RustWgpuDimensions: React.FC = () => (
   <Snippet
     language="rust"
-    lines="108-118"
-    hash="cbb5295"
+    lines="192-202"
+    hash="8ea4739"
     className="text-xs"
     title="Creating the Dimensions struct on the CPU and writing it to the GPU"
   >
@@ -49,13 +49,13 @@ export const RustWgpuDimensions: React.FC = () => (
 );
 
 export const RustCpuBackendHarness: React.FC = () => (
-  <Snippet language="rust" className="text-xs" lines="30-79" hash="7ad7cab">
+  <Snippet language="rust" className="text-xs" lines="30-78" hash="b6fcf2c">
     {RustCpuBackendSource}
   </Snippet>
 );
 
 export const RustCpuBackendTest: React.FC = () => (
-  <Snippet language="rust" className="text-xs" lines="174-194" hash="7ad7cab">
+  <Snippet language="rust" className="text-xs" lines="169-189" hash="b6fcf2c">
     {RustCpuBackendSource}
   </Snippet>
 );
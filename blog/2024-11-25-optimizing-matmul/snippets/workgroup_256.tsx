@@ -4,7 +4,7 @@ import RustKernelSource from "!!raw-loader!../code/crates/gpu/workgroup_256/src/
 import VariantsSource from "!!raw-loader!../code/crates/cpu/matmul/src/variants.rs";
 
 export const RustWorkgroup256Workgroup: React.FC = () => (
-  <Snippet language="rust" className="text-xs" lines="7" hash="56b3ae8">
+  <Snippet language="rust" className="text-xs" lines="7" hash="eaa18a0">
     {RustKernelSource}
   </Snippet>
 );
@@ -13,8 +13,8 @@ export const RustWorkgroup256WorkgroupCount: React.FC = () => (
   <Snippet
     language="rust"
     className="text-xs"
-    lines="51-65"
-    hash="8abb43d"
+    lines="92-106"
+    hash="c95e50e"
     title="Calculating how many workgroup dispatches are needed on the CPU"
   >
     {VariantsSource}
@@ -19,8 +19,8 @@ export const RustWorkgroup2dWorkgroupCount: React.FC = () => (
   <Snippet
     language="rust"
     className="text-xs"
-    lines="82-94"
-    hash="8abb43d"
+    lines="139-149"
+    hash="c95e50e"
     title="Calculating how many workgroup dispatches are needed on the CPU"
   >
     {VariantsSource}